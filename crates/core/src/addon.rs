@@ -1,14 +1,17 @@
 use crate::{
+    config::Flavor,
     error::{ParseError, RepositoryError},
     repository::{
-        Changelog, GitKind, GlobalReleaseChannel, ReleaseChannel, RemotePackage,
+        fetch_changelog, Changelog, GitKind, GlobalReleaseChannel, ReleaseChannel, RemotePackage,
         RepositoryIdentifiers, RepositoryKind, RepositoryMetadata, RepositoryPackage,
     },
-    utility::strip_non_digits,
+    utility::{interface_to_game_version, is_version_number, parse_version, strip_non_digits},
 };
 
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -33,18 +36,29 @@ pub enum AddonState {
 
 /// Struct that stores the metadata parsed from an Addon folder's
 /// `.toc` file
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AddonFolder {
     /// ID is always the folder name
     pub id: String,
     pub title: String,
     pub interface: Option<String>,
+    /// Every interface number listed in the `.toc` file.
+    pub interfaces: Vec<u32>,
+    /// Interface numbers listed for a single flavor, eg. `## Interface-Classic:`.
+    #[serde(default)]
+    pub flavor_interfaces: HashMap<Flavor, Vec<u32>>,
+    /// Flavor of the `.toc` file, if it was a flavor specific one, eg. `Foo_Mainline.toc`.
+    pub toc_flavor: Option<Flavor>,
     pub path: PathBuf,
     pub author: Option<String>,
     pub notes: Option<String>,
     pub version: Option<String>,
     pub repository_identifiers: RepositoryIdentifiers,
     pub dependencies: Vec<String>,
+    /// Names listed as `## OptionalDeps:`, which the addon can run without.
+    pub optional_dependencies: Vec<String>,
+    /// Not part of the `.toc` file, so it's never persisted with it.
+    #[serde(skip)]
     pub fingerprint: Option<u32>,
 }
 
@@ -74,26 +88,49 @@ impl AddonFolder {
         id: String,
         title: String,
         interface: Option<String>,
+        interfaces: Vec<u32>,
+        flavor_interfaces: HashMap<Flavor, Vec<u32>>,
+        toc_flavor: Option<Flavor>,
         path: PathBuf,
         author: Option<String>,
         notes: Option<String>,
         version: Option<String>,
         repository_identifiers: RepositoryIdentifiers,
         dependencies: Vec<String>,
+        optional_dependencies: Vec<String>,
     ) -> Self {
         AddonFolder {
             id,
             title,
             interface,
+            interfaces,
+            flavor_interfaces,
+            toc_flavor,
             path,
             author,
             notes,
             version,
             repository_identifiers,
             dependencies,
+            optional_dependencies,
             fingerprint: None,
         }
     }
+
+    /// Returns the game version for `flavor`, read from its `## Interface-<Flavor>:`
+    /// directive if there is one, otherwise from `## Interface:`.
+    pub fn interface_for_flavor(&self, flavor: Flavor) -> Option<String> {
+        match self.flavor_interfaces.get(&flavor.base_flavor()) {
+            Some(interfaces) => Some(
+                interfaces
+                    .iter()
+                    .map(|i| interface_to_game_version(*i))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            None => self.interface.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -117,6 +154,24 @@ pub struct Addon {
     pub state: AddonState,
     pub release_channel: ReleaseChannel,
 
+    /// Version this addon is pinned to. A pinned addon is never considered
+    /// updatable, and won't be installed over unless forced.
+    pub pinned_version: Option<String>,
+
+    /// Whether "update all" includes this addon. An addon with auto update
+    /// disabled is still marked updatable and can be updated manually.
+    /// Persisted in `config::Addons::auto_update_disabled`.
+    pub auto_update: bool,
+
+    /// When the addon was last installed or updated, set once its archive has
+    /// been extracted. Persisted in `config::Addons::updated_at`.
+    pub updated_at: Option<DateTime<Utc>>,
+
+    /// Source picked by `repository::resolve_source` when the folders of this
+    /// addon matched packages from several repositories. Persisted in
+    /// `config::Addons::sources`.
+    pub source: Option<RepositoryKind>,
+
     /// The repository package that this addon is linked against.
     pub(crate) repository: Option<RepositoryPackage>,
 
@@ -152,6 +207,10 @@ impl Addon {
             folders: Default::default(),
             release_channel: Default::default(),
             state: AddonState::Idle,
+            pinned_version: None,
+            auto_update: true,
+            updated_at: None,
+            source: None,
             repository: Default::default(),
 
             #[cfg(feature = "gui")]
@@ -208,6 +267,7 @@ impl Addon {
     pub fn update_addon_folders(&mut self, mut folders: Vec<AddonFolder>) {
         if !folders.is_empty() {
             folders.sort_by(|a, b| a.id.cmp(&b.id));
+            folders.dedup_by(|a, b| a.id == b.id);
 
             // Assign the primary folder id based on the first folder alphabetically with
             // a matching repository identifier otherwise just the first
@@ -229,8 +289,10 @@ impl Addon {
                         RepositoryKind::WowI => {
                             self.repository_id() == f.repository_identifiers.wowi.as_deref()
                         }
-                        // For git and hub sources, prioritize the folder that has a version in it
-                        RepositoryKind::Hub | RepositoryKind::Git(_) => f.version.is_some(),
+                        // For git, hub and local sources, prioritize the folder that has a version in it
+                        RepositoryKind::Hub | RepositoryKind::Git(_) | RepositoryKind::Local => {
+                            f.version.is_some()
+                        }
                     }
                 } else {
                     false
@@ -385,11 +447,10 @@ impl Addon {
         &self,
         default_release_channel: GlobalReleaseChannel,
     ) -> Result<Changelog, RepositoryError> {
-        let text = if let Some(repo) = self.repository.as_ref() {
-            repo.get_changelog(self.release_channel, default_release_channel)
-                .await?
-        } else {
-            None
+        let text = match fetch_changelog(self, default_release_channel).await {
+            Ok(text) => Some(text),
+            Err(RepositoryError::MissingChangelog { .. }) => None,
+            Err(error) => return Err(error),
         };
 
         Ok(Changelog { text })
@@ -456,6 +517,14 @@ impl Addon {
         self.folders.iter().find(|f| f.id == self.primary_folder_id)
     }
 
+    /// Returns the `.toc` file of the primary folder on disk, `Foo/Foo.toc` or a
+    /// flavor suffixed one like `Foo/Foo_Mainline.toc` if there's no plain one.
+    pub fn primary_toc_path(&self) -> Option<PathBuf> {
+        let folder = self.primary_addon_folder()?;
+
+        crate::fs::primary_toc_path(&folder.path, &folder.id)
+    }
+
     /// Returns the repository id for the active repository
     pub fn repository_id(&self) -> Option<&str> {
         self.repository().map(|r| r.id.as_str())
@@ -469,8 +538,29 @@ impl Addon {
         }
     }
 
+    /// Function returns a `bool` indicating if the user has pinned the addon to a version.
+    pub fn is_pinned(&self) -> bool {
+        self.pinned_version.is_some()
+    }
+
+    /// Pins the addon to its current version.
+    pub fn pin(&mut self) {
+        self.pinned_version = Some(self.version().unwrap_or_default().to_string());
+    }
+
+    /// Removes the version pin of the addon.
+    pub fn unpin(&mut self) {
+        self.pinned_version = None;
+    }
+
     /// Function returns a `bool` indicating if the `remote_package` is a update.
+    ///
+    /// Pinned addons are never updatable.
     pub fn is_updatable(&self, remote_package: &RemotePackage) -> bool {
+        if self.is_pinned() {
+            return false;
+        }
+
         let file_id = self.file_id();
 
         if file_id.is_none() {
@@ -480,10 +570,20 @@ impl Addon {
         remote_package.file_id > file_id
     }
 
-    /// We strip both version for non digits, and then
-    /// checks if `remote_version` is a sub_slice of `local_version`.
+    /// Compares both versions by their numbers, see `compare_versions`. If either
+    /// isn't a version number, eg. a commit hash, we strip both version for non
+    /// digits, and then check if `remote_version` is a sub_slice of `local_version`.
     fn is_updatable_by_version_comparison(&self, remote_package: &RemotePackage) -> bool {
         if let Some(version) = self.version() {
+            if is_version_number(&remote_package.version) && is_version_number(version) {
+                if let (Some(remote), Some(local)) = (
+                    parse_version(&remote_package.version),
+                    parse_version(version),
+                ) {
+                    return remote > local;
+                }
+            }
+
             let srv = strip_non_digits(&remote_package.version);
             let slv = strip_non_digits(version);
 
@@ -595,3 +695,581 @@ impl Ord for Addon {
 }
 
 impl Eq for Addon {}
+
+/// A dependency declared in the `.toc` file of an installed addon, which isn't
+/// installed itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingDependency {
+    /// `primary_folder_id` of the addon declaring the dependency.
+    pub addon_id: String,
+    /// Id of the `AddonFolder` declaring the dependency.
+    pub folder_id: String,
+    /// Folder name of the missing dependency.
+    pub dependency: String,
+    /// `true` if declared in `## OptionalDeps:`, the addon will still load without it.
+    pub optional: bool,
+}
+
+/// Returns all dependencies declared by the folders of `installed` which aren't
+/// provided by any installed folder. Dependencies on Blizzard addons are skipped,
+/// since they ship with the game.
+pub fn check_dependencies(installed: &[Addon]) -> Vec<MissingDependency> {
+    let is_installed = |dependency: &str| {
+        dependency.starts_with("Blizzard_")
+            || installed
+                .iter()
+                .flat_map(|a| a.folders.iter())
+                .any(|f| f.id.eq_ignore_ascii_case(dependency))
+    };
+
+    let mut missing = vec![];
+
+    for addon in installed {
+        for folder in addon.folders.iter() {
+            let dependencies = folder.dependencies.iter().map(|d| (d, false));
+            let optional_dependencies = folder.optional_dependencies.iter().map(|d| (d, true));
+
+            for (dependency, optional) in dependencies.chain(optional_dependencies) {
+                if !dependency.is_empty() && !is_installed(dependency) {
+                    missing.push(MissingDependency {
+                        addon_id: addon.primary_folder_id.clone(),
+                        folder_id: folder.id.clone(),
+                        dependency: dependency.clone(),
+                        optional,
+                    });
+                }
+            }
+        }
+    }
+
+    missing
+}
+
+/// Groups `folders` into addons, nesting folders which belong to another one
+/// under it, so bundled libraries and modules don't show up as separate addons.
+///
+/// A folder belongs to another one if
+/// - its id extends the other id with `_` or `-`, eg. `ElvUI_Options` of `ElvUI`,
+///   or extends it at all while depending on it, eg. `WeakAurasOptions`.
+/// - it doesn't depend on anything itself and is a required dependency of only
+///   that folder, eg. a library only one installed addon needs.
+///
+/// The first folder of each addon is its primary folder.
+pub fn group_addon_folders(folders: Vec<AddonFolder>) -> Vec<Addon> {
+    let depends_on = |folder: &AddonFolder, id: &str| {
+        folder
+            .dependencies
+            .iter()
+            .any(|d| d.eq_ignore_ascii_case(id))
+    };
+
+    // Parents derived from the folder name, longest matching id wins.
+    let name_parents: Vec<Option<usize>> = folders
+        .iter()
+        .map(|folder| {
+            folders
+                .iter()
+                .enumerate()
+                .filter(|(_, other)| {
+                    let rest = match folder.id.strip_prefix(other.id.as_str()) {
+                        Some(rest) if !rest.is_empty() => rest,
+                        _ => return false,
+                    };
+
+                    rest.starts_with('_') || rest.starts_with('-') || depends_on(folder, &other.id)
+                })
+                .max_by_key(|(_, other)| other.id.len())
+                .map(|(idx, _)| idx)
+        })
+        .collect();
+
+    let parents: Vec<Option<usize>> = folders
+        .iter()
+        .enumerate()
+        .map(|(idx, folder)| {
+            if name_parents[idx].is_some() {
+                return name_parents[idx];
+            }
+
+            // Folders with dependencies of their own are addons, not libraries.
+            if !folder.dependencies.is_empty() {
+                return None;
+            }
+
+            let mut dependents = folders
+                .iter()
+                .enumerate()
+                .filter(|(other_idx, other)| *other_idx != idx && depends_on(other, &folder.id));
+
+            match (dependents.next(), dependents.next()) {
+                // Never nest a folder under one of its own modules.
+                (Some((dependent, _)), None) if name_parents[dependent] != Some(idx) => {
+                    Some(dependent)
+                }
+                _ => None,
+            }
+        })
+        .collect();
+
+    // Follows the parents up to the top level folder. Folders in a cycle are
+    // their own top level folder.
+    let root_of = |idx: usize| {
+        let mut current = idx;
+
+        for _ in 0..folders.len() {
+            match parents[current] {
+                Some(parent) if parent == idx => return idx,
+                Some(parent) => current = parent,
+                None => return current,
+            }
+        }
+
+        idx
+    };
+
+    let roots: Vec<usize> = (0..folders.len()).map(root_of).collect();
+    let mut slots: Vec<Option<AddonFolder>> = folders.into_iter().map(Some).collect();
+    let mut addons = vec![];
+
+    for root in 0..slots.len() {
+        if roots[root] != root {
+            continue;
+        }
+
+        let primary = slots[root].take().unwrap();
+        let mut addon = Addon::empty(&primary.id);
+        addon.folders.push(primary);
+
+        let mut children: Vec<_> = (0..slots.len())
+            .filter(|idx| roots[*idx] == root && *idx != root)
+            .filter_map(|idx| slots[idx].take())
+            .collect();
+        children.sort();
+        addon.folders.extend(children);
+
+        addons.push(addon);
+    }
+
+    addons
+}
+
+/// Sorts the folders of `addon` by id and removes all but the first folder of
+/// each id. Multi-toc addons can otherwise list a folder more than once, which
+/// inflates folder counts and deletes the same folder twice.
+pub fn dedup_folders(addon: &mut Addon) {
+    addon.folders.sort_by(|a, b| a.id.cmp(&b.id));
+    addon.folders.dedup_by(|a, b| a.id == b.id);
+}
+
+/// A top level folder claimed by more than one addon. Installing one of them
+/// overwrites the folder of the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FolderConflict {
+    /// Id of the conflicting `AddonFolder`.
+    pub folder_id: String,
+    /// `primary_folder_id` of every addon claiming the folder.
+    pub addon_ids: Vec<String>,
+}
+
+/// Returns all top level folders claimed by more than one of `addons`.
+/// Folder ids are compared case-insensitively, like the filesystem on Windows.
+pub fn detect_folder_conflicts(addons: &[Addon]) -> Vec<FolderConflict> {
+    let mut claims: Vec<FolderConflict> = vec![];
+
+    for addon in addons {
+        for folder in addon.folders.iter() {
+            let claim = match claims
+                .iter_mut()
+                .find(|c| c.folder_id.eq_ignore_ascii_case(&folder.id))
+            {
+                Some(claim) => claim,
+                None => {
+                    claims.push(FolderConflict {
+                        folder_id: folder.id.clone(),
+                        addon_ids: vec![],
+                    });
+                    claims.last_mut().unwrap()
+                }
+            };
+
+            if !claim.addon_ids.contains(&addon.primary_folder_id) {
+                claim.addon_ids.push(addon.primary_folder_id.clone());
+            }
+        }
+    }
+
+    claims.retain(|c| c.addon_ids.len() > 1);
+    claims
+}
+
+/// A folder whose version differs between two scans, see `diff_addons`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionChange {
+    /// Id of the changed `AddonFolder`.
+    pub folder_id: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Folders which changed on disk between two scans, sorted by folder id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddonDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<VersionChange>,
+}
+
+impl AddonDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares the folders of the `previous` addons against the `current` ones,
+/// by folder id and `.toc` version.
+pub fn diff_addons(previous: &[Addon], current: &[Addon]) -> AddonDiff {
+    let versions = |addons: &[Addon]| {
+        addons
+            .iter()
+            .flat_map(|a| a.folders.iter())
+            .map(|f| (f.id.clone(), f.version.clone()))
+            .collect::<BTreeMap<_, _>>()
+    };
+
+    let previous = versions(previous);
+    let mut current = versions(current);
+    let mut diff = AddonDiff::default();
+
+    for (folder_id, from) in previous {
+        match current.remove(&folder_id) {
+            Some(to) if to != from => diff.changed.push(VersionChange {
+                folder_id,
+                from,
+                to,
+            }),
+            Some(_) => {}
+            None => diff.removed.push(folder_id),
+        }
+    }
+
+    diff.added = current.into_keys().collect();
+    diff
+}
+
+/// A named set of addons used together, eg. for raiding. Persisted in
+/// `config::Addons::profiles`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// `primary_folder_id`'s of the addons in the profile.
+    pub addons: Vec<String>,
+}
+
+impl Profile {
+    pub fn new(name: &str, addons: Vec<String>) -> Self {
+        Profile {
+            name: name.to_string(),
+            addons,
+        }
+    }
+
+    /// Function returns a `bool` indicating if `addon` is part of the profile.
+    pub fn contains(&self, addon: &Addon) -> bool {
+        self.addons.iter().any(|id| id == &addon.primary_folder_id)
+    }
+}
+
+/// Addon ids to enable and disable to switch to a `Profile`, sorted by id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileDiff {
+    /// Addons of the profile which aren't active.
+    pub add: Vec<String>,
+    /// Active addons which aren't part of the profile.
+    pub remove: Vec<String>,
+}
+
+/// Compares the `active` addons against the addons of `profile`.
+pub fn profiles_diff(active: &[Addon], profile: &Profile) -> ProfileDiff {
+    let mut diff = ProfileDiff {
+        add: profile
+            .addons
+            .iter()
+            .filter(|id| !active.iter().any(|a| &a.primary_folder_id == *id))
+            .cloned()
+            .collect(),
+        remove: active
+            .iter()
+            .filter(|a| !profile.contains(a))
+            .map(|a| a.primary_folder_id.clone())
+            .collect(),
+    };
+
+    diff.add.sort();
+    diff.add.dedup();
+    diff.remove.sort();
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primary_toc_path() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("Foo_Mainline.toc"), "").unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        assert_eq!(addon.primary_toc_path(), None);
+
+        addon.folders = vec![AddonFolder {
+            id: "Foo".to_string(),
+            path: path.clone(),
+            ..Default::default()
+        }];
+        assert_eq!(
+            addon.primary_toc_path(),
+            Some(path.join("Foo_Mainline.toc"))
+        );
+    }
+
+    #[test]
+    fn test_is_updatable_by_version_comparison() {
+        let is_updatable = |local: &str, remote: &str| {
+            let mut addon = Addon::empty("Foo");
+            addon.folders = vec![AddonFolder {
+                id: "Foo".to_string(),
+                version: Some(local.to_string()),
+                ..Default::default()
+            }];
+
+            addon.is_updatable(&RemotePackage {
+                version: remote.to_string(),
+                download_url: String::new(),
+                file_id: None,
+                date_time: None,
+                modules: vec![],
+                changelog: None,
+            })
+        };
+
+        assert!(is_updatable("1.9.0", "1.10.0"));
+        assert!(!is_updatable("1.10.0", "1.9.0"));
+        assert!(!is_updatable("v1.2", "1.2.0"));
+
+        // Commit hashes can't be ordered, a different one is an update.
+        assert!(is_updatable("9f86d08", "5ad3c1f"));
+        assert!(is_updatable("5ad3c1f", "9f86d08"));
+        assert!(!is_updatable("5ad3c1f", "5ad3c1f"));
+    }
+
+    #[test]
+    fn test_dedup_folders() {
+        let folder = |id: &str, title: &str| AddonFolder {
+            id: id.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        };
+
+        let mut addon = Addon::empty("Foo");
+        addon.folders = vec![
+            folder("FooOptions", "Options"),
+            folder("Foo", "Mainline"),
+            folder("Foo", "Classic"),
+            folder("FooOptions", "Options"),
+        ];
+
+        dedup_folders(&mut addon);
+
+        let folders: Vec<_> = addon
+            .folders
+            .iter()
+            .map(|f| (f.id.as_str(), f.title.as_str()))
+            .collect();
+        assert_eq!(
+            folders,
+            vec![("Foo", "Mainline"), ("FooOptions", "Options")]
+        );
+    }
+
+    #[test]
+    fn test_check_dependencies() {
+        let folder =
+            |id: &str, dependencies: &[&str], optional_dependencies: &[&str]| AddonFolder {
+                id: id.to_string(),
+                dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+                optional_dependencies: optional_dependencies
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect(),
+                ..Default::default()
+            };
+
+        let mut foo = Addon::empty("Foo");
+        foo.folders = vec![
+            folder("Foo", &["Blizzard_Calendar", "LibStub"], &["Masque"]),
+            folder("Foo_Options", &["foo"], &[]),
+        ];
+        let mut bar = Addon::empty("Bar");
+        bar.folders = vec![folder("Bar", &["Ace3"], &[])];
+        let mut libstub = Addon::empty("LibStub");
+        libstub.folders = vec![folder("LibStub", &[], &[])];
+
+        let missing = check_dependencies(&[foo, bar, libstub]);
+
+        assert_eq!(
+            missing
+                .iter()
+                .map(|m| (m.folder_id.as_str(), m.dependency.as_str(), m.optional))
+                .collect::<Vec<_>>(),
+            vec![("Foo", "Masque", true), ("Bar", "Ace3", false)]
+        );
+    }
+    #[test]
+    fn test_detect_folder_conflicts() {
+        let addon = |id: &str, folders: &[&str]| {
+            let mut addon = Addon::empty(id);
+            addon.folders = folders
+                .iter()
+                .map(|f| AddonFolder {
+                    id: f.to_string(),
+                    ..Default::default()
+                })
+                .collect();
+            addon
+        };
+
+        let conflicts = detect_folder_conflicts(&[
+            addon("Foo", &["Foo", "Ace3"]),
+            addon("Bar", &["Bar", "ace3"]),
+            addon("Baz", &["Baz", "Baz"]),
+            addon("Qux", &["Ace3"]),
+        ]);
+
+        assert_eq!(
+            conflicts,
+            vec![FolderConflict {
+                folder_id: "Ace3".to_string(),
+                addon_ids: vec!["Foo".to_string(), "Bar".to_string(), "Qux".to_string()],
+            }]
+        );
+    }
+    #[test]
+    fn test_group_addon_folders() {
+        let folder = |id: &str, dependencies: &[&str]| AddonFolder {
+            id: id.to_string(),
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let addons = group_addon_folders(vec![
+            folder("Ace3", &[]),
+            folder("Details", &[]),
+            folder("DBM-Core", &["LibDBIcon"]),
+            folder("DBM-Raids", &["DBM-Core"]),
+            folder("ElvUI", &["ElvUI_Libraries"]),
+            folder("ElvUI_Libraries", &[]),
+            folder("ElvUI_Options", &["ElvUI"]),
+            folder("LibDBIcon", &[]),
+            folder("Plater", &["Ace3"]),
+            folder("Skada", &["Ace3"]),
+            folder("WeakAuras", &[]),
+            folder("WeakAurasOptions", &["WeakAuras"]),
+            folder("Weak", &[]),
+        ]);
+
+        assert_eq!(
+            addons
+                .iter()
+                .map(|a| (
+                    a.primary_folder_id.as_str(),
+                    a.folders.iter().map(|f| f.id.as_str()).collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Ace3", vec!["Ace3"]),
+                ("Details", vec!["Details"]),
+                ("DBM-Core", vec!["DBM-Core", "LibDBIcon"]),
+                ("DBM-Raids", vec!["DBM-Raids"]),
+                ("ElvUI", vec!["ElvUI", "ElvUI_Libraries", "ElvUI_Options"]),
+                ("Plater", vec!["Plater"]),
+                ("Skada", vec!["Skada"]),
+                ("WeakAuras", vec!["WeakAuras", "WeakAurasOptions"]),
+                ("Weak", vec!["Weak"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_addons() {
+        let addon = |folders: &[(&str, Option<&str>)]| {
+            let mut addon = Addon::empty(folders[0].0);
+            addon.folders = folders
+                .iter()
+                .map(|(id, version)| AddonFolder {
+                    id: id.to_string(),
+                    version: version.map(str::to_string),
+                    ..Default::default()
+                })
+                .collect();
+            addon
+        };
+
+        let previous = vec![
+            addon(&[("Foo", Some("1.0")), ("Foo_Options", Some("1.0"))]),
+            addon(&[("Bar", Some("2.0"))]),
+            addon(&[("Baz", None)]),
+        ];
+        let current = vec![
+            addon(&[("Foo", Some("1.1")), ("Foo_Options", Some("1.0"))]),
+            addon(&[("Baz", Some("3.0"))]),
+            addon(&[("Qux", None)]),
+        ];
+
+        assert_eq!(
+            diff_addons(&previous, &current),
+            AddonDiff {
+                added: vec!["Qux".to_string()],
+                removed: vec!["Bar".to_string()],
+                changed: vec![
+                    VersionChange {
+                        folder_id: "Baz".to_string(),
+                        from: None,
+                        to: Some("3.0".to_string()),
+                    },
+                    VersionChange {
+                        folder_id: "Foo".to_string(),
+                        from: Some("1.0".to_string()),
+                        to: Some("1.1".to_string()),
+                    },
+                ],
+            }
+        );
+        assert!(diff_addons(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_profiles_diff() {
+        let active = vec![Addon::empty("Details"), Addon::empty("WeakAuras")];
+        let profile = Profile::new(
+            "Raiding",
+            vec!["WeakAuras".to_string(), "BigWigs".to_string()],
+        );
+
+        assert!(profile.contains(&active[1]));
+        assert!(!profile.contains(&active[0]));
+        assert_eq!(
+            profiles_diff(&active, &profile),
+            ProfileDiff {
+                add: vec!["BigWigs".to_string()],
+                remove: vec!["Details".to_string()],
+            }
+        );
+        assert_eq!(
+            profiles_diff(&[], &Profile::new("Empty", vec![])),
+            ProfileDiff::default()
+        );
+    }
+}
@@ -11,11 +11,13 @@ use crate::{config::Flavor, error::DownloadError};
 use async_std::fs::rename;
 use async_std::sync::{Arc, Mutex};
 use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct FingerprintCache(HashMap<Flavor, Vec<Fingerprint>>);
@@ -57,21 +59,150 @@ pub async fn load_fingerprint_cache() -> Result<FingerprintCache, CacheError> {
     Ok(FingerprintCache::load_or_default()?)
 }
 
+/// Version of the `.toc` parsing a `TocCache` was filled with. Bump it whenever
+/// parsing changes, so unchanged `.toc` files are parsed again instead of being
+/// served outdated entries until they're modified.
+const TOC_CACHE_VERSION: u32 = 1;
+
+/// Held while `TocCache::save_merged` reads and writes the cache file.
+static TOC_CACHE_LOCK: Lazy<std::sync::Mutex<()>> = Lazy::new(Default::default);
+
+/// Parsed `.toc` files keyed by their path, so unchanged files don't have to be
+/// parsed again on every refresh.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TocCache {
+    version: u32,
+    entries: HashMap<PathBuf, TocCacheEntry>,
+}
+
+impl Default for TocCache {
+    fn default() -> Self {
+        TocCache {
+            version: TOC_CACHE_VERSION,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TocCacheEntry {
+    pub modified: SystemTime,
+    pub addon_folder: AddonFolder,
+}
+
+impl TocCache {
+    /// Loads the cache. A cache of another `TOC_CACHE_VERSION`, or an unversioned
+    /// one of older releases, is dropped.
+    pub(crate) fn load_current() -> TocCache {
+        TocCache::load_or_default()
+            .ok()
+            .filter(TocCache::is_current)
+            .unwrap_or_default()
+    }
+
+    fn is_current(&self) -> bool {
+        self.version == TOC_CACHE_VERSION
+    }
+
+    /// Returns the cached `AddonFolder` for `toc_path`, if it was parsed while
+    /// the file had the same `modified` time.
+    pub(crate) fn get(&self, toc_path: &Path, modified: SystemTime) -> Option<AddonFolder> {
+        self.entries
+            .get(toc_path)
+            .filter(|entry| entry.modified == modified)
+            .map(|entry| entry.addon_folder.clone())
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        toc_path: PathBuf,
+        modified: SystemTime,
+        addon_folder: AddonFolder,
+    ) {
+        self.entries.insert(
+            toc_path,
+            TocCacheEntry {
+                modified,
+                addon_folder,
+            },
+        );
+    }
+
+    /// Saves the cache merged into the saved one, which may have changed since
+    /// it was loaded, as flavors are parsed concurrently. Entries of `.toc` files
+    /// which no longer exist are dropped.
+    pub(crate) fn save_merged(self) -> Result<(), FilesystemError> {
+        let _guard = TOC_CACHE_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let mut saved = TocCache::load_current();
+        saved.merge(self);
+        saved.remove_missing();
+        saved.save()
+    }
+
+    /// Adds the entries of `other`. For a `.toc` file in both, the entry of the
+    /// more recently modified file is kept.
+    fn merge(&mut self, other: TocCache) {
+        for (toc_path, entry) in other.entries {
+            match self.entries.get(&toc_path) {
+                Some(existing) if existing.modified > entry.modified => {}
+                _ => {
+                    self.entries.insert(toc_path, entry);
+                }
+            }
+        }
+    }
+
+    /// Removes all entries of `.toc` files which no longer exist.
+    fn remove_missing(&mut self) {
+        self.entries.retain(|toc_path, _| toc_path.exists());
+    }
+}
+
+impl PersistentData for TocCache {
+    fn relative_path() -> PathBuf {
+        PathBuf::from("cache/tocs.yml")
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum AddonCache {
     V1(HashMap<Flavor, Vec<AddonCacheEntry>>),
+    /// Same as `V1`, but the folder names of every entry are deduplicated.
+    V2(HashMap<Flavor, Vec<AddonCacheEntry>>),
 }
 
 impl Default for AddonCache {
     fn default() -> Self {
-        AddonCache::V1(Default::default())
+        AddonCache::V2(Default::default())
     }
 }
 
 impl AddonCache {
     pub(crate) fn get_mut_for_flavor(&mut self, flavor: Flavor) -> &mut Vec<AddonCacheEntry> {
         match self {
-            AddonCache::V1(cache) => cache.entry(flavor).or_default(),
+            AddonCache::V1(cache) | AddonCache::V2(cache) => cache.entry(flavor).or_default(),
+        }
+    }
+
+    /// Migrates the cache to the latest version. Returns `true` if anything
+    /// changed and the cache should be saved.
+    pub(crate) fn migrate(&mut self) -> bool {
+        match self {
+            AddonCache::V1(cache) => {
+                let mut cache = std::mem::take(cache);
+
+                for entry in cache.values_mut().flatten() {
+                    entry.folder_names.sort();
+                    entry.folder_names.dedup();
+                }
+
+                *self = AddonCache::V2(cache);
+                true
+            }
+            AddonCache::V2(_) => false,
         }
     }
 }
@@ -83,7 +214,14 @@ impl PersistentData for AddonCache {
 }
 
 pub async fn load_addon_cache() -> Result<AddonCache, CacheError> {
-    Ok(AddonCache::load_or_default()?)
+    let mut addon_cache: AddonCache = AddonCache::load_or_default()?;
+
+    if addon_cache.migrate() {
+        log::debug!("migrated addon cache");
+        addon_cache.save()?;
+    }
+
+    Ok(addon_cache)
 }
 
 /// Update the cache with input entry. If an entry already exists in the cache,
@@ -207,6 +345,7 @@ impl TryFrom<&Addon> for AddonCacheEntry {
         {
             let mut folder_names: Vec<_> = addon.folders.iter().map(|a| a.id.clone()).collect();
             folder_names.sort();
+            folder_names.dedup();
 
             let external_release_id = if repository == RepositoryKind::Hub {
                 addon.file_id().map(ExternalReleaseId::FileId)
@@ -240,24 +379,62 @@ pub enum ExternalReleaseId {
     Version(String),
 }
 
+/// The catalog of all sources, shared by all flavors. Filter it by flavor
+/// with `Catalog::for_flavor`.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CatalogCache {
     etag: String,
+    /// When the cached catalog should be checked for a newer one. Caches
+    /// written before this existed are always checked.
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
     catalog: Catalog,
 }
 
+impl CatalogCache {
+    fn is_expired(&self) -> bool {
+        self.expires_at.map(|at| at <= Utc::now()).unwrap_or(true)
+    }
+
+    /// Returns the cached catalog if it can be used without checking for a
+    /// newer one, which is never the case when `force` is set.
+    fn fresh_catalog(&self, force: bool) -> Option<&Catalog> {
+        if force || self.is_expired() {
+            None
+        } else {
+            Some(&self.catalog)
+        }
+    }
+}
+
 impl PersistentData for CatalogCache {
     fn relative_path() -> PathBuf {
         PathBuf::from("cache/catalog.yml")
     }
 }
 
-pub async fn catalog_download_latest_or_use_cache() -> Result<Catalog, DownloadError> {
+/// Returns the cached catalog if it hasn't expired yet, otherwise downloads
+/// the latest one unless the cached one is still the latest. The cache then
+/// expires after `ttl`. With `force`, the cached catalog is always checked.
+pub async fn catalog_download_latest_or_use_cache(
+    ttl: chrono::Duration,
+    force: bool,
+) -> Result<Catalog, DownloadError> {
     let maybe_cached_catalog = CatalogCache::load();
 
+    if let Some(catalog) = maybe_cached_catalog
+        .as_ref()
+        .ok()
+        .and_then(|cache| cache.fresh_catalog(force))
+    {
+        log::debug!("Cached catalog hasn't expired yet, using it");
+        return Ok(catalog.clone());
+    }
+
     // If no cache file exists yet, this will be None and download_catalog will
     // always download the latest catalog
     let cached_etag = maybe_cached_catalog.as_ref().map(|c| c.etag.clone()).ok();
+    let expires_at = Some(Utc::now() + ttl);
 
     if let Some((downloaded_etag, downloaded_catalog)) = download_catalog(cached_etag).await? {
         // Etag didn't match latest catalog, so we downloaded new one. Let's update
@@ -266,6 +443,7 @@ pub async fn catalog_download_latest_or_use_cache() -> Result<Catalog, DownloadE
             // Save it as cache
             let new_cache = CatalogCache {
                 catalog: downloaded_catalog.clone(),
+                expires_at,
                 etag,
             };
             new_cache.save()?;
@@ -274,7 +452,11 @@ pub async fn catalog_download_latest_or_use_cache() -> Result<Catalog, DownloadE
         Ok(downloaded_catalog)
     } else {
         // If download_catalog returns None, we have the latest cache file, so use it
-        let cache = maybe_cached_catalog?;
+        let mut cache = maybe_cached_catalog?;
+
+        // Push back the expiry, the cached catalog was just confirmed as latest.
+        cache.expires_at = expires_at;
+        cache.save()?;
 
         Ok(cache.catalog)
     }
@@ -297,6 +479,9 @@ mod test {
                     id: format!("folder_{}", idx + 1),
                     title: format!("folder_{}", idx + 1),
                     interface: Default::default(),
+                    interfaces: Default::default(),
+                    flavor_interfaces: Default::default(),
+                    toc_flavor: Default::default(),
                     path: Default::default(),
                     author: Default::default(),
                     notes: Default::default(),
@@ -306,6 +491,7 @@ mod test {
                         ..Default::default()
                     },
                     dependencies: Default::default(),
+                    optional_dependencies: Default::default(),
                     fingerprint: Default::default(),
                 })
                 .collect::<Vec<_>>();
@@ -354,4 +540,89 @@ mod test {
             assert_eq!(names, vec!["Test2".to_string()]);
         });
     }
+
+    #[test]
+    fn test_toc_cache_version() {
+        // Unversioned caches of older releases were a plain map of `.toc` paths.
+        let yaml = "---\n/Foo/Foo.toc:\n  modified: ~\n  addon_folder: ~\n";
+        assert!(serde_yaml::from_str::<TocCache>(yaml).is_err());
+
+        let yaml = "---\nversion: 0\nentries: {}\n";
+        assert!(!serde_yaml::from_str::<TocCache>(yaml).unwrap().is_current());
+
+        let yaml = serde_yaml::to_string(&TocCache::default()).unwrap();
+        assert!(serde_yaml::from_str::<TocCache>(&yaml)
+            .unwrap()
+            .is_current());
+    }
+
+    #[test]
+    fn test_merge_toc_cache() {
+        let folder = |title: &str| AddonFolder {
+            title: title.to_string(),
+            ..Default::default()
+        };
+        let old = SystemTime::UNIX_EPOCH;
+        let new = old + std::time::Duration::from_secs(60);
+
+        // Saved by the parse of another flavor in the meantime.
+        let mut saved = TocCache::default();
+        saved.insert(PathBuf::from("/Retail/Foo.toc"), new, folder("Foo new"));
+        saved.insert(PathBuf::from("/Classic/Bar.toc"), old, folder("Bar"));
+
+        let mut cache = TocCache::default();
+        cache.insert(PathBuf::from("/Retail/Foo.toc"), old, folder("Foo old"));
+        cache.insert(PathBuf::from("/Retail/Baz.toc"), old, folder("Baz"));
+        saved.merge(cache);
+
+        let title = |path: &str, modified| {
+            saved
+                .get(Path::new(path), modified)
+                .map(|folder| folder.title)
+        };
+        assert_eq!(title("/Retail/Foo.toc", new), Some("Foo new".to_string()));
+        assert_eq!(title("/Classic/Bar.toc", old), Some("Bar".to_string()));
+        assert_eq!(title("/Retail/Baz.toc", old), Some("Baz".to_string()));
+    }
+
+    #[test]
+    fn test_migrate_addon_cache() {
+        let yaml = r#"---
+V1:
+  Retail:
+    - title: Foo
+      repository: Curse
+      repository_id: "1"
+      primary_folder_id: Foo
+      folder_names: [FooOptions, Foo, Foo]
+      modified: "2021-01-01T00:00:00Z"
+      external_release_id: ~
+"#;
+        let mut cache: AddonCache = serde_yaml::from_str(yaml).unwrap();
+
+        assert!(cache.migrate());
+        assert!(matches!(cache, AddonCache::V2(_)));
+        assert_eq!(
+            cache.get_mut_for_flavor(Flavor::Retail)[0].folder_names,
+            vec!["Foo".to_string(), "FooOptions".to_string()]
+        );
+
+        assert!(!cache.migrate());
+    }
+
+    #[test]
+    fn test_catalog_cache_force() {
+        let cache = |expires_at| CatalogCache {
+            etag: "etag".to_string(),
+            expires_at,
+            catalog: Catalog { addons: vec![] },
+        };
+        let later = Some(Utc::now() + chrono::Duration::hours(1));
+        let earlier = Some(Utc::now() - chrono::Duration::hours(1));
+
+        assert!(cache(later).fresh_catalog(false).is_some());
+        assert!(cache(later).fresh_catalog(true).is_none());
+        assert!(cache(earlier).fresh_catalog(false).is_none());
+        assert!(cache(None).fresh_catalog(false).is_none());
+    }
 }
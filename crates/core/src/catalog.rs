@@ -1,6 +1,7 @@
 use crate::config::Flavor;
 use crate::error::DownloadError;
 use crate::network::request_async;
+use crate::utility::{flavor_from_interface, game_version_to_interface};
 
 use chrono::prelude::*;
 use isahc::AsyncReadResponseExt;
@@ -83,6 +84,44 @@ pub struct Catalog {
     pub addons: Vec<CatalogAddon>,
 }
 
+impl Catalog {
+    /// Returns the catalog with only the addons supporting `flavor`, see
+    /// `CatalogAddon::supports_flavor`.
+    pub fn for_flavor(&self, flavor: Flavor) -> Catalog {
+        let addons = self
+            .addons
+            .iter()
+            .filter(|addon| addon.supports_flavor(flavor))
+            .cloned()
+            .collect();
+
+        Catalog { addons }
+    }
+
+    /// Returns all addons matching `query` with their score, see
+    /// `CatalogAddon::search_score`. Best matches come first, ties are broken
+    /// by number of downloads.
+    pub fn search(&self, query: &str) -> Vec<(&CatalogAddon, i64)> {
+        let query = query.trim().to_lowercase();
+
+        let mut matches: Vec<_> = self
+            .addons
+            .iter()
+            .filter_map(|addon| addon.search_score(&query).map(|score| (addon, score)))
+            .collect();
+
+        matches.sort_by(|(addon_a, score_a), (addon_b, score_b)| {
+            score_b.cmp(score_a).then_with(|| {
+                addon_b
+                    .number_of_downloads
+                    .cmp(&addon_a.number_of_downloads)
+            })
+        });
+
+        matches
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Version {
     pub flavor: Flavor,
@@ -110,6 +149,158 @@ pub struct CatalogAddon {
     pub versions: Vec<Version>,
 }
 
+impl CatalogAddon {
+    /// Returns `true` if the addon has a version for the base flavor of `flavor`,
+    /// built for a game version of that flavor. A version without a game version
+    /// of a known flavor is trusted to be built for its flavor.
+    pub fn supports_flavor(&self, flavor: Flavor) -> bool {
+        let flavor = flavor.base_flavor();
+
+        self.versions.iter().any(|v| {
+            v.flavor == flavor
+                && v.game_version
+                    .as_deref()
+                    .and_then(game_version_to_interface)
+                    .and_then(flavor_from_interface)
+                    .map(|version_flavor| version_flavor == flavor)
+                    .unwrap_or(true)
+        })
+    }
+
+    /// Returns how well the addon matches `query`, higher is better, or `None`
+    /// if it doesn't match at all.
+    ///
+    /// The characters of `query` have to appear in order in the name, so `dbm`
+    /// matches `Deadly Boss Mods`. Matches on word starts and consecutive
+    /// characters score higher. If that fails, a word of the name one typo
+    /// away from `query` still matches. Matches in the summary score half.
+    pub fn search_score(&self, query: &str) -> Option<i64> {
+        let query = query.to_lowercase();
+        let query = query.trim();
+
+        let name = fuzzy_score(query, &self.name).or_else(|| typo_score(query, &self.name));
+        let summary = fuzzy_score(query, &self.summary).map(|score| score / 2);
+
+        name.max(summary)
+    }
+}
+
+/// Scores a subsequence match of the lowercase `query` in `text`.
+///
+/// Characters are matched greedily in a single pass, which keeps scoring a
+/// catalog of tens of thousands of addons cheap enough for every keystroke.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    const MATCH: i64 = 16;
+    const WORD_START: i64 = 24;
+    const CONSECUTIVE: i64 = 16;
+    const MAX_GAP_PENALTY: i64 = 12;
+
+    let query: Vec<char> = query.chars().collect();
+    if query.is_empty() {
+        return None;
+    }
+
+    let mut score = 0;
+    let mut matched = 0;
+    let mut last_match: Option<usize> = None;
+    let mut previous: Option<char> = None;
+
+    for (i, c) in text.chars().enumerate() {
+        if matched == query.len() {
+            break;
+        }
+
+        if c.to_lowercase().eq(std::iter::once(query[matched])) {
+            score += MATCH;
+
+            let word_start = match previous {
+                None => true,
+                Some(p) => !p.is_alphanumeric() || (p.is_lowercase() && c.is_uppercase()),
+            };
+            if word_start {
+                score += WORD_START;
+            }
+
+            match last_match {
+                Some(last) if last + 1 == i => score += CONSECUTIVE,
+                Some(last) => score -= (2 + (i - last) as i64).min(MAX_GAP_PENALTY),
+                None => {}
+            }
+
+            matched += 1;
+            last_match = Some(i);
+        }
+
+        previous = Some(c);
+    }
+
+    if matched < query.len() {
+        return None;
+    }
+
+    let text = text.to_lowercase();
+    if text.starts_with(query.iter().collect::<String>().as_str()) {
+        score += MATCH * query.len() as i64;
+    } else if text.contains(query.iter().collect::<String>().as_str()) {
+        score += MATCH * query.len() as i64 / 2;
+    }
+
+    Some(score.max(1))
+}
+
+/// Scores `query` against the words of `text`, allowing a single typo. Only
+/// used for queries of at least four characters, shorter ones would match
+/// almost anything.
+fn typo_score(query: &str, text: &str) -> Option<i64> {
+    let query: Vec<char> = query.chars().collect();
+    if query.len() < 4 {
+        return None;
+    }
+
+    let matches = text
+        .split(|c: char| !c.is_alphanumeric())
+        .map(|word| word.to_lowercase().chars().collect::<Vec<_>>())
+        .any(|word| {
+            // The typo can also be an added or removed character in the query.
+            (query.len() - 1..=query.len() + 1)
+                .filter(|&len| len <= word.len())
+                .any(|len| within_one_edit(&query, &word[..len]))
+        });
+
+    if matches {
+        Some(8 * query.len() as i64)
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if `a` and `b` differ by at most one substitution,
+/// insertion, deletion or swap of adjacent characters.
+fn within_one_edit(a: &[char], b: &[char]) -> bool {
+    if a.len() > b.len() {
+        return within_one_edit(b, a);
+    }
+    if b.len() - a.len() > 1 {
+        return false;
+    }
+
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    if prefix == a.len() {
+        return true;
+    }
+
+    if a.len() < b.len() {
+        return a[prefix..] == b[prefix + 1..];
+    }
+
+    let swapped = prefix + 1 < a.len()
+        && a[prefix] == b[prefix + 1]
+        && a[prefix + 1] == b[prefix]
+        && a[prefix + 2..] == b[prefix + 2..];
+
+    a[prefix + 1..] == b[prefix + 1..] || swapped
+}
+
 mod null_to_default {
     use serde::{self, Deserialize, Deserializer};
 
@@ -257,6 +448,110 @@ mod tests {
         });
     }
 
+    fn catalog_addon(name: &str, summary: &str, number_of_downloads: u64) -> CatalogAddon {
+        CatalogAddon {
+            id: 0,
+            url: String::new(),
+            name: name.to_string(),
+            categories: vec![],
+            summary: summary.to_string(),
+            number_of_downloads,
+            source: Source::Curse,
+            versions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_catalog_search() {
+        let catalog = Catalog {
+            addons: vec![
+                catalog_addon("Details! Damage Meter", "Damage meter", 500),
+                catalog_addon("Deadly Boss Mods", "Boss timers", 1000),
+                catalog_addon("WeakAuras", "Powerful display framework", 800),
+                catalog_addon("Bagnon", "Single window bags", 300),
+                catalog_addon("DBM - Voicepack", "Voice packs for Deadly Boss Mods", 10),
+            ],
+        };
+
+        let names = |query: &str| {
+            catalog
+                .search(query)
+                .into_iter()
+                .map(|(addon, _)| addon.name.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("dbm"), vec!["DBM - Voicepack", "Deadly Boss Mods"]);
+        assert_eq!(names("weakaruas"), vec!["WeakAuras"]);
+        assert_eq!(names("bgnon"), vec!["Bagnon"]);
+        assert_eq!(names("Bags")[0], "Bagnon");
+        assert!(names("zzz").is_empty());
+    }
+
+    #[test]
+    fn test_catalog_for_flavor() {
+        let version = |flavor| Version {
+            flavor,
+            game_version: None,
+            date: None,
+        };
+
+        let mut retail = catalog_addon("Retail", "", 0);
+        retail.versions = vec![version(Flavor::Retail)];
+        let mut both = catalog_addon("Both", "", 0);
+        both.versions = vec![version(Flavor::Retail), version(Flavor::ClassicEra)];
+        let catalog = Catalog {
+            addons: vec![retail, both],
+        };
+
+        let names = |flavor| {
+            catalog
+                .for_flavor(flavor)
+                .addons
+                .into_iter()
+                .map(|addon| addon.name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(Flavor::Retail), vec!["Retail", "Both"]);
+        assert_eq!(names(Flavor::RetailPtr), vec!["Retail", "Both"]);
+        assert_eq!(names(Flavor::ClassicEra), vec!["Both"]);
+    }
+
+    #[test]
+    fn test_catalog_addon_supports_flavor() {
+        let version = |flavor, game_version: &str| Version {
+            flavor,
+            game_version: Some(game_version.to_string()),
+            date: None,
+        };
+
+        let mut addon = catalog_addon("Foo", "", 0);
+        addon.versions = vec![
+            version(Flavor::Retail, "9.1.5"),
+            // Tagged as Classic Era, but built for Burning Crusade Classic.
+            version(Flavor::ClassicEra, "2.5.2"),
+        ];
+        assert!(addon.supports_flavor(Flavor::Retail));
+        assert!(!addon.supports_flavor(Flavor::ClassicEra));
+        assert!(!addon.supports_flavor(Flavor::ClassicTbc));
+
+        addon.versions.push(version(Flavor::ClassicEra, "1.14.1"));
+        assert!(addon.supports_flavor(Flavor::ClassicEra));
+    }
+
+    #[test]
+    fn test_within_one_edit() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+
+        assert!(within_one_edit(&chars("bagnon"), &chars("bagnon")));
+        assert!(within_one_edit(&chars("bagnon"), &chars("bagmon")));
+        assert!(within_one_edit(&chars("bagnon"), &chars("bgnon")));
+        assert!(within_one_edit(&chars("bagnon"), &chars("bangon")));
+        assert!(!within_one_edit(&chars("bagnon"), &chars("bgnno")));
+        assert!(!within_one_edit(&chars("bagnon"), &chars("bag")));
+    }
+
     #[test]
     fn test_null_fields() {
         let tests = [
@@ -1,8 +1,11 @@
 use super::Flavor;
-use crate::repository::{GlobalReleaseChannel, ReleaseChannel};
+use crate::addon::Profile;
+use crate::repository::{GlobalReleaseChannel, ReleaseChannel, RepositoryKind};
+use chrono::{DateTime, Utc};
 use de::de_ignored;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 /// Struct for addons specific settings.
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
@@ -18,6 +21,44 @@ pub struct Addons {
 
     #[serde(default)]
     pub delete_saved_variables: bool,
+
+    /// Addon ids pinned to a version, which updates should skip.
+    #[serde(default)]
+    pub pinned: HashMap<Flavor, HashMap<String, String>>,
+
+    /// Addon ids left out of "update all", which only update manually.
+    #[serde(default)]
+    pub auto_update_disabled: HashMap<Flavor, Vec<String>>,
+
+    /// Named sets of addons used together.
+    #[serde(default)]
+    pub profiles: HashMap<Flavor, Vec<Profile>>,
+
+    /// When each addon was last installed or updated, by addon id.
+    #[serde(default)]
+    pub updated_at: HashMap<Flavor, HashMap<String, DateTime<Utc>>>,
+
+    /// Folder ids Ajour never deletes, replaces or reports as orphaned.
+    #[serde(default)]
+    pub ignored_folders: Vec<String>,
+
+    /// Order in which sources are preferred when a folder matches several.
+    /// Empty uses `repository::DEFAULT_SOURCE_PRIORITY`.
+    #[serde(default)]
+    pub source_priority: Vec<RepositoryKind>,
+
+    /// Source chosen for each folder id which matched several, so it sticks.
+    #[serde(default)]
+    pub sources: HashMap<Flavor, HashMap<String, RepositoryKind>>,
+
+    /// Program run after each install with the addon id and the paths of its
+    /// installed folders as arguments. Unset by default.
+    #[serde(default)]
+    pub post_install_command: Option<PathBuf>,
+
+    /// Base urls of self-hosted GitLab instances, next to gitlab.com.
+    #[serde(default)]
+    pub gitlab_instances: Vec<String>,
 }
 
 impl Default for Addons {
@@ -27,6 +68,15 @@ impl Default for Addons {
             ignored: HashMap::new(),
             release_channels: HashMap::new(),
             delete_saved_variables: Default::default(),
+            pinned: HashMap::new(),
+            auto_update_disabled: HashMap::new(),
+            profiles: HashMap::new(),
+            updated_at: HashMap::new(),
+            ignored_folders: vec![],
+            source_priority: vec![],
+            sources: HashMap::new(),
+            post_install_command: None,
+            gitlab_instances: vec![],
         }
     }
 }
@@ -72,6 +72,10 @@ pub struct Config {
     #[serde(default)]
     pub catalog_source: Option<catalog::Source>,
 
+    /// Hours the cached catalog is used without checking for a newer one.
+    #[serde(default)]
+    pub catalog_cache_ttl_hours: Option<i64>,
+
     #[serde(default)]
     pub auto_update: bool,
 
@@ -114,6 +118,12 @@ impl Config {
         }
     }
 
+    /// Returns how long the cached catalog is used without checking for a newer
+    /// one, `catalog_cache_ttl_hours` or 24 hours if not set.
+    pub fn catalog_cache_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.catalog_cache_ttl_hours.unwrap_or(24))
+    }
+
     /// Returns a `PathBuf` to the flavor directory.
     pub fn get_flavor_directory_for_flavor(&self, flavor: &Flavor, path: &Path) -> PathBuf {
         path.join(&flavor.folder_name())
@@ -385,7 +395,15 @@ impl Default for Language {
 pub async fn load_config() -> Result<Config, FilesystemError> {
     log::debug!("loading config");
 
-    Ok(Config::load_or_default()?)
+    let config: Config = Config::load_or_default()?;
+
+    crate::fs::set_ignored_folders(config.addons.ignored_folders.clone());
+    crate::repository::set_source_priority(config.addons.source_priority.clone());
+    crate::repository::set_chosen_sources(config.addons.sources.clone());
+    crate::fs::set_post_install_command(config.addons.post_install_command.clone());
+    crate::repository::set_gitlab_instances(config.addons.gitlab_instances.clone());
+
+    Ok(config)
 }
 
 const fn default_true() -> bool {
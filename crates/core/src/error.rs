@@ -9,11 +9,17 @@ pub enum FilesystemError {
     #[error(transparent)]
     SerdeYaml(#[from] serde_yaml::Error),
     #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error(transparent)]
     Zip(#[from] zip::result::ZipError),
     #[error(transparent)]
     WalkDir(#[from] walkdir::Error),
+    #[error(transparent)]
+    GlobPattern(#[from] glob::PatternError),
     #[error("File doesn't exist: {path:?}")]
     FileDoesntExist { path: PathBuf },
+    #[error("Addon folder {id} has no .toc file in {path:?}")]
+    MissingToc { id: String, path: PathBuf },
     #[cfg(target_os = "macos")]
     #[error("Could not file bin name {bin_name} in archive")]
     BinMissingFromTar { bin_name: String },
@@ -21,6 +27,26 @@ pub enum FilesystemError {
     NormalizingPathSlash { path: PathBuf },
     #[error("Could not strip prefix {prefix:?} from {from:?}")]
     StripPrefix { prefix: String, from: String },
+    #[error("Archive entry {entry} uses the unsupported compression method {method}")]
+    UnsupportedCompression { method: String, entry: String },
+    #[error("Archive entry {name} resolves to a path outside of the addon directory")]
+    UnsafeArchivePath { name: String },
+    #[error("Addon {id} doesn't support {flavor}, it was built for interface {interfaces:?}")]
+    IncompatibleFlavor {
+        id: String,
+        flavor: Flavor,
+        interfaces: Vec<u32>,
+    },
+    #[error("Failed to move {path:?} to the trash: {source}")]
+    Trash { path: PathBuf, source: trash::Error },
+    #[error("Failed to uninstall {id}: {}", .errors.join(", "))]
+    Uninstall { id: String, errors: Vec<String> },
+    #[error("Addon {id} is pinned to version {version}")]
+    AddonPinned { id: String, version: String },
+    #[error("Archive checksum mismatch, expected {expected} but got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("Operation was cancelled")]
+    Cancelled,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -38,6 +64,8 @@ pub enum DownloadError {
         content_length: u64,
         body_length: u64,
     },
+    #[error("Download from {url} was interrupted after {downloaded} bytes")]
+    Interrupted { url: String, downloaded: u64 },
     #[error("Invalid status code {code} for url {url}")]
     InvalidStatusCode {
         code: isahc::http::StatusCode,
@@ -45,6 +73,19 @@ pub enum DownloadError {
     },
     #[error("No new release binary available for {bin_name}")]
     MissingSelfUpdateRelease { bin_name: String },
+    #[error("Too many requests to {url}")]
+    TooManyRequests {
+        url: String,
+        retry_after: Option<std::time::Duration>,
+    },
+    #[error("Download from {url} failed after {attempts} attempts: {source}")]
+    RetriesExhausted {
+        url: String,
+        attempts: u32,
+        source: Box<DownloadError>,
+    },
+    #[error("Download from {url} is not a zip archive")]
+    NotZipArchive { url: String },
     #[error("Catalog failed to download")]
     CatalogFailed,
     #[error("Self update for linux only works from AppImage")]
@@ -81,6 +122,12 @@ pub enum RepositoryError {
     WowIMissingPackage { id: String },
     #[error("No package found for Hub id {id}")]
     HubMissingPackage { id: String },
+    #[error("No package found for Tukui id {id}")]
+    TukuiMissingPackage { id: String },
+    #[error("Tukui addon {id} doesn't support {flavor}")]
+    TukuiUnsupportedFlavor { id: String, flavor: Flavor },
+    #[error("No changelog found for {id}")]
+    MissingChangelog { id: String },
     #[error("No remote package found for channel {channel}")]
     MissingPackageChannel { channel: ReleaseChannel },
     #[error("Git repo must be created with `from_source_url`")]
@@ -89,7 +136,9 @@ pub enum RepositoryError {
     GitInvalidUrl { url: String },
     #[error("No valid host in {url}")]
     GitMissingHost { url: String },
-    #[error("Invalid host {host}, only github.com and gitlab.com are supported")]
+    #[error(
+        "Invalid host {host}, only github.com, gitlab.com and configured GitLab instances are supported"
+    )]
     GitInvalidHost { host: String },
     #[error("Author not present in {url}")]
     GitMissingAuthor { url: String },
@@ -101,6 +150,10 @@ pub enum RepositoryError {
     GitNoZip { flavor: Flavor, url: String },
     #[error("Tag name must be specified for git changelog")]
     GitChangelogTagName,
+    #[error("No archive found for local addon {id}")]
+    LocalMissingPackage { id: String },
+    #[error("Local repo must be created with `from_local_dir`")]
+    LocalWrongConstructor,
     #[error(transparent)]
     Download(#[from] DownloadError),
     #[error(transparent)]
@@ -1,138 +1,4930 @@
 use super::Result;
 use crate::{
     addon::{Addon, AddonFolder},
-    parse::parse_toc_path,
+    config::Flavor,
+    error::FilesystemError,
+    parse::{flavor_from_toc_suffix, parse_toc_path},
+    repository::RepositoryKind,
+    utility::{flavor_from_interface, remove_dir_all},
 };
-use std::collections::HashSet;
-use std::fs::{remove_dir_all, remove_file};
-use std::path::Path;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use flate2::read::GzDecoder;
+use futures::stream::{self, StreamExt};
+use md5::{Digest, Md5};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::remove_file;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tar::EntryType;
 use walkdir::WalkDir;
 
+/// Whether folders are looked up case-insensitively by default. WoW expects
+/// case-insensitive paths, which only Linux (eg. running WoW through Wine)
+/// doesn't provide.
+pub const DEFAULT_CASE_INSENSITIVE: bool = cfg!(target_os = "linux");
+
+/// Folder ids the user maintains by hand, which are never deleted, replaced or
+/// reported as orphaned.
+static IGNORED_FOLDERS: Lazy<RwLock<Vec<String>>> = Lazy::new(Default::default);
+
+/// Sets the folder ids which are never touched by any filesystem operation.
+/// Replaces the previously set ids.
+pub fn set_ignored_folders(folder_ids: Vec<String>) {
+    *IGNORED_FOLDERS.write().unwrap() = folder_ids;
+}
+
+/// Returns `true` if the folder `id` was set with `set_ignored_folders`.
+/// Ids are compared case-insensitively, like WoW does.
+pub fn is_folder_ignored(id: &str) -> bool {
+    IGNORED_FOLDERS
+        .read()
+        .unwrap()
+        .iter()
+        .any(|ignored| ignored.eq_ignore_ascii_case(id))
+}
+
+/// Command run after every successful install, see `set_post_install_command`.
+static POST_INSTALL_COMMAND: Lazy<RwLock<Option<PathBuf>>> = Lazy::new(Default::default);
+
+/// Sets the command run after an addon was installed, with the addon id and the
+/// paths of its installed folders as arguments. `None` disables it, which is the
+/// default since it runs an arbitrary program.
+pub fn set_post_install_command(command: Option<PathBuf>) {
+    *POST_INSTALL_COMMAND.write().unwrap() = command;
+}
+
+/// Runs `command` for the installed `addon_folders` of addon `id`, logging its
+/// output. A failing command is only logged, as the install itself succeeded.
+fn run_post_install_command(command: &Path, id: &str, addon_folders: &[AddonFolder]) {
+    log::debug!("{} - running post install command {:?}", id, command);
+
+    let output = std::process::Command::new(command)
+        .arg(id)
+        .args(addon_folders.iter().map(|f| &f.path))
+        .output();
+
+    let output = match output {
+        Ok(output) => output,
+        Err(error) => {
+            log::warn!(
+                "{} - failed to run post install command {:?}: {}",
+                id,
+                command,
+                error
+            );
+            return;
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if !stdout.trim().is_empty() {
+        log::info!("{} - post install command stdout: {}", id, stdout.trim());
+    }
+    if !stderr.trim().is_empty() {
+        log::info!("{} - post install command stderr: {}", id, stderr.trim());
+    }
+
+    if !output.status.success() {
+        log::warn!(
+            "{} - post install command {:?} exited with {}",
+            id,
+            command,
+            output.status
+        );
+    }
+}
+
 /// Deletes an Addon and all dependencies from disk.
-pub fn delete_addons(addon_folders: &[AddonFolder]) -> Result<()> {
+///
+/// If `to_trash` is set, folders are moved to the trash of the OS instead, so
+/// they can be recovered. They're only deleted with a warning if the system has
+/// no trash at all, any other failure to trash a folder is returned instead.
+///
+/// Ignored folders are skipped, see `set_ignored_folders`.
+pub fn delete_addons(addon_folders: &[AddonFolder], to_trash: bool) -> Result<()> {
+    delete_paths(
+        managed_folder_paths(addon_folders, DEFAULT_CASE_INSENSITIVE),
+        to_trash,
+    )
+}
+
+/// Same as `delete_addons` without trash, but folders whose path doesn't exist
+/// are looked up case-insensitively in their parent directory if `case_insensitive` is set.
+pub fn delete_addons_with_case(
+    addon_folders: &[AddonFolder],
+    case_insensitive: bool,
+) -> Result<()> {
+    delete_paths(managed_folder_paths(addon_folders, case_insensitive), false)
+}
+
+/// Deletes all `paths`, or moves them to the trash if `to_trash` is set.
+fn delete_paths(paths: Vec<PathBuf>, to_trash: bool) -> Result<()> {
+    let trash_available = to_trash && has_trash();
+
+    for path in paths {
+        if trash_available {
+            if let Err(source) = trash::delete(&path) {
+                return Err(FilesystemError::Trash { path, source });
+            }
+            continue;
+        }
+
+        if to_trash {
+            log::warn!(
+                "no trash available to move {:?} to, deleting it instead",
+                &path
+            );
+        }
+
+        remove_dir_all(path)?;
+    }
+
+    Ok(())
+}
+
+/// Programs one of which is used to move files to the trash on Linux and the BSDs.
+const TRASH_PROGRAMS: &[&str] = &["gio", "kioclient5", "kioclient"];
+
+/// Returns `true` if the system has a trash to move files to. On Linux and the
+/// BSDs the trash is used through a program like `gio`, which may not be
+/// installed. Windows and macOS always have a trash.
+fn has_trash() -> bool {
+    if cfg!(all(unix, not(target_os = "macos"))) {
+        std::env::var_os("PATH")
+            .and_then(|path| find_trash_program(&path))
+            .is_some()
+    } else {
+        true
+    }
+}
+
+/// Returns the path of the first of `TRASH_PROGRAMS` found in the directories
+/// of the `PATH` like list `path`.
+fn find_trash_program(path: &std::ffi::OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .flat_map(|dir| TRASH_PROGRAMS.iter().map(move |program| dir.join(program)))
+        .find(|program| program.is_file())
+}
+
+/// Uninstalls `addon` by deleting all of its folders and, if `wtf_path` is given,
+/// all of its saved variables in it.
+///
+/// Saved variables are removed even if deleting a folder failed. Failures of both
+/// steps are collected into a single `FilesystemError::Uninstall`.
+pub fn uninstall_addon(addon: &Addon, wtf_path: Option<&Path>) -> Result<()> {
+    let mut errors = vec![];
+
+    if let Err(e) = delete_addons(&addon.folders, false) {
+        errors.push(format!("deleting folders failed ({})", e));
+    }
+
+    if let Some(wtf_path) = wtf_path {
+        if let Err(e) = delete_saved_variables(&addon.folders, wtf_path, None, None) {
+            errors.push(format!("deleting saved variables failed ({})", e));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(FilesystemError::Uninstall {
+            id: addon.primary_folder_id.clone(),
+            errors,
+        })
+    }
+}
+
+/// Uninstalls all `addons` like `uninstall_addon`, but the WTF folder is only
+/// walked once for the saved variables of all addons combined.
+///
+/// Failures of every addon are collected into a single `FilesystemError::Uninstall`.
+pub fn uninstall_addons(addons: &[Addon], wtf_path: Option<&Path>) -> Result<()> {
+    let mut errors = vec![];
+
+    for addon in addons {
+        if let Err(e) = delete_addons(&addon.folders, false) {
+            errors.push(format!(
+                "deleting folders of {} failed ({})",
+                addon.primary_folder_id, e
+            ));
+        }
+    }
+
+    if let Some(wtf_path) = wtf_path {
+        let folders = addons
+            .iter()
+            .flat_map(|addon| addon.folders.iter().cloned())
+            .collect::<Vec<_>>();
+
+        if let Err(e) = delete_saved_variables(&folders, wtf_path, None, None) {
+            errors.push(format!("deleting saved variables failed ({})", e));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(FilesystemError::Uninstall {
+            id: addons
+                .iter()
+                .map(|addon| addon.primary_folder_id.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            errors,
+        })
+    }
+}
+
+/// Returns every existing path `delete_addons` would remove for `[AddonFolder]`,
+/// without touching the disk.
+pub fn delete_addons_dry_run(addon_folders: &[AddonFolder]) -> Result<Vec<PathBuf>> {
+    Ok(managed_folder_paths(
+        addon_folders,
+        DEFAULT_CASE_INSENSITIVE,
+    ))
+}
+
+/// Same as `existing_folder_paths`, but without the paths of ignored folders.
+fn managed_folder_paths(addon_folders: &[AddonFolder], case_insensitive: bool) -> Vec<PathBuf> {
+    existing_folder_paths(addon_folders, case_insensitive)
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| !is_folder_ignored(name))
+                .unwrap_or(true)
+        })
+        .collect()
+}
+
+/// Returns the deduplicated, existing paths of `[AddonFolder]`.
+fn existing_folder_paths(addon_folders: &[AddonFolder], case_insensitive: bool) -> Vec<PathBuf> {
+    let mut paths = vec![];
+
     for folder in addon_folders {
-        let path = &folder.path;
-        if path.exists() {
-            remove_dir_all(path)?;
+        if let Some(path) = find_existing_path(&folder.path, case_insensitive) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+
+    paths
+}
+
+/// Returns `path` if it exists. Otherwise, if `case_insensitive` is set, returns
+/// the entry of its parent directory with the same name ignoring case.
+fn find_existing_path(path: &Path, case_insensitive: bool) -> Option<PathBuf> {
+    if path.exists() {
+        return Some(path.to_path_buf());
+    }
+
+    if !case_insensitive {
+        return None;
+    }
+
+    let name = path.file_name()?.to_str()?;
+
+    path.parent()?
+        .read_dir()
+        .ok()?
+        .filter_map(std::result::Result::ok)
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(name)
+        })
+        .map(|entry| entry.path())
+}
+
+/// Returns the total on-disk size in bytes of all existing folders of `addon`.
+/// Files which can't be read are skipped.
+pub fn addon_size(addon: &Addon) -> Result<u64> {
+    let mut size = 0;
+
+    for path in existing_folder_paths(&addon.folders, DEFAULT_CASE_INSENSITIVE) {
+        size += WalkDir::new(path)
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum::<u64>();
+    }
+
+    Ok(size)
+}
+
+/// Archives all folders of `addon` into `<backup_dir>/<addon_id>-<timestamp>.zip`,
+/// so it can be restored with `restore_addon_backup` if an update goes wrong.
+///
+/// Folders which no longer exist on disk are skipped. Returns the archive path.
+pub fn backup_addon(addon: &Addon, backup_dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(backup_dir)?;
+
+    let archive_path = backup_dir.join(format!(
+        "{}-{}.zip",
+        addon.primary_folder_id,
+        Local::now().format("%Y-%m-%d_%H-%M-%S")
+    ));
+
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive_path)?);
+    let options = zip::write::FileOptions::default();
+
+    for path in existing_folder_paths(&addon.folders, DEFAULT_CASE_INSENSITIVE) {
+        let root = path.parent().unwrap_or(&path);
+
+        for entry in WalkDir::new(&path) {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+
+            // Archives always use `/`, regardless of the platform.
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            if entry.file_type().is_dir() {
+                writer.add_directory(name, options)?;
+            } else if entry.file_type().is_file() {
+                writer.start_file(name, options)?;
+                std::io::copy(&mut std::fs::File::open(entry.path())?, &mut writer)?;
+            }
+        }
+    }
+
+    writer.finish()?;
+
+    log::debug!(
+        "{} - backed up folders to {:?}",
+        addon.primary_folder_id,
+        &archive_path
+    );
+
+    Ok(archive_path)
+}
+
+/// Restores a backup created by `backup_addon` by installing `archive_path`
+/// into `to_directory`, replacing the current folders of `addon`.
+///
+/// The backup archive itself is kept.
+pub async fn restore_addon_backup(
+    addon: &Addon,
+    archive_path: &Path,
+    to_directory: &Path,
+) -> Result<Vec<AddonFolder>> {
+    // `install_addon` expects the archive to be named after the addon.
+    let from_directory = archive_path.parent().unwrap_or_else(|| Path::new("."));
+    std::fs::copy(archive_path, from_directory.join(&addon.primary_folder_id))?;
+
+    let options = InstallOptions {
+        force: true,
+        ..Default::default()
+    };
+
+    install_addon_with_options(addon, from_directory, to_directory, &options, |_, _| {}).await
+}
+
+/// Deletes all but the newest `keep` backups of each addon in `backup_dir`, as
+/// created by `backup_addon`. Returns the deleted archives, sorted by path.
+///
+/// Backups are ordered by the timestamp in their file name rather than their
+/// modification time, which copying them around would change. Files which
+/// aren't named like a backup are left alone.
+pub fn prune_backups(backup_dir: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+    let mut backups: HashMap<String, Vec<(NaiveDateTime, PathBuf)>> = HashMap::new();
+
+    if !backup_dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    for entry in backup_dir.read_dir()?.filter_map(std::result::Result::ok) {
+        let path = entry.path();
+
+        if path.extension().and_then(|ext| ext.to_str()) != Some("zip") || !path.is_file() {
+            continue;
+        }
+
+        if let Some((id, timestamp)) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(parse_backup_name)
+        {
+            backups.entry(id).or_default().push((timestamp, path));
+        }
+    }
+
+    let mut pruned = vec![];
+
+    for mut archives in backups.into_values() {
+        archives.sort_by(|a, b| b.0.cmp(&a.0));
+
+        for (_, path) in archives.into_iter().skip(keep) {
+            std::fs::remove_file(&path)?;
+            pruned.push(path);
+        }
+    }
+
+    pruned.sort();
+
+    log::debug!("pruned {} addon backups in {:?}", pruned.len(), backup_dir);
+
+    Ok(pruned)
+}
+
+/// Splits the file stem of a backup, `<addon_id>-<timestamp>`, into its addon id
+/// and timestamp.
+fn parse_backup_name(stem: &str) -> Option<(String, NaiveDateTime)> {
+    const TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+    // Eg. `2021-01-31_12-00-00`.
+    const TIMESTAMP_LEN: usize = 19;
+
+    let split = stem.len().checked_sub(TIMESTAMP_LEN + 1)?;
+    let id = stem.get(..split).filter(|id| !id.is_empty())?;
+    let timestamp = stem.get(split..)?.strip_prefix('-')?;
+
+    NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|timestamp| (id.to_string(), timestamp))
+}
+
+/// Returns the `## Version:` of the addon folder at `addon_folder`, read from its
+/// `.toc` file on disk rather than any cached `Addon` state.
+///
+/// Returns `None` if the folder has no `.toc` file or it has no version.
+pub fn installed_version(addon_folder: &Path) -> Result<Option<String>> {
+    if !addon_folder.is_dir() {
+        return Err(FilesystemError::FileDoesntExist {
+            path: addon_folder.to_path_buf(),
+        });
+    }
+
+    let id = addon_folder
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    Ok(primary_toc_path(addon_folder, id)
+        .and_then(|toc_path| parse_toc_path(&toc_path))
+        .and_then(|addon_folder| addon_folder.version))
+}
+
+/// Returns `true` if any folder of `addon` lost its `.toc` file, eg. after a
+/// partial install or files being quarantined, or `scan_integrity` finds a
+/// truncated Lua file. The game no longer loads such folders, so the addon
+/// should be installed again.
+pub fn needs_repair(addon: &Addon) -> Result<bool> {
+    for folder in addon.folders.iter() {
+        if primary_toc_path(&folder.path, &folder.id).is_none() {
+            log::debug!(
+                "{} - folder {:?} is missing its .toc file",
+                addon.primary_folder_id,
+                &folder.path
+            );
+            return Ok(true);
+        }
+    }
+
+    let issues = scan_integrity(addon)?;
+    for issue in issues.iter() {
+        log::debug!("{} - {}", addon.primary_folder_id, issue);
+    }
+
+    Ok(!issues.is_empty())
+}
+
+/// A Lua file of an addon which looks truncated, found by `scan_integrity`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityIssue {
+    /// The file is empty.
+    Empty { path: PathBuf },
+    /// The file opens more blocks than it closes, eg. a `function` without `end`.
+    UnclosedBlocks { path: PathBuf, count: usize },
+}
+
+impl std::fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntegrityIssue::Empty { path } => write!(f, "{:?} is empty", path),
+            IntegrityIssue::UnclosedBlocks { path, count } => {
+                write!(f, "{:?} has {} unclosed blocks", path, count)
+            }
+        }
+    }
+}
+
+/// Checks every Lua file of `addon` for truncation, as left behind by an
+/// interrupted download or extraction.
+///
+/// This is a coarse check. Files are flagged if they are empty, or if they have
+/// more `function`, `do`, `if` and `repeat` keywords than `end` and `until`,
+/// ignoring those in comments and strings.
+pub fn scan_integrity(addon: &Addon) -> Result<Vec<IntegrityIssue>> {
+    let mut issues = vec![];
+
+    for folder in addon.folders.iter() {
+        for entry in WalkDir::new(&folder.path)
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+        {
+            let is_lua = entry
+                .path()
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| ext.eq_ignore_ascii_case("lua"))
+                .unwrap_or_default();
+
+            if !is_lua {
+                continue;
+            }
+
+            let path = entry.into_path();
+            let source = std::fs::read(&path)?;
+
+            if source.is_empty() {
+                issues.push(IntegrityIssue::Empty { path });
+                continue;
+            }
+
+            let count = unclosed_lua_blocks(&source);
+            if count > 0 {
+                issues.push(IntegrityIssue::UnclosedBlocks { path, count });
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Returns how many more blocks `source` opens than it closes.
+fn unclosed_lua_blocks(source: &[u8]) -> usize {
+    let mut depth: isize = 0;
+    let mut i = 0;
+
+    while i < source.len() {
+        match source[i] {
+            b'-' if source.get(i + 1) == Some(&b'-') => {
+                i += 2;
+
+                match lua_long_bracket_end(source, i) {
+                    Some(end) => i = end,
+                    None => {
+                        while i < source.len() && source[i] != b'\n' {
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            b'[' => match lua_long_bracket_end(source, i) {
+                Some(end) => i = end,
+                None => i += 1,
+            },
+            b'"' | b'\'' => {
+                let quote = source[i];
+                i += 1;
+
+                while i < source.len() && source[i] != quote && source[i] != b'\n' {
+                    if source[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+
+                i += 1;
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                let start = i;
+
+                while i < source.len() && (source[i].is_ascii_alphanumeric() || source[i] == b'_') {
+                    i += 1;
+                }
+
+                match &source[start..i] {
+                    b"function" | b"do" | b"if" | b"repeat" => depth += 1,
+                    b"end" | b"until" => depth -= 1,
+                    _ => {}
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    depth.max(0) as usize
+}
+
+/// If a Lua long bracket like `[[` or `[==[` starts at `start`, returns the
+/// index after its matching closing bracket, or the end of `source` if it's
+/// never closed.
+fn lua_long_bracket_end(source: &[u8], start: usize) -> Option<usize> {
+    if source.get(start) != Some(&b'[') {
+        return None;
+    }
+
+    let level = source[start + 1..]
+        .iter()
+        .take_while(|c| **c == b'=')
+        .count();
+
+    if source.get(start + 1 + level) != Some(&b'[') {
+        return None;
+    }
+
+    let mut close = vec![b']'];
+    close.extend(std::iter::repeat(b'=').take(level));
+    close.push(b']');
+
+    let body = start + level + 2;
+    let end = source[body..]
+        .windows(close.len())
+        .position(|window| window == &close[..])
+        .map(|position| body + position + close.len())
+        .unwrap_or(source.len());
+
+    Some(end)
+}
+
+/// Confirms every folder returned by `install_addon` exists on disk and has its
+/// `.toc` file, catching archives whose `.toc` paths and extracted paths disagree.
+///
+/// Returns `FilesystemError::FileDoesntExist` for a missing folder and
+/// `FilesystemError::MissingToc` for a folder without `.toc` file.
+pub fn verify_install(folders: &[AddonFolder]) -> Result<()> {
+    for folder in folders {
+        if !folder.path.is_dir() {
+            return Err(FilesystemError::FileDoesntExist {
+                path: folder.path.clone(),
+            });
+        }
+
+        if primary_toc_path(&folder.path, &folder.id).is_none() {
+            return Err(FilesystemError::MissingToc {
+                id: folder.id.clone(),
+                path: folder.path.clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns all top level folders in `addon_dir` which have a `.toc` file but
+/// aren't part of `known`. Folders without a `.toc` file are libraries and are
+/// returned by `find_orphaned_libraries` instead.
+pub fn find_orphaned_folders(addon_dir: &Path, known: &[AddonFolder]) -> Result<Vec<AddonFolder>> {
+    let mut orphans: Vec<_> = untracked_folders(addon_dir, known)?
+        .into_iter()
+        .filter_map(|(_, toc_path)| toc_path)
+        .filter_map(|toc_path| parse_toc_path(&toc_path))
+        .collect();
+    orphans.sort();
+
+    Ok(orphans)
+}
+
+/// Returns all top level folders in `addon_dir` which have no `.toc` file, such
+/// as pure Lua libraries, and aren't part of `known`.
+pub fn find_orphaned_libraries(addon_dir: &Path, known: &[AddonFolder]) -> Result<Vec<PathBuf>> {
+    let mut orphans: Vec<_> = untracked_folders(addon_dir, known)?
+        .into_iter()
+        .filter(|(_, toc_path)| toc_path.is_none())
+        .map(|(path, _)| path)
+        .collect();
+    orphans.sort();
+
+    Ok(orphans)
+}
+
+/// Returns all top level folders in `addon_dir` not part of `known`, together
+/// with the `.toc` file of each folder, if it has one. Ignored folders are
+/// never returned.
+fn untracked_folders(
+    addon_dir: &Path,
+    known: &[AddonFolder],
+) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    let mut folders = vec![];
+
+    for entry in addon_dir.read_dir()?.filter_map(std::result::Result::ok) {
+        let path = entry.path();
+        let id = match entry.file_name().to_str() {
+            Some(id) => id.to_string(),
+            None => continue,
+        };
+
+        // Hidden folders, e.g. staging directories, aren't addons.
+        if !path.is_dir()
+            || id.starts_with('.')
+            || known.iter().any(|f| f.id == id)
+            || is_folder_ignored(&id)
+        {
+            continue;
+        }
+
+        let toc_path = primary_toc_path(&path, &id);
+        folders.push((path, toc_path));
+    }
+
+    Ok(folders)
+}
+
+/// Returns the `.toc` file describing the addon folder `id` at `path`, which
+/// doesn't have to be named after the folder, eg. `BigFolderName/Short.toc`.
+///
+/// The first of these is picked:
+/// 1. `<id>.toc`.
+/// 2. The only `.toc` file in the folder.
+/// 3. A flavor suffixed one, like `<id>_Mainline.toc`.
+/// 4. The first `.toc` file in the folder, by name.
+pub(crate) fn primary_toc_path(path: &Path, id: &str) -> Option<PathBuf> {
+    let toc_path = path.join(format!("{}.toc", id));
+
+    if toc_path.is_file() {
+        return Some(toc_path);
+    }
+
+    let mut tocs = find_toc_files(path).ok()?;
+    tocs.sort();
+
+    if tocs.len() == 1 {
+        return tocs.pop();
+    }
+
+    let suffixed = tocs.iter().position(|toc| {
+        toc.file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(|stem| strip_toc_flavor_suffix(stem) == id)
+            .unwrap_or_default()
+    });
+
+    match suffixed {
+        Some(idx) => Some(tocs.swap_remove(idx)),
+        None => tocs.into_iter().next(),
+    }
+}
+
+/// Name of the folder inside the WTF folder saved variables are backed up to.
+const SAVED_VARIABLES_BACKUP_DIR: &str = "_ajour_sv_backup";
+
+/// Number of walked entries between two progress reports while scanning for
+/// saved variables.
+const SAVED_VARIABLES_PROGRESS_INTERVAL: usize = 500;
+
+/// Deletes all saved varaible files correlating to `[AddonFolder]`.
+///
+/// Deletion can be scoped to a single `account` and / or `character`. Character
+/// scoped files are never removed when only an `account` is given, unless they
+/// belong to a character of that account.
+pub fn delete_saved_variables(
+    addon_folders: &[AddonFolder],
+    wtf_path: &Path,
+    account: Option<&str>,
+    character: Option<&str>,
+) -> Result<()> {
+    delete_saved_variables_with_progress(
+        addon_folders,
+        wtf_path,
+        account,
+        character,
+        |_| {},
+        &AtomicBool::new(false),
+    )
+}
+
+/// Same as `delete_saved_variables`, but reports the number of scanned entries
+/// to `progress` while walking the WTF folder, and stops as soon as `cancel` is set.
+///
+/// Files deleted before the cancellation are not restored, and
+/// `FilesystemError::Cancelled` is returned.
+pub fn delete_saved_variables_with_progress(
+    addon_folders: &[AddonFolder],
+    wtf_path: &Path,
+    account: Option<&str>,
+    character: Option<&str>,
+    mut progress: impl FnMut(usize),
+    cancel: &AtomicBool,
+) -> Result<()> {
+    let files = scan_saved_variables_files(addon_folders, wtf_path, &mut progress, cancel)?;
+
+    for path in files {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(FilesystemError::Cancelled);
+        }
+
+        let (file_account, file_character) = saved_variables_scope(&path);
+
+        if account.is_some() && account != file_account {
+            continue;
+        }
+        if character.is_some() && character != file_character {
+            continue;
         }
+
+        remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Returns all saved variable files correlating to `[AddonFolder]`, matched the
+/// same way as `delete_saved_variables`, without touching them.
+///
+/// Paths are sorted by account and character, so files belonging together are
+/// next to each other.
+pub fn find_saved_variables(
+    addon_folders: &[AddonFolder],
+    wtf_path: &Path,
+) -> Result<Vec<PathBuf>> {
+    let mut files = saved_variables_files(addon_folders, wtf_path);
+
+    files.sort_by(|a, b| {
+        saved_variables_scope(a)
+            .cmp(&saved_variables_scope(b))
+            .then_with(|| a.cmp(b))
+    });
+
+    Ok(files)
+}
+
+/// Returns all `.lua.bak` saved variable files in `wtf_path` whose `.lua` file
+/// exists and is newer, so they can be removed to reclaim space.
+pub fn find_stale_sv_backups(wtf_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+
+    for entry in WalkDir::new(wtf_path)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != SAVED_VARIABLES_BACKUP_DIR)
+        .filter_map(std::result::Result::ok)
+    {
+        let path = entry.path();
+        let parent_name = path
+            .parent()
+            .and_then(|a| a.file_name())
+            .and_then(|a| a.to_str());
+        let file_name = path.file_name().and_then(|a| a.to_str());
+
+        if parent_name != Some("SavedVariables") {
+            continue;
+        }
+
+        if let Some(lua_name) = file_name.and_then(|a| a.strip_suffix(".bak")) {
+            if !lua_name.ends_with(".lua") {
+                continue;
+            }
+
+            let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+            let lua_path = path.with_file_name(lua_name);
+
+            if let (Some(bak), Some(lua)) = (modified(path), modified(&lua_path)) {
+                if lua > bak {
+                    files.push(path.to_path_buf());
+                }
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Moves all saved variable files correlating to `[AddonFolder]` into a
+/// timestamped folder inside `<wtf_path>/_ajour_sv_backup/`, instead of deleting them.
+///
+/// Returns the path of the created backup folder.
+pub fn backup_saved_variables(addon_folders: &[AddonFolder], wtf_path: &Path) -> Result<PathBuf> {
+    let backup_path = wtf_path
+        .join(SAVED_VARIABLES_BACKUP_DIR)
+        .join(Local::now().format("%Y-%m-%d_%H-%M-%S").to_string());
+
+    for path in saved_variables_files(addon_folders, wtf_path) {
+        // Keep the account and character structure, so files can be restored.
+        let relative = path.strip_prefix(wtf_path).unwrap_or(&path);
+        let to = backup_path.join(relative);
+
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        crate::utility::rename(&path, &to)?;
+    }
+
+    Ok(backup_path)
+}
+
+/// Copies all saved variable files correlating to `[AddonFolder]` from a
+/// `backup_path` created by `backup_saved_variables` back into `wtf_path`.
+///
+/// Files which already exist in `wtf_path` are only replaced if `overwrite` is set,
+/// to avoid clobbering newer config.
+pub fn restore_saved_variables(
+    addon_folders: &[AddonFolder],
+    backup_path: &Path,
+    wtf_path: &Path,
+    overwrite: bool,
+) -> Result<()> {
+    for path in saved_variables_files(addon_folders, backup_path) {
+        let relative = path.strip_prefix(backup_path).unwrap_or(&path);
+        let to = wtf_path.join(relative);
+
+        if to.exists() && !overwrite {
+            continue;
+        }
+
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::copy(&path, &to)?;
+    }
+
+    Ok(())
+}
+
+/// Returns all saved variable files inside `root` correlating to `[AddonFolder]`.
+fn saved_variables_files(addon_folders: &[AddonFolder], root: &Path) -> Vec<PathBuf> {
+    scan_saved_variables_files(addon_folders, root, &mut |_| {}, &AtomicBool::new(false))
+        .unwrap_or_default()
+}
+
+/// Walks `root` for saved variable files correlating to `[AddonFolder]`, calling
+/// `progress` with the number of scanned entries every
+/// `SAVED_VARIABLES_PROGRESS_INTERVAL` entries and once when done.
+///
+/// Returns `FilesystemError::Cancelled` as soon as `cancel` is set.
+fn scan_saved_variables_files(
+    addon_folders: &[AddonFolder],
+    root: &Path,
+    progress: &mut impl FnMut(usize),
+    cancel: &AtomicBool,
+) -> Result<Vec<PathBuf>> {
+    let ids = addon_folders
+        .iter()
+        .map(|folder| folder.id.as_str())
+        .collect::<HashSet<_>>();
+    let mut files = vec![];
+    let mut scanned = 0;
+
+    for entry in WalkDir::new(&root)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != SAVED_VARIABLES_BACKUP_DIR)
+        .filter_map(std::result::Result::ok)
+    {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(FilesystemError::Cancelled);
+        }
+
+        scanned += 1;
+        if scanned % SAVED_VARIABLES_PROGRESS_INTERVAL == 0 {
+            progress(scanned);
+        }
+
+        let path = entry.path();
+        let parent_name = path
+            .parent()
+            .and_then(|a| a.file_name())
+            .and_then(|a| a.to_str());
+
+        if parent_name == Some("SavedVariables") {
+            let file_name = path
+                .file_name()
+                .and_then(|a| a.to_str())
+                .map(|a| a.trim_end_matches(".bak").trim_end_matches(".lua"));
+
+            // NOTE: Will reject "Foobar_<invalid utf8>".
+            if let Some(file_name_str) = file_name {
+                if ids.contains(file_name_str) {
+                    files.push(path.to_path_buf());
+                }
+            }
+        }
+    }
+
+    progress(scanned);
+
+    Ok(files)
+}
+
+/// Derives the account and character a saved variable file belongs to from the
+/// path components above its `SavedVariables` folder.
+///
+/// Account scoped files live in `Account/<account>/SavedVariables`, character
+/// scoped files live in `Account/<account>/<realm>/<character>/SavedVariables`.
+fn saved_variables_scope(path: &Path) -> (Option<&str>, Option<&str>) {
+    fn file_name(path: Option<&Path>) -> Option<&str> {
+        path.and_then(Path::file_name).and_then(|a| a.to_str())
+    }
+
+    let owner = path.parent().and_then(Path::parent);
+    let owner_parent = owner.and_then(Path::parent);
+
+    if file_name(owner_parent) == Some("Account") {
+        (file_name(owner), None)
+    } else {
+        let account = owner_parent.and_then(Path::parent);
+        (file_name(account), file_name(owner))
+    }
+}
+
+/// Prefix of the directory an archive is extracted into before its folders
+/// are moved into the addon directory.
+const STAGING_PREFIX: &str = ".ajour-staging-";
+
+/// Removes the staging directories an install interrupted by a crash left behind
+/// in `to_directory`, and returns their paths. Must only run while no install
+/// is in progress, eg. at startup.
+///
+/// An existing folder which was renamed aside to be replaced, but whose
+/// replacement never got moved in, is renamed back instead, so the previous
+/// version of the addon is kept.
+pub fn cleanup_stale_staging(to_directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut stale = vec![];
+
+    if !to_directory.is_dir() {
+        return Ok(stale);
+    }
+
+    for entry in to_directory.read_dir()?.filter_map(std::result::Result::ok) {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        let suffix = match name.strip_prefix(STAGING_PREFIX) {
+            Some(suffix) => suffix,
+            None => continue,
+        };
+
+        let original = suffix
+            .strip_prefix("old-")
+            .filter(|original| !original.is_empty())
+            .map(|original| to_directory.join(original));
+
+        match original {
+            Some(original) if path.is_dir() && !original.exists() => {
+                log::debug!("restoring folder {:?} from {:?}", &original, &path);
+                crate::utility::rename(&path, &original)?;
+            }
+            _ if path.is_dir() => remove_dir_all(&path)?,
+            _ => remove_file(&path)?,
+        }
+
+        stale.push(path);
+    }
+
+    stale.sort();
+
+    Ok(stale)
+}
+
+/// Unzips an `Addon` archive, and once that is done, it moves the content
+/// to the `to_directory`.
+///
+/// The archive is first extracted into a staging directory inside `to_directory`.
+/// Existing addon folders are only replaced once extraction has fully succeeded,
+/// so a failed install leaves the previous version untouched. Each folder is then
+/// swapped in with renames, so it's only missing for a moment.
+///
+/// Symlink entries are never created. They are logged and skipped, so a hostile
+/// archive can't use them to write outside of the addon directory.
+///
+/// At the end it will cleanup and remove the archive, use `install_addon_with_options`
+/// to keep it.
+pub async fn install_addon(
+    addon: &Addon,
+    from_directory: &Path,
+    to_directory: &Path,
+) -> Result<Vec<AddonFolder>> {
+    install_addon_with_progress(addon, from_directory, to_directory, |_, _| {}).await
+}
+
+/// Same as `install_addon`, but calls `progress` with `(bytes_done, total_bytes)`
+/// while the archive is being extracted.
+pub async fn install_addon_with_progress(
+    addon: &Addon,
+    from_directory: &Path,
+    to_directory: &Path,
+    progress: impl FnMut(u64, u64),
+) -> Result<Vec<AddonFolder>> {
+    install_addon_with_options(
+        addon,
+        from_directory,
+        to_directory,
+        &InstallOptions::default(),
+        progress,
+    )
+    .await
+}
+
+/// Installs the local archive at `path` into `to_directory`, eg. a manually
+/// downloaded beta of an addon.
+///
+/// The archive is extracted just like with `install_addon`, but is left where
+/// it is. The returned folders are parsed from their `.toc` files, so they can
+/// be matched against a repository to track updates like any other addon.
+pub async fn install_local_archive(path: &Path, to_directory: &Path) -> Result<Vec<AddonFolder>> {
+    let (from_directory, file_name) = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) if path.is_file() => (parent, file_name),
+        _ => {
+            return Err(FilesystemError::FileDoesntExist {
+                path: path.to_path_buf(),
+            })
+        }
+    };
+
+    let addon = Addon::empty(&file_name.to_string_lossy());
+    let options = InstallOptions {
+        cleanup: false,
+        ..Default::default()
+    };
+
+    install_addon_with_options(&addon, from_directory, to_directory, &options, |_, _| {}).await
+}
+
+/// Options which alter how `install_addon_with_options` installs an addon.
+#[derive(Debug, Clone)]
+pub struct InstallOptions {
+    /// Remove the archive once the addon has been installed. Defaults to `true`.
+    pub cleanup: bool,
+    /// Install the addon even if it's pinned to a version. Defaults to `false`.
+    pub force: bool,
+    /// Only install these top level folders of the archive. Existing folders
+    /// not in the list are left untouched. Defaults to `None`, installing all.
+    pub folders: Option<Vec<String>>,
+    /// Flavor of the game directory being installed into. If set, archives
+    /// built only for another flavor are refused. Defaults to `None`.
+    pub flavor: Option<Flavor>,
+    /// Merge the archive into the existing folders instead of replacing them.
+    /// Files in the archive overwrite existing ones, all other existing files
+    /// are kept. Defaults to `false`.
+    ///
+    /// Only use this for addons which expect user files inside their folders,
+    /// like profiles. Files removed or renamed by the new version are left
+    /// behind and can break the addon.
+    pub merge: bool,
+    /// Write a `Provenance` file into the primary folder, recording where the
+    /// addon was installed from. Defaults to `false`.
+    pub provenance: bool,
+    /// Find existing folders to replace case-insensitively, so `myaddon` on
+    /// disk is replaced when installing `MyAddon`. Defaults to
+    /// `DEFAULT_CASE_INSENSITIVE`.
+    pub case_insensitive: bool,
+    /// All installed addons. If set, existing folders the new version no longer
+    /// has are kept if another of these addons still claims them, like a shared
+    /// `Libs` folder. Defaults to `None`.
+    pub installed_addons: Option<Vec<Addon>>,
+    /// Aborts the install with `FilesystemError::Cancelled` once set. Existing
+    /// folders are left untouched, unless the extracted folders were already
+    /// being moved into place. Defaults to `None`.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Directory the archive is extracted into before its folders are moved
+    /// into the addon directory, eg. on faster local storage. Folders are copied
+    /// if it's on another filesystem. Defaults to `None`, extracting inside the
+    /// addon directory.
+    pub staging_directory: Option<PathBuf>,
+    /// Also extract inner `.zip` archives, if the archive or one of its top
+    /// level folders contains nothing but one. Defaults to `false`.
+    pub nested_archives: bool,
+    /// Skip top level folders of the archive built for a flavor other than
+    /// `flavor`, like `Foo_Classic` next to `Foo`. Everything is installed if
+    /// no folder would be left. Does nothing without `flavor`. Defaults to `false`.
+    pub flavor_folders_only: bool,
+    /// Glob patterns, relative to each addon folder like `**/*.user.lua`, of
+    /// existing files to keep when replacing the folder. Matching files are
+    /// carried over unless the archive provides them. Not needed with `merge`,
+    /// which keeps all existing files. Defaults to none.
+    pub preserve: Vec<String>,
+    /// Write a manifest with the hash of every installed file into the primary
+    /// folder, see `verify_against_manifest`. Defaults to `false`.
+    pub manifest: bool,
+    /// Directory existing folders which don't belong to the addon are moved
+    /// into when the archive replaces them, instead of deleting them, see
+    /// `read_quarantine`. The addon's own folders are still replaced. Defaults
+    /// to `None`, deleting them.
+    pub quarantine: Option<PathBuf>,
+}
+
+impl Default for InstallOptions {
+    fn default() -> Self {
+        InstallOptions {
+            cleanup: true,
+            force: false,
+            folders: None,
+            flavor: None,
+            merge: false,
+            provenance: false,
+            case_insensitive: DEFAULT_CASE_INSENSITIVE,
+            installed_addons: None,
+            cancel: None,
+            staging_directory: None,
+            nested_archives: false,
+            flavor_folders_only: false,
+            preserve: vec![],
+            manifest: false,
+            quarantine: None,
+        }
+    }
+}
+
+/// Same as `install_addon_with_progress`, but with `options` controlling how
+/// the addon is installed.
+pub async fn install_addon_with_options(
+    addon: &Addon,
+    from_directory: &Path,
+    to_directory: &Path,
+    options: &InstallOptions,
+    progress: impl FnMut(u64, u64),
+) -> Result<Vec<AddonFolder>> {
+    install(
+        addon,
+        from_directory,
+        to_directory,
+        options,
+        progress,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Same as `install_addon_with_options`, but archive entries which can't be
+/// written don't stop the install. The rest of the archive is still extracted
+/// and installed, and the failed entries are returned with their error
+/// alongside the installed folders.
+///
+/// Use this to diagnose partially extractable archives, the caller decides if
+/// the partial install is acceptable.
+pub async fn install_addon_collecting_errors(
+    addon: &Addon,
+    from_directory: &Path,
+    to_directory: &Path,
+    options: &InstallOptions,
+) -> Result<(Vec<AddonFolder>, Vec<(PathBuf, std::io::Error)>)> {
+    let mut failures = vec![];
+
+    let folders = install(
+        addon,
+        from_directory,
+        to_directory,
+        options,
+        |_, _| {},
+        Some(&mut failures),
+        None,
+    )
+    .await?;
+
+    Ok((folders, failures))
+}
+
+/// What an install put into the addon directory, see `install_addon_with_stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstallStats {
+    pub files: usize,
+    /// Folders created, including the top level addon folders.
+    pub folders: usize,
+    pub total_bytes: u64,
+    /// Path of the largest file relative to the addon directory, eg.
+    /// `Foo/Media/Font.ttf`, and its size.
+    pub largest_file: Option<(PathBuf, u64)>,
+}
+
+/// Same as `install_addon_with_options`, but also returns `InstallStats` of the
+/// installed folders.
+///
+/// Next to showing them after an install, they help spotting broken archives,
+/// eg. an install with hardly any bytes is likely not the real addon.
+pub async fn install_addon_with_stats(
+    addon: &Addon,
+    from_directory: &Path,
+    to_directory: &Path,
+    options: &InstallOptions,
+) -> Result<(Vec<AddonFolder>, InstallStats)> {
+    let mut stats = InstallStats::default();
+
+    let folders = install(
+        addon,
+        from_directory,
+        to_directory,
+        options,
+        |_, _| {},
+        None,
+        Some(&mut stats),
+    )
+    .await?;
+
+    Ok((folders, stats))
+}
+
+/// Installs `addon`, see `install_addon_with_options`. If `failures` is given,
+/// entries which can't be written are collected into it instead of failing. If
+/// `stats` is given, it's set to the `InstallStats` of the installed folders.
+async fn install(
+    addon: &Addon,
+    from_directory: &Path,
+    to_directory: &Path,
+    options: &InstallOptions,
+    mut progress: impl FnMut(u64, u64),
+    mut failures: Option<&mut Vec<(PathBuf, std::io::Error)>>,
+    stats: Option<&mut InstallStats>,
+) -> Result<Vec<AddonFolder>> {
+    if let Some(version) = &addon.pinned_version {
+        if !options.force {
+            return Err(FilesystemError::AddonPinned {
+                id: addon.primary_folder_id.clone(),
+                version: version.clone(),
+            });
+        }
+    }
+
+    let id = &addon.primary_folder_id;
+    let archive_path = from_directory.join(id);
+    let staging_directory = options
+        .staging_directory
+        .as_deref()
+        .unwrap_or(to_directory)
+        .join(format!("{}{}", STAGING_PREFIX, id));
+
+    log::debug!("{} - installing from {:?}", id, &archive_path);
+
+    // Start from a clean staging directory, a previous install might have been interrupted.
+    if staging_directory.exists() {
+        remove_dir_all(&staging_directory)?;
+    }
+
+    let cancel = options.cancel.as_deref();
+    let result = extract_archive(
+        id,
+        &archive_path,
+        &staging_directory,
+        &mut progress,
+        &mut failures,
+        cancel,
+    )
+    .and_then(|files| {
+        log::debug!("{} - extracted {} files", id, files);
+
+        if options.nested_archives {
+            extract_nested_archives(id, &staging_directory, &mut failures, cancel)?;
+        }
+
+        wrap_flat_archive(&staging_directory)
+    })
+    .and_then(|_| match options.flavor {
+        Some(flavor) => check_staged_flavor(id, &staging_directory, flavor),
+        None => Ok(()),
+    })
+    .and_then(|_| match options.flavor {
+        Some(flavor) if options.flavor_folders_only => {
+            remove_staged_foreign_folders(id, &staging_directory, flavor)
+        }
+        _ => Ok(()),
+    })
+    .and_then(|_| check_cancelled(cancel))
+    .and_then(|_| match stats {
+        Some(stats) => {
+            *stats = staged_stats(&staging_directory, options)?;
+            Ok(())
+        }
+        None => Ok(()),
+    })
+    .and_then(|_| move_staged_folders(addon, &staging_directory, to_directory, options));
+
+    // Staging directory is no longer needed, regardless of the result.
+    if staging_directory.exists() {
+        let _ = remove_dir_all(&staging_directory);
+    }
+
+    let new_top_level_folders = match result {
+        Ok(folders) => folders,
+        Err(error) => {
+            log::warn!("{} - install failed: {}", id, error);
+            return Err(error);
+        }
+    };
+
+    // Cleanup
+    if options.cleanup {
+        std::fs::remove_file(&archive_path)?;
+    }
+
+    let installed = |toc_path: &PathBuf| {
+        toc_path
+            .parent()
+            .and_then(Path::file_name)
+            .and_then(|name| name.to_str())
+            .map(|name| new_top_level_folders.iter().any(|folder| folder == name))
+            .unwrap_or_default()
+    };
+    let toc_files: Vec<_> = detect_toc_files(to_directory)
+        .into_iter()
+        .filter(installed)
+        .collect();
+
+    let mut addon_folders: Vec<_> = toc_files.iter().filter_map(|p| parse_toc_path(p)).collect();
+    addon_folders.sort();
+    // Needed since multi-toc can now insert folder name more than once
+    addon_folders.dedup();
+
+    log::debug!(
+        "{} - installed addon folders: {:?}",
+        id,
+        addon_folders.iter().map(|f| &f.id).collect::<Vec<_>>()
+    );
+
+    if options.provenance {
+        write_provenance(addon, &addon_folders, to_directory)?;
+    }
+
+    if options.manifest {
+        write_manifest(addon, &new_top_level_folders, to_directory)?;
+    }
+
+    // Cloned, so the lock isn't held for as long as the command runs.
+    let command = POST_INSTALL_COMMAND
+        .read()
+        .ok()
+        .and_then(|command| command.clone());
+    if let Some(command) = command {
+        run_post_install_command(&command, id, &addon_folders);
+    }
+
+    Ok(addon_folders)
+}
+
+/// Name of the file inside the primary folder of an addon storing its `Manifest`.
+const MANIFEST_FILE: &str = ".ajour-manifest.json";
+
+/// Sha256 hashes of all files of an addon, by path relative to the addon
+/// directory, eg. `Foo/Foo.lua`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    files: BTreeMap<String, String>,
+}
+
+/// Writes the `Manifest` of the installed top level `folders` into the primary
+/// folder of `addon`, or the first folder if it has no primary folder.
+fn write_manifest(addon: &Addon, folders: &[String], to_directory: &Path) -> Result<()> {
+    let mut folders = folders.to_vec();
+    folders.sort();
+
+    let primary = match folders
+        .iter()
+        .find(|f| **f == addon.primary_folder_id)
+        .or_else(|| folders.first())
+    {
+        Some(primary) => to_directory.join(primary),
+        None => return Ok(()),
+    };
+
+    let mut manifest = Manifest::default();
+
+    for folder in folders.iter() {
+        for entry in WalkDir::new(to_directory.join(folder)) {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy();
+
+            if !entry.file_type().is_file() || name == MANIFEST_FILE || name == PROVENANCE_FILE {
+                continue;
+            }
+
+            manifest.files.insert(
+                manifest_key(entry.path(), to_directory)?,
+                hash_file(entry.path(), ChecksumAlgorithm::Sha256)?,
+            );
+        }
+    }
+
+    if primary.is_dir() {
+        let contents = serde_json::to_string_pretty(&manifest)?;
+        std::fs::write(primary.join(MANIFEST_FILE), contents)?;
+    }
+
+    Ok(())
+}
+
+/// Returns `path` relative to `to_directory`, with forward slashes.
+fn manifest_key(path: &Path, to_directory: &Path) -> Result<String> {
+    let relative = path
+        .strip_prefix(to_directory)
+        .map_err(|_| FilesystemError::StripPrefix {
+            prefix: to_directory.to_string_lossy().to_string(),
+            from: path.to_string_lossy().to_string(),
+        })?;
+
+    Ok(relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// Checks the files of `addon` against the manifest written by
+/// `install_addon_with_options` when `InstallOptions::manifest` was set.
+///
+/// Returns the paths of all files which are missing or whose content changed
+/// since the install. Returns `FilesystemError::FileDoesntExist` if the addon
+/// has no manifest.
+pub fn verify_against_manifest(addon: &Addon) -> Result<Vec<PathBuf>> {
+    let mut folders: Vec<_> = addon.folders.iter().collect();
+    folders.sort_by_key(|f| f.id != addon.primary_folder_id);
+
+    let manifest_path = folders
+        .iter()
+        .map(|f| f.path.join(MANIFEST_FILE))
+        .find(|path| path.is_file());
+
+    let manifest_path = match manifest_path {
+        Some(manifest_path) => manifest_path,
+        None => {
+            let folder = folders.first().map(|f| f.path.clone()).unwrap_or_default();
+            return Err(FilesystemError::FileDoesntExist {
+                path: folder.join(MANIFEST_FILE),
+            });
+        }
+    };
+
+    // The manifest lives in a top level folder of the addon directory.
+    let to_directory = manifest_path
+        .parent()
+        .and_then(Path::parent)
+        .unwrap_or_else(|| Path::new(""));
+    let manifest: Manifest = serde_json::from_str(&std::fs::read_to_string(&manifest_path)?)?;
+
+    let mut changed = vec![];
+
+    for (key, hash) in manifest.files {
+        let path = key
+            .split('/')
+            .fold(to_directory.to_path_buf(), |path, c| path.join(c));
+
+        let unchanged = path.is_file() && hash_file(&path, ChecksumAlgorithm::Sha256)? == hash;
+        if !unchanged {
+            changed.push(path);
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Name of the file inside the primary folder of an addon storing its `Provenance`.
+const PROVENANCE_FILE: &str = ".ajour.json";
+
+/// Where an installed addon came from, written into its primary folder by
+/// `install_addon_with_options` when `InstallOptions::provenance` is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub source: RepositoryKind,
+    pub id: String,
+    pub version: Option<String>,
+    pub installed: DateTime<Utc>,
+}
+
+/// Reads the `Provenance` of the addon folder at `folder`.
+///
+/// Returns `None` if the folder has no provenance file.
+pub fn read_provenance(folder: &Path) -> Result<Option<Provenance>> {
+    let path = folder.join(PROVENANCE_FILE);
+
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read_to_string(path)?;
+
+    Ok(Some(serde_json::from_str(&contents)?))
+}
+
+fn write_provenance(
+    addon: &Addon,
+    addon_folders: &[AddonFolder],
+    to_directory: &Path,
+) -> Result<()> {
+    let id = &addon.primary_folder_id;
+    let repository = match addon.repository() {
+        Some(repository) => repository,
+        None => {
+            log::debug!("{} - no repository, skipping provenance", id);
+            return Ok(());
+        }
+    };
+
+    // Prefer the version of the freshly installed `.toc` file.
+    let version = addon_folders
+        .iter()
+        .find(|f| &f.id == id)
+        .and_then(|f| f.version.clone())
+        .or_else(|| addon.version().map(str::to_string));
+
+    let provenance = Provenance {
+        source: repository.kind,
+        id: repository.id.clone(),
+        version,
+        installed: Utc::now(),
+    };
+
+    let folder = to_directory.join(id);
+    if folder.is_dir() {
+        let contents = serde_json::to_string_pretty(&provenance)?;
+        std::fs::write(folder.join(PROVENANCE_FILE), contents)?;
+    }
+
+    Ok(())
+}
+
+/// Returns all `.toc` files directly inside `folder`. Returns nothing if `folder`
+/// isn't a directory.
+fn find_toc_files(folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut toc_files = vec![];
+
+    if !folder.is_dir() {
+        return Ok(toc_files);
+    }
+
+    for entry in folder.read_dir()?.filter_map(std::result::Result::ok) {
+        let path = entry.path();
+
+        if path.extension().and_then(|ext| ext.to_str()) == Some("toc") && path.is_file() {
+            toc_files.push(path);
+        }
+    }
+
+    Ok(toc_files)
+}
+
+/// Returns the top level `.toc` files of the addon folders in `root`, eg.
+/// `root/Foo/Foo.toc`, sorted by path. `.toc` files directly in `root` or
+/// nested deeper inside a folder aren't included.
+pub fn detect_toc_files(root: &Path) -> Vec<PathBuf> {
+    let mut toc_files: Vec<_> = WalkDir::new(root)
+        .min_depth(2)
+        .max_depth(2)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(walkdir::DirEntry::into_path)
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("toc"))
+        .collect();
+
+    toc_files.sort();
+    toc_files
+}
+
+/// Parses the `.toc` files of every top level folder in `addons_dir` across
+/// rayon's thread pool.
+///
+/// Like with `install_addon`, the folders are sorted and deduplicated at the end,
+/// so the result doesn't depend on the order the files were parsed in.
+pub fn scan_addons_parallel(addons_dir: &Path) -> Vec<AddonFolder> {
+    let mut addon_folders: Vec<_> = detect_toc_files(addons_dir)
+        .par_iter()
+        .filter_map(|p| parse_toc_path(p))
+        .collect();
+
+    addon_folders.sort();
+    addon_folders.dedup();
+    addon_folders
+}
+
+/// Errors if none of the `.toc` files extracted into `staging_directory` support
+/// `flavor`. A flavor specific `.toc` file, eg. `Foo_Mainline.toc`, counts as
+/// support for its flavor. Archives without any interface are always accepted.
+fn check_staged_flavor(id: &str, staging_directory: &Path, flavor: Flavor) -> Result<()> {
+    let flavor = flavor.base_flavor();
+    let mut interfaces = vec![];
+
+    for toc_path in detect_toc_files(staging_directory) {
+        let addon_folder = match parse_toc_path(&toc_path) {
+            Some(addon_folder) => addon_folder,
+            None => continue,
+        };
+
+        if supports_flavor(&addon_folder, flavor) {
+            return Ok(());
+        }
+
+        interfaces.extend(addon_folder.interfaces);
+    }
+
+    if interfaces.is_empty() {
+        return Ok(());
+    }
+
+    interfaces.sort_unstable();
+    interfaces.dedup();
+
+    Err(FilesystemError::IncompatibleFlavor {
+        id: id.to_string(),
+        flavor,
+        interfaces,
+    })
+}
+
+/// Default amount of addons `install_addons` installs at the same time.
+pub const DEFAULT_INSTALL_CONCURRENCY: usize = 4;
+
+/// Installs many addons at once by running up to `max_concurrent` calls of
+/// `install_addon` at the same time. A `max_concurrent` of `0` is treated as `1`.
+///
+/// Returns the result of each install together with the `primary_folder_id` of
+/// its addon, in the same order as `addons`.
+pub async fn install_addons(
+    addons: &[Addon],
+    from_directory: &Path,
+    to_directory: &Path,
+    max_concurrent: usize,
+) -> Vec<(String, Result<Vec<AddonFolder>>)> {
+    let installs = addons.iter().cloned().map(|addon| {
+        let from_directory = from_directory.to_path_buf();
+        let to_directory = to_directory.to_path_buf();
+
+        // Extraction is blocking, so every install gets its own thread.
+        async_std::task::spawn_blocking(move || {
+            let result =
+                async_std::task::block_on(install_addon(&addon, &from_directory, &to_directory));
+
+            (addon.primary_folder_id, result)
+        })
+    });
+
+    stream::iter(installs)
+        .buffered(max_concurrent.max(1))
+        .collect()
+        .await
+}
+
+/// Hash algorithms a downloaded archive can be verified with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha256,
+}
+
+/// Hashes the archive of `addon` in `from_directory` and compares it against
+/// the `expected` hex encoded hash. Meant to be called before `install_addon`,
+/// so truncated downloads never get extracted.
+pub fn verify_addon_checksum(
+    addon: &Addon,
+    from_directory: &Path,
+    algorithm: ChecksumAlgorithm,
+    expected: &str,
+) -> Result<()> {
+    let archive_path = from_directory.join(&addon.primary_folder_id);
+    let actual = hash_file(&archive_path, algorithm)?;
+
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(FilesystemError::ChecksumMismatch {
+            expected: expected.to_string(),
+            actual,
+        })
+    }
+}
+
+/// Streams the file at `path` through `algorithm` and returns the hex encoded hash.
+fn hash_file(path: &Path, algorithm: ChecksumAlgorithm) -> Result<String> {
+    fn digest<D: Digest>(path: &Path) -> Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = D::new();
+        let mut buffer = vec![0; 64 * 1024];
+
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..read]);
+        }
+
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
+
+    match algorithm {
+        ChecksumAlgorithm::Md5 => digest::<Md5>(path),
+        ChecksumAlgorithm::Sha256 => digest::<Sha256>(path),
+    }
+}
+
+/// Folders with byte-identical contents found by `dedup_shared_libs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateFolders {
+    /// All copies of the folder, sorted.
+    pub paths: Vec<PathBuf>,
+    /// Size in bytes of a single copy.
+    pub size: u64,
+}
+
+impl DuplicateFolders {
+    /// Bytes which could be saved by keeping only one copy.
+    pub fn savings(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Result of `dedup_shared_libs`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DedupReport {
+    /// Duplicated folders, the biggest savings first.
+    pub duplicates: Vec<DuplicateFolders>,
+}
+
+impl DedupReport {
+    /// Bytes which could be saved by keeping only one copy of every duplicate.
+    pub fn savings(&self) -> u64 {
+        self.duplicates.iter().map(DuplicateFolders::savings).sum()
+    }
+}
+
+/// Finds folders with identical contents inside the folders of `addons`, like
+/// the same library bundled by several addons. Nothing is changed on disk.
+///
+/// Folders are compared by hashing their file names and contents. Folders
+/// inside a reported duplicate aren't reported again.
+pub fn dedup_shared_libs(addons: &[Addon]) -> Result<DedupReport> {
+    let folders = addons
+        .iter()
+        .flat_map(|addon| addon.folders.iter().cloned())
+        .collect::<Vec<_>>();
+
+    let mut hashes = HashMap::new();
+    for path in existing_folder_paths(&folders, DEFAULT_CASE_INSENSITIVE) {
+        hash_dir(&path, &mut hashes)?;
+    }
+
+    let mut groups: HashMap<String, DuplicateFolders> = HashMap::new();
+    for (path, (hash, size)) in hashes {
+        if size > 0 {
+            groups
+                .entry(hash)
+                .or_insert_with(|| DuplicateFolders {
+                    paths: vec![],
+                    size,
+                })
+                .paths
+                .push(path);
+        }
+    }
+    groups.retain(|_, group| group.paths.len() > 1);
+
+    // A folder inside a duplicated folder is always duplicated as well.
+    let duplicated = groups
+        .values()
+        .flat_map(|group| group.paths.iter().cloned())
+        .collect::<HashSet<_>>();
+    let mut duplicates = groups
+        .into_values()
+        .map(|mut group| {
+            group.paths.sort();
+            group
+        })
+        .filter(|group| {
+            !group.paths.iter().all(|path| {
+                path.parent()
+                    .map(|p| duplicated.contains(p))
+                    .unwrap_or_default()
+            })
+        })
+        .collect::<Vec<_>>();
+    duplicates.sort_by(|a, b| {
+        b.savings()
+            .cmp(&a.savings())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+
+    Ok(DedupReport { duplicates })
+}
+
+/// Hashes the names and contents of everything inside the folder at `path`,
+/// recording the hash and size of it and every folder inside it in `hashes`.
+fn hash_dir(path: &Path, hashes: &mut HashMap<PathBuf, (String, u64)>) -> Result<(String, u64)> {
+    let mut entries = std::fs::read_dir(path)?
+        .filter_map(std::result::Result::ok)
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+
+    for entry in entries {
+        let file_type = entry.file_type()?;
+        let (kind, hash) = if file_type.is_dir() {
+            let (hash, dir_size) = hash_dir(&entry.path(), hashes)?;
+            size += dir_size;
+            ("d", hash)
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+            ("f", hash_file(&entry.path(), ChecksumAlgorithm::Sha256)?)
+        } else {
+            continue;
+        };
+
+        hasher.update(entry.file_name().to_string_lossy().as_bytes());
+        hasher.update(&[0]);
+        hasher.update(kind.as_bytes());
+        hasher.update(hash.as_bytes());
+    }
+
+    let hash = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    hashes.insert(path.to_path_buf(), (hash.clone(), size));
+
+    Ok((hash, size))
+}
+
+/// Archive formats an addon can be distributed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// Sniffs the format of the archive at `path` from its magic bytes.
+    /// Anything which isn't gzip is treated as zip.
+    fn detect(path: &Path) -> Result<ArchiveFormat> {
+        let mut magic = [0; 2];
+        let read = std::fs::File::open(path)?.read(&mut magic)?;
+
+        if read == magic.len() && magic == [0x1f, 0x8b] {
+            Ok(ArchiveFormat::TarGz)
+        } else {
+            Ok(ArchiveFormat::Zip)
+        }
+    }
+}
+
+/// Extracts the archive at `archive_path` into `to_directory`, regardless of its format.
+/// Returns the amount of extracted files. `id` is only used for logging.
+///
+/// Entries which can't be written are pushed to `failures` if given, otherwise
+/// the first one stops the extraction. Stops with `FilesystemError::Cancelled`
+/// as soon as `cancel` is set.
+fn extract_archive(
+    id: &str,
+    archive_path: &Path,
+    to_directory: &Path,
+    progress: &mut impl FnMut(u64, u64),
+    failures: &mut Option<&mut Vec<(PathBuf, std::io::Error)>>,
+    cancel: Option<&AtomicBool>,
+) -> Result<usize> {
+    match ArchiveFormat::detect(archive_path)? {
+        ArchiveFormat::Zip => {
+            extract_zip(id, archive_path, to_directory, progress, failures, cancel)
+        }
+        ArchiveFormat::TarGz => {
+            extract_tar_gz(id, archive_path, to_directory, progress, failures, cancel)
+        }
+    }
+}
+
+/// Returns `FilesystemError::Cancelled` if `cancel` is set.
+fn check_cancelled(cancel: Option<&AtomicBool>) -> Result<()> {
+    match cancel {
+        Some(cancel) if cancel.load(Ordering::Relaxed) => Err(FilesystemError::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+/// Extracts every entry of the zip archive at `zip_path` into `to_directory`.
+///
+/// Entries are streamed to disk through a single fixed size buffer, and are
+/// visited by index, so neither their content nor the list of names is collected.
+fn extract_zip(
+    id: &str,
+    zip_path: &Path,
+    to_directory: &Path,
+    progress: &mut impl FnMut(u64, u64),
+    failures: &mut Option<&mut Vec<(PathBuf, std::io::Error)>>,
+    cancel: Option<&AtomicBool>,
+) -> Result<usize> {
+    let mut zip_file = std::fs::File::open(zip_path)?;
+    let mut archive = zip::ZipArchive::new(&mut zip_file)?;
+
+    // Validate all entries before anything is written, so a single malicious
+    // entry aborts the whole install. Entries are read raw, so an unsupported
+    // compression method can be reported instead of a generic zip error.
+    let mut total_bytes = 0;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        entry_path(zip_entry_name(&file), to_directory)?;
+        check_zip_compression(&file)?;
+        if !is_zip_symlink(&file) {
+            total_bytes += file.size();
+        }
+    }
+
+    let mut bytes_done = 0;
+    let mut files = 0;
+    let mut buffer = vec![0; 64 * 1024];
+
+    std::fs::create_dir_all(to_directory)?;
+
+    for i in 0..archive.len() {
+        check_cancelled(cancel)?;
+
+        let mut file = archive.by_index(i)?;
+        let name = zip_entry_name(&file).to_string();
+        let path = entry_path(&name, to_directory)?;
+
+        if is_zip_symlink(&file) {
+            log::warn!("{} - skipping symlink entry {} in archive", id, name);
+        } else if file.is_dir() || name.ends_with('\\') {
+            if let Err(error) = std::fs::create_dir_all(&path) {
+                record_failure(id, failures, path, error)?;
+            }
+        } else {
+            let written = write_file(&mut file, &path, &mut buffer, |read| {
+                bytes_done += read;
+                progress(bytes_done, total_bytes);
+            })
+            .and_then(|_| set_unix_mode(&path, file.unix_mode()));
+
+            match written {
+                Ok(_) => files += 1,
+                Err(error) => record_failure(id, failures, path, error)?,
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns the name of a zip entry.
+///
+/// The zip crate decodes names as UTF-8 if the entry's general purpose bit 11 is
+/// set and as CP437 otherwise, as the spec says. Many archivers write UTF-8 names
+/// without setting the flag though, which would then end up garbled, so names
+/// which are valid UTF-8 are taken as is. Non-ASCII CP437 names practically
+/// never are valid UTF-8.
+fn zip_entry_name<'a>(file: &'a zip::read::ZipFile) -> &'a str {
+    std::str::from_utf8(file.name_raw()).unwrap_or_else(|_| file.name())
+}
+
+/// Errors with `FilesystemError::UnsupportedCompression` if `file` is compressed
+/// with a method the zip crate can't decompress.
+fn check_zip_compression(file: &zip::read::ZipFile) -> Result<()> {
+    #[allow(deprecated)]
+    match file.compression() {
+        zip::CompressionMethod::Unsupported(code) => {
+            let method = match code {
+                1 => "Shrink".to_string(),
+                2..=5 => "Reduce".to_string(),
+                6 => "Implode".to_string(),
+                9 => "Deflate64".to_string(),
+                12 => "BZIP2".to_string(),
+                14 => "LZMA".to_string(),
+                93 => "Zstandard".to_string(),
+                95 => "XZ".to_string(),
+                98 => "PPMd".to_string(),
+                code => format!("{}", code),
+            };
+
+            Err(FilesystemError::UnsupportedCompression {
+                method,
+                entry: zip_entry_name(file).to_string(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns `true` if the Unix mode stored for `file` marks it as a symlink.
+fn is_zip_symlink(file: &zip::read::ZipFile) -> bool {
+    const S_IFMT: u32 = 0o170_000;
+    const S_IFLNK: u32 = 0o120_000;
+
+    file.unix_mode()
+        .map(|mode| mode & S_IFMT == S_IFLNK)
+        .unwrap_or_default()
+}
+
+/// Extracts every entry of the gzip compressed tar archive at `archive_path`
+/// into `to_directory`.
+fn extract_tar_gz(
+    id: &str,
+    archive_path: &Path,
+    to_directory: &Path,
+    progress: &mut impl FnMut(u64, u64),
+    failures: &mut Option<&mut Vec<(PathBuf, std::io::Error)>>,
+    cancel: Option<&AtomicBool>,
+) -> Result<usize> {
+    let open = || -> Result<tar::Archive<GzDecoder<std::fs::File>>> {
+        Ok(tar::Archive::new(GzDecoder::new(std::fs::File::open(
+            archive_path,
+        )?)))
+    };
+
+    // Tar archives can only be read sequentially, so validation needs its own
+    // pass over the archive before anything is written.
+    let mut total_bytes = 0;
+    let mut archive = open()?;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        entry_path(&entry.path()?.to_string_lossy(), to_directory)?;
+        total_bytes += entry.size();
+    }
+
+    let mut bytes_done = 0;
+    let mut files = 0;
+    let mut buffer = vec![0; 64 * 1024];
+
+    std::fs::create_dir_all(to_directory)?;
+
+    let mut archive = open()?;
+    for entry in archive.entries()? {
+        check_cancelled(cancel)?;
+
+        let mut entry = entry?;
+        let path = entry_path(&entry.path()?.to_string_lossy(), to_directory)?;
+
+        match entry.header().entry_type() {
+            EntryType::Directory => {
+                if let Err(error) = std::fs::create_dir_all(&path) {
+                    record_failure(id, failures, path, error)?;
+                }
+            }
+            EntryType::Regular | EntryType::Continuous => {
+                let mode = entry.header().mode().ok();
+                let written = write_file(&mut entry, &path, &mut buffer, |read| {
+                    bytes_done += read;
+                    progress(bytes_done, total_bytes);
+                })
+                .and_then(|_| set_unix_mode(&path, mode));
+
+                match written {
+                    Ok(_) => files += 1,
+                    Err(error) => record_failure(id, failures, path, error)?,
+                }
+            }
+            EntryType::Symlink | EntryType::Link => {
+                log::warn!(
+                    "{} - skipping link entry {:?} in archive",
+                    id,
+                    entry.path()?
+                );
+            }
+            // Devices and metadata entries have no place in an addon.
+            _ => {}
+        }
+    }
+
+    Ok(files)
+}
+
+/// Pushes the `error` writing the entry at `path` to `failures`, or returns it
+/// if failures aren't collected.
+fn record_failure(
+    id: &str,
+    failures: &mut Option<&mut Vec<(PathBuf, std::io::Error)>>,
+    path: PathBuf,
+    error: std::io::Error,
+) -> Result<()> {
+    match failures {
+        Some(failures) => {
+            log::warn!("{} - failed to extract {:?}: {}", id, &path, error);
+            failures.push((path, error));
+
+            Ok(())
+        }
+        None => Err(error.into()),
+    }
+}
+
+/// Writes the content of `reader` to a new file at `path`, creating its parent
+/// directories if needed. `on_write` is called with the amount of bytes
+/// written after every chunk.
+fn write_file(
+    reader: &mut impl Read,
+    path: &Path,
+    buffer: &mut [u8],
+    mut on_write: impl FnMut(u64),
+) -> std::io::Result<()> {
+    if let Some(p) = path.parent() {
+        if !p.exists() {
+            std::fs::create_dir_all(p)?;
+        }
+    }
+    let mut outfile = std::fs::File::create(path)?;
+
+    loop {
+        let read = reader.read(buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        outfile.write_all(&buffer[..read])?;
+        on_write(read as u64);
+    }
+
+    Ok(())
+}
+
+/// Applies the permission bits of `mode`, as stored in the archive, to the
+/// extracted file or folder at `path`, so executable bits survive the install.
+///
+/// The owner always keeps read and write access, and folders stay searchable,
+/// otherwise later updates and uninstalls of the addon would fail.
+#[cfg(unix)]
+fn set_unix_mode(path: &Path, mode: Option<u32>) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Some(mode) = mode {
+        let owner = if path.is_dir() { 0o700 } else { 0o600 };
+
+        // Only keep the permission bits, never setuid / setgid / sticky.
+        let permissions = std::fs::Permissions::from_mode(mode & 0o777 | owner);
+        std::fs::set_permissions(path, permissions)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_unix_mode(_path: &Path, _mode: Option<u32>) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Returns the path the archive entry `name` should be extracted to. Errors if
+/// the entry would resolve to a path outside of `to_directory`.
+///
+/// Both `/` and `\` are treated as separators, since some archives built on
+/// Windows use backslashes. Absolute entries, with leading separators or a drive
+/// letter, are extracted relative to `to_directory`.
+fn entry_path(name: &str, to_directory: &Path) -> Result<PathBuf> {
+    let unsafe_path = || FilesystemError::UnsafeArchivePath {
+        name: name.to_string(),
+    };
+
+    if name.contains('\0') {
+        return Err(unsafe_path());
+    }
+
+    let normalized = name.replace('\\', "/");
+    let normalized = match normalized.as_bytes() {
+        [drive, b':', ..] if drive.is_ascii_alphabetic() => &normalized[2..],
+        _ => &normalized[..],
+    }
+    .trim_start_matches('/');
+
+    let mut depth = 0_usize;
+    for component in Path::new(normalized).components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return Err(unsafe_path()),
+            Component::ParentDir => depth = depth.checked_sub(1).ok_or_else(unsafe_path)?,
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+        }
+    }
+
+    Ok(to_directory.join(normalized))
+}
+
+/// How many levels of archives inside archives `extract_nested_archives` extracts.
+const MAX_NESTED_ARCHIVE_DEPTH: usize = 3;
+
+/// Some addon packs ship archives inside the archive. If the archive extracted
+/// into `staging_directory`, or one of its top level folders, contains nothing
+/// but a single `.zip` file, it's replaced with the content of that archive.
+///
+/// Repeats for archives found inside those, up to `MAX_NESTED_ARCHIVE_DEPTH` levels.
+fn extract_nested_archives(
+    id: &str,
+    staging_directory: &Path,
+    failures: &mut Option<&mut Vec<(PathBuf, std::io::Error)>>,
+    cancel: Option<&AtomicBool>,
+) -> Result<()> {
+    // Returns the path of the `.zip` file, if it's the only entry of `dir`.
+    let single_archive = |dir: &Path| -> Result<Option<PathBuf>> {
+        let entries = dir
+            .read_dir()?
+            .filter_map(std::result::Result::ok)
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+
+        Ok(match entries.as_slice() {
+            [path]
+                if path.is_file()
+                    && path
+                        .extension()
+                        .map(|ext| ext.eq_ignore_ascii_case("zip"))
+                        .unwrap_or_default() =>
+            {
+                Some(path.clone())
+            }
+            _ => None,
+        })
+    };
+
+    // Returns the nested archives with the path each of them replaces.
+    let nested_archives = || -> Result<Vec<(PathBuf, PathBuf)>> {
+        let mut archives = vec![];
+
+        if let Some(archive) = single_archive(staging_directory)? {
+            archives.push((archive.clone(), archive));
+        } else {
+            for entry in staging_directory
+                .read_dir()?
+                .filter_map(std::result::Result::ok)
+                .filter(|entry| entry.path().is_dir())
+            {
+                if let Some(archive) = single_archive(&entry.path())? {
+                    archives.push((entry.path(), archive));
+                }
+            }
+        }
+
+        Ok(archives)
+    };
+
+    for _ in 0..MAX_NESTED_ARCHIVE_DEPTH {
+        let archives = nested_archives()?;
+
+        if archives.is_empty() {
+            return Ok(());
+        }
+
+        let nested = staging_directory.join(format!("{}nested", STAGING_PREFIX));
+
+        for (replaced, archive) in archives {
+            log::debug!("{} - extracting nested archive {:?}", id, &archive);
+
+            extract_archive(id, &archive, &nested, &mut |_, _| {}, failures, cancel)?;
+
+            if replaced.is_dir() {
+                remove_dir_all(&replaced)?;
+            } else {
+                remove_file(&replaced)?;
+            }
+
+            for entry in nested.read_dir()?.filter_map(std::result::Result::ok) {
+                merge_staged_dir(&entry.path(), &staging_directory.join(entry.file_name()))?;
+            }
+            remove_dir_all(&nested)?;
+        }
+    }
+
+    if !nested_archives()?.is_empty() {
+        log::warn!(
+            "{} - not extracting archives nested deeper than {} levels",
+            id,
+            MAX_NESTED_ARCHIVE_DEPTH
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if the `.toc` file `addon_folder` was parsed from is meant for
+/// the base flavor `flavor`, either by being a flavor specific `.toc` file or
+/// by listing an interface of `flavor`.
+fn supports_flavor(addon_folder: &AddonFolder, flavor: Flavor) -> bool {
+    match addon_folder.toc_flavor {
+        Some(toc_flavor) => toc_flavor == flavor,
+        None => addon_folder
+            .interfaces
+            .iter()
+            .any(|i| flavor_from_interface(*i) == Some(flavor)),
+    }
+}
+
+/// Removes the top level folders extracted into `staging_directory` which are
+/// built for another flavor than `flavor`, unless that would remove all of them.
+///
+/// A folder named with a flavor suffix, eg. `Foo_Classic` or `Foo-BCC`, is
+/// built for that flavor. Otherwise a folder is built for another flavor if
+/// none of its `.toc` files support `flavor`, while listing some interface.
+fn remove_staged_foreign_folders(id: &str, staging_directory: &Path, flavor: Flavor) -> Result<()> {
+    let flavor = flavor.base_flavor();
+    let mut foreign = vec![];
+    let mut folder_count = 0;
+
+    for entry in staging_directory
+        .read_dir()?
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.path().is_dir())
+    {
+        folder_count += 1;
+
+        let name = entry.file_name().to_string_lossy().to_string();
+        let name_flavor = name
+            .rfind(|c| c == '_' || c == '-')
+            .and_then(|idx| flavor_from_toc_suffix(&name[idx + 1..]));
+
+        let is_foreign = match name_flavor {
+            Some(name_flavor) => name_flavor != flavor,
+            None => {
+                let addon_folders: Vec<_> = find_toc_files(&entry.path())?
+                    .iter()
+                    .filter_map(|toc_path| parse_toc_path(toc_path))
+                    .collect();
+
+                addon_folders.iter().any(|f| !f.interfaces.is_empty())
+                    && !addon_folders.iter().any(|f| supports_flavor(f, flavor))
+            }
+        };
+
+        if is_foreign {
+            foreign.push(entry.path());
+        }
+    }
+
+    if foreign.len() == folder_count {
+        log::debug!("{} - no folders built for {}, installing all", id, flavor);
+        return Ok(());
+    }
+
+    for path in foreign {
+        log::debug!(
+            "{} - skipping {:?}, it isn't built for {}",
+            id,
+            path,
+            flavor
+        );
+        remove_dir_all(&path)?;
+    }
+
+    Ok(())
+}
+
+/// Some archives have their `.toc` and Lua files at the root, instead of inside
+/// a folder named after the addon. If that's the case for the archive extracted
+/// into `staging_directory`, all of its entries are moved into a folder named
+/// after the primary `.toc` file.
+fn wrap_flat_archive(staging_directory: &Path) -> Result<()> {
+    let mut toc_stems: Vec<_> = staging_directory
+        .read_dir()?
+        .filter_map(std::result::Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("toc"))
+        .filter(|path| path.is_file())
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+
+    if toc_stems.is_empty() {
+        return Ok(());
+    }
+
+    // Prefer `Foo.toc` over `Foo_Mainline.toc`, by stripping flavor suffixes.
+    toc_stems.sort();
+    let folder_name = toc_stems
+        .iter()
+        .map(|stem| strip_toc_flavor_suffix(stem))
+        .min_by_key(|stem| stem.len())
+        .unwrap_or_default()
+        .to_string();
+
+    // Move to a temporary folder first, since an entry can share the folder name.
+    let wrapper = staging_directory.join(format!("{}wrapper", STAGING_PREFIX));
+    std::fs::create_dir_all(&wrapper)?;
+
+    for entry in staging_directory
+        .read_dir()?
+        .filter_map(std::result::Result::ok)
+    {
+        if entry.path() != wrapper {
+            std::fs::rename(entry.path(), wrapper.join(entry.file_name()))?;
+        }
+    }
+
+    std::fs::rename(&wrapper, staging_directory.join(folder_name))?;
+
+    Ok(())
+}
+
+/// Strips a flavor suffix like `_Mainline` or `-BCC` from a `.toc` file stem.
+fn strip_toc_flavor_suffix(stem: &str) -> &str {
+    for suffix in &["mainline", "classic", "vanilla", "bcc", "tbc"] {
+        let split = stem.len().saturating_sub(suffix.len());
+
+        // Stems can be non-ASCII, so `split` may fall inside of a character.
+        if stem.len() > suffix.len() + 1 && stem.is_char_boundary(split) {
+            let (head, tail) = stem.split_at(split);
+
+            if tail.eq_ignore_ascii_case(suffix) && (head.ends_with('-') || head.ends_with('_')) {
+                return &head[..head.len() - 1];
+            }
+        }
+    }
+
+    stem
+}
+
+/// Replaces the existing folders of `addon` with the top level entries extracted
+/// into `staging_directory`. Returns the names of the moved top level entries.
+///
+/// If `InstallOptions::folders` is given, folders not part of it are neither
+/// removed nor moved. Ignored folders are never removed nor moved either.
+fn move_staged_folders(
+    addon: &Addon,
+    staging_directory: &Path,
+    to_directory: &Path,
+    options: &InstallOptions,
+) -> Result<Vec<String>> {
+    let case_insensitive = options.case_insensitive;
+    let included = |name: &str| installs_folder(options, name);
+
+    // Get all new top level folders. These are read from the staging directory
+    // rather than the archive entries, so only the top level names are ever held
+    // in memory, no matter how many entries the archive has. Names are unique.
+    let new_top_level_folders = staging_directory
+        .read_dir()?
+        .filter_map(std::result::Result::ok)
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .filter(|name| included(name))
+        .collect::<Vec<_>>();
+
+    if options.merge {
+        for folder in new_top_level_folders.iter() {
+            let path = to_directory.join(folder);
+            let path = find_existing_path(&path, case_insensitive).unwrap_or(path);
+
+            merge_staged_dir(&staging_directory.join(folder), &path)?;
+        }
+
+        return Ok(new_top_level_folders);
+    }
+
+    // Collect all existing top level addon folders the new version no longer has,
+    // unless another installed addon still claims them. The others are swapped below.
+    let same_id = |a: &str, b: &str| a == b || (case_insensitive && a.eq_ignore_ascii_case(b));
+    let replaced = |id: &str| new_top_level_folders.iter().any(|f| same_id(f, id));
+    let shared = |id: &str| {
+        options
+            .installed_addons
+            .iter()
+            .flatten()
+            .filter(|other| other.primary_folder_id != addon.primary_folder_id)
+            .any(|other| other.folders.iter().any(|f| same_id(&f.id, id)))
+    };
+    let mut dropped = vec![];
+    for folder in addon
+        .folders
+        .iter()
+        .filter(|f| included(&f.id) && !replaced(&f.id))
+    {
+        if shared(&folder.id) {
+            log::debug!(
+                "{} - keeping folder {} claimed by another addon",
+                addon.primary_folder_id,
+                &folder.id
+            );
+            continue;
+        }
+
+        if let Some(path) = find_existing_path(&folder.path, case_insensitive) {
+            dropped.push(path);
+        }
+    }
+
+    let preserve = options
+        .preserve
+        .iter()
+        .map(|pattern| glob::Pattern::new(pattern))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let mut swaps = vec![];
+    for folder in new_top_level_folders.iter() {
+        let path = to_directory.join(folder);
+        let existing = find_existing_path(&path, case_insensitive);
+
+        if let Some(existing) = existing.as_deref().filter(|_| !preserve.is_empty()) {
+            preserve_files(addon, existing, &staging_directory.join(folder), &preserve)?;
+        }
+
+        // Only folders of other addons or the user are quarantined, not the
+        // previous version of this addon.
+        let quarantine = options
+            .quarantine
+            .as_deref()
+            .filter(|_| !addon.folders.iter().any(|f| same_id(&f.id, folder)));
+
+        swaps.push(StagedSwap {
+            staged: staging_directory.join(folder),
+            path,
+            existing,
+            quarantine,
+        });
+    }
+
+    swap_staged_folders(addon, &swaps, &dropped)?;
+
+    Ok(new_top_level_folders)
+}
+
+/// Returns `true` if the top level folder `name` of an archive gets installed
+/// with `options`.
+fn installs_folder(options: &InstallOptions, name: &str) -> bool {
+    let selected = match options.folders.as_deref() {
+        Some(only) => only.iter().any(|o| o == name),
+        None => true,
+    };
+
+    selected && !is_folder_ignored(name)
+}
+
+/// Returns the `InstallStats` of the top level folders in `staging_directory`
+/// which get installed with `options`.
+fn staged_stats(staging_directory: &Path, options: &InstallOptions) -> Result<InstallStats> {
+    let mut stats = InstallStats::default();
+
+    for entry in staging_directory
+        .read_dir()?
+        .filter_map(std::result::Result::ok)
+    {
+        if !installs_folder(options, &entry.file_name().to_string_lossy()) {
+            continue;
+        }
+
+        for entry in WalkDir::new(entry.path()) {
+            let entry = entry?;
+
+            if entry.file_type().is_dir() {
+                stats.folders += 1;
+            } else if entry.file_type().is_file() {
+                let size = entry.metadata()?.len();
+
+                stats.files += 1;
+                stats.total_bytes += size;
+
+                let is_largest = match &stats.largest_file {
+                    Some((_, largest)) => size > *largest,
+                    None => true,
+                };
+
+                if is_largest {
+                    let path = entry
+                        .path()
+                        .strip_prefix(staging_directory)
+                        .unwrap_or(entry.path());
+                    stats.largest_file = Some((path.to_path_buf(), size));
+                }
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Copies the files in `existing` matching any of the `patterns` into `staged`,
+/// unless `staged` already has them. Patterns match paths relative to `existing`.
+fn preserve_files(
+    addon: &Addon,
+    existing: &Path,
+    staged: &Path,
+    patterns: &[glob::Pattern],
+) -> Result<()> {
+    if !existing.is_dir() || !staged.is_dir() {
+        return Ok(());
+    }
+
+    let match_options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+
+    for entry in WalkDir::new(existing).min_depth(1) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(existing).unwrap_or(entry.path());
+        if !patterns
+            .iter()
+            .any(|pattern| pattern.matches_path_with(relative, match_options))
+        {
+            continue;
+        }
+
+        let target = staged.join(relative);
+        if target.exists() {
+            continue;
+        }
+
+        log::debug!(
+            "{} - preserving {:?}",
+            addon.primary_folder_id,
+            entry.path()
+        );
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(entry.path(), &target)?;
+    }
+
+    Ok(())
+}
+
+/// A staged top level folder and the `existing` entry at `path` it replaces.
+struct StagedSwap<'a> {
+    staged: PathBuf,
+    path: PathBuf,
+    existing: Option<PathBuf>,
+    /// Directory the existing entry is moved to instead of being removed.
+    quarantine: Option<&'a Path>,
+}
+
+/// Moves all staged folders of `swaps` into place, replacing their existing
+/// entries, and removes the `dropped` folders.
+///
+/// All existing entries are first renamed aside, then the staged folders are
+/// moved in. If any step fails, every completed step is undone, so either all
+/// folders are replaced or the previous ones are left as they were. The entries
+/// renamed aside and the `dropped` folders are only removed, or quarantined,
+/// once every staged folder is in place.
+fn swap_staged_folders(addon: &Addon, swaps: &[StagedSwap], dropped: &[PathBuf]) -> Result<()> {
+    let mut asides: Vec<(&Path, PathBuf)> = vec![];
+
+    for existing in swaps.iter().filter_map(|swap| swap.existing.as_deref()) {
+        let name = existing.file_name().unwrap_or_default().to_string_lossy();
+        let aside = existing.with_file_name(format!("{}old-{}", STAGING_PREFIX, name));
+
+        let result = remove_entry(&aside)
+            .and_then(|_| crate::utility::rename(existing, &aside).map_err(Into::into));
+        if let Err(error) = result {
+            restore_asides(&asides);
+            return Err(error);
+        }
+
+        asides.push((existing, aside));
+    }
+
+    for (index, swap) in swaps.iter().enumerate() {
+        if let Err(error) = move_dir(&swap.staged, &swap.path) {
+            for swap in swaps[..index].iter().rev() {
+                let _ = move_dir(&swap.path, &swap.staged);
+            }
+            restore_asides(&asides);
+            return Err(error);
+        }
+    }
+
+    for path in dropped {
+        log::debug!("{} - removing folder {:?}", addon.primary_folder_id, path);
+        remove_entry(path)?;
+    }
+
+    let quarantines = swaps
+        .iter()
+        .filter(|swap| swap.existing.is_some())
+        .map(|swap| swap.quarantine);
+    for ((existing, aside), quarantine) in asides.iter().zip(quarantines) {
+        match quarantine {
+            Some(quarantine) => quarantine_entry(addon, aside, existing, quarantine)?,
+            None => {
+                log::debug!("{} - removing folder {:?}", addon.primary_folder_id, aside);
+                remove_entry(aside)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Renames the entries `swap_staged_folders` renamed aside back, in reverse.
+fn restore_asides(asides: &[(&Path, PathBuf)]) {
+    for (existing, aside) in asides.iter().rev() {
+        if let Err(error) = crate::utility::rename(aside, existing) {
+            log::error!(
+                "failed to restore {:?} from {:?}: {}",
+                existing,
+                aside,
+                error
+            );
+        }
+    }
+}
+
+/// Removes the directory or file at `path`, if there is one.
+fn remove_entry(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        remove_dir_all(path)?;
+    } else if path.exists() {
+        remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Name of the file inside a quarantine directory listing its `QuarantineEntry`'s.
+const QUARANTINE_FILE: &str = "quarantine.json";
+
+/// A folder an install moved into a quarantine directory instead of deleting
+/// it, see `InstallOptions::quarantine`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    /// Id of the addon whose install replaced the folder.
+    pub addon: String,
+    /// Where the folder was before the install.
+    pub original: PathBuf,
+    /// Where the folder is now, inside the quarantine directory.
+    pub quarantined: PathBuf,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+/// Reads the entries of the quarantine directory `quarantine`, oldest first.
+pub fn read_quarantine(quarantine: &Path) -> Result<Vec<QuarantineEntry>> {
+    let path = quarantine.join(QUARANTINE_FILE);
+
+    if !path.is_file() {
+        return Ok(vec![]);
+    }
+
+    let contents = std::fs::read_to_string(path)?;
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Moves `from`, the replaced entry `original`, into a new folder in
+/// `quarantine` and records it in the quarantine file.
+fn quarantine_entry(addon: &Addon, from: &Path, original: &Path, quarantine: &Path) -> Result<()> {
+    let quarantined = quarantine
+        .join(format!(
+            "{}-{}",
+            addon.primary_folder_id,
+            Local::now().format("%Y-%m-%d_%H-%M-%S")
+        ))
+        .join(original.file_name().unwrap_or_default());
+
+    if quarantined.is_dir() {
+        remove_dir_all(&quarantined)?;
+    } else if quarantined.exists() {
+        remove_file(&quarantined)?;
+    }
+
+    std::fs::create_dir_all(quarantined.parent().unwrap_or(quarantine))?;
+    move_dir(from, &quarantined)?;
+
+    log::debug!(
+        "{} - quarantined {:?} to {:?}",
+        addon.primary_folder_id,
+        original,
+        &quarantined
+    );
+
+    let mut entries = read_quarantine(quarantine)?;
+    entries.push(QuarantineEntry {
+        addon: addon.primary_folder_id.clone(),
+        original: original.to_path_buf(),
+        quarantined,
+        quarantined_at: Utc::now(),
+    });
+
+    let contents = serde_json::to_string_pretty(&entries)?;
+    std::fs::write(quarantine.join(QUARANTINE_FILE), contents)?;
+
+    Ok(())
+}
+
+/// Renames the directory or file `from` to `to`. Falls back to copying if both
+/// aren't on the same filesystem, which rename doesn't support.
+fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::rename(from, to) {
+        Ok(_) => Ok(()),
+        Err(error) if is_cross_device(&error) => {
+            log::debug!("moving {:?} across filesystems by copying", from);
+
+            if let Err(error) = copy_dir(from, to) {
+                let _ = if to.is_dir() {
+                    remove_dir_all(to)
+                } else {
+                    remove_file(to)
+                };
+                return Err(error);
+            }
+
+            if from.is_dir() {
+                remove_dir_all(from)?;
+            } else {
+                remove_file(from)?;
+            }
+            Ok(())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Returns `true` if `error` is caused by renaming across filesystems.
+fn is_cross_device(error: &std::io::Error) -> bool {
+    // EXDEV on unix, ERROR_NOT_SAME_DEVICE on Windows.
+    if cfg!(windows) {
+        error.raw_os_error() == Some(17)
+    } else {
+        error.raw_os_error() == Some(18)
+    }
+}
+
+/// Recursively copies the directory or file `from` to `to`.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    if !from.is_dir() {
+        std::fs::copy(from, to)?;
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(to)?;
+
+    for entry in from.read_dir()? {
+        let entry = entry?;
+        copy_dir(&entry.path(), &to.join(entry.file_name()))?;
+    }
+
+    Ok(())
+}
+
+/// Moves everything in `from` into `to`, overwriting existing files but keeping
+/// files in `to` which aren't in `from`.
+fn merge_staged_dir(from: &Path, to: &Path) -> Result<()> {
+    if !from.is_dir() {
+        if to.is_dir() {
+            remove_dir_all(to)?;
+        }
+
+        return move_dir(from, to);
+    }
+
+    if to.exists() && !to.is_dir() {
+        remove_file(to)?;
+    }
+    std::fs::create_dir_all(to)?;
+
+    for entry in from.read_dir()? {
+        let entry = entry?;
+        merge_staged_dir(&entry.path(), &to.join(entry.file_name()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tempfile::tempdir;
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    /// Writes a zip archive with the given `(name, content)` entries to `path`.
+    fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut writer = ZipWriter::new(fs::File::create(path).unwrap());
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+        for (name, content) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+
+        writer.finish().unwrap();
+    }
+
+    /// Writes a gzip compressed tar archive with the given `(name, content)` entries to `path`.
+    fn write_tar_gz(path: &Path, entries: &[(&str, &[u8])]) {
+        let encoder = flate2::write::GzEncoder::new(
+            fs::File::create(path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+
+        for (name, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_install_addon_failure_keeps_existing_folders() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        // Existing install which should survive the failed update
+        let existing = to_directory.join("Foo");
+        fs::create_dir_all(&existing).unwrap();
+        fs::write(existing.join("Foo.toc"), "## Version: 1.0").unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        addon.folders = vec![AddonFolder {
+            id: "Foo".to_string(),
+            path: existing.clone(),
+            ..Default::default()
+        }];
+
+        // Corrupt the content of the last entry so its checksum fails on extraction
+        let zip_path = from_directory.join("Foo");
+        write_zip(
+            &zip_path,
+            &[
+                ("Foo/Foo.toc", b"## Version: 2.0"),
+                ("Foo/Foo.lua", b"print('corrupt me')"),
+            ],
+        );
+        let mut bytes = fs::read(&zip_path).unwrap();
+        let pos = bytes.windows(7).position(|w| w == b"corrupt").unwrap();
+        bytes[pos] = b'C';
+        fs::write(&zip_path, bytes).unwrap();
+
+        let result =
+            async_std::task::block_on(install_addon(&addon, &from_directory, &to_directory));
+
+        assert!(result.is_err());
+        assert_eq!(
+            fs::read_to_string(existing.join("Foo.toc")).unwrap(),
+            "## Version: 1.0"
+        );
+        assert!(!existing.join("Foo.lua").exists());
+        assert_eq!(fs::read_dir(&to_directory).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_install_addon_with_progress() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let addon = Addon::empty("Foo");
+        write_zip(
+            &from_directory.join("Foo"),
+            &[
+                ("Foo/Foo.toc", b"## Title: Foo"),
+                ("Foo/Foo.lua", b"print('foo')"),
+            ],
+        );
+
+        let mut last = (0, 0);
+        let folders = async_std::task::block_on(install_addon_with_progress(
+            &addon,
+            &from_directory,
+            &to_directory,
+            |done, total| last = (done, total),
+        ))
+        .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert_eq!(last, (25, 25));
+    }
+
+    #[test]
+    fn test_install_addon_unsupported_compression() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let archive_path = from_directory.join("Foo");
+        write_zip(&archive_path, &[("Foo/Foo.toc", b"## Title: Foo")]);
+
+        // Rewrite the compression method of the entry to zstd (93), both in
+        // the local file header and the central directory.
+        let mut bytes = fs::read(&archive_path).unwrap();
+        bytes[8..10].copy_from_slice(&93u16.to_le_bytes());
+        let central = bytes.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        bytes[central + 10..central + 12].copy_from_slice(&93u16.to_le_bytes());
+        fs::write(&archive_path, bytes).unwrap();
+
+        let addon = Addon::empty("Foo");
+        let result =
+            async_std::task::block_on(install_addon(&addon, &from_directory, &to_directory));
+
+        match result {
+            Err(FilesystemError::UnsupportedCompression { method, entry }) => {
+                assert_eq!(method, "Zstandard");
+                assert_eq!(entry, "Foo/Foo.toc");
+            }
+            result => panic!("expected unsupported compression, got {:?}", result),
+        }
+        assert!(!to_directory.join("Foo").exists());
+    }
+
+    #[test]
+    fn test_install_addon_non_utf8_names() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let archive_path = from_directory.join("Foo");
+        write_zip(
+            &archive_path,
+            &[
+                ("Caf~/Caf~.toc", b"## Title: Test"),
+                ("Na~~ve/Na~~ve.toc", b"## Title: Test"),
+            ],
+        );
+
+        // Rewrite the names without setting the UTF-8 flag, as CP437 (`é` is
+        // 0x82) and as UTF-8 (`ï`) respectively.
+        let mut bytes = fs::read(&archive_path).unwrap();
+        let replace = |bytes: &mut Vec<u8>, from: &[u8], to: &[u8]| {
+            while let Some(position) = bytes.windows(from.len()).position(|w| w == from) {
+                bytes[position..position + to.len()].copy_from_slice(to);
+            }
+        };
+        replace(&mut bytes, b"Caf~", b"Caf\x82");
+        replace(&mut bytes, b"Na~~ve", b"Na\xc3\xafve");
+        fs::write(&archive_path, bytes).unwrap();
+
+        let addon = Addon::empty("Foo");
+        async_std::task::block_on(install_addon(&addon, &from_directory, &to_directory)).unwrap();
+
+        assert!(to_directory.join("Café").join("Café.toc").exists());
+        assert!(to_directory.join("Naïve").join("Naïve.toc").exists());
+    }
+
+    #[test]
+    fn test_install_addon_tar_gz() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let existing = to_directory.join("Foo");
+        fs::create_dir_all(&existing).unwrap();
+        fs::write(existing.join("Old.lua"), "print('old')").unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        addon.folders = vec![AddonFolder {
+            id: "Foo".to_string(),
+            path: existing.clone(),
+            ..Default::default()
+        }];
+        write_tar_gz(
+            &from_directory.join("Foo"),
+            &[
+                ("Foo/Foo.toc", b"## Title: Foo"),
+                ("Foo/Foo.lua", b"print('foo')"),
+                ("FooOptions/FooOptions.toc", b"## Title: Foo Options"),
+            ],
+        );
+
+        let mut last = (0, 0);
+        let folders = async_std::task::block_on(install_addon_with_progress(
+            &addon,
+            &from_directory,
+            &to_directory,
+            |done, total| last = (done, total),
+        ))
+        .unwrap();
+
+        let ids: Vec<_> = folders.iter().map(|f| f.id.as_str()).collect();
+        assert_eq!(ids, vec!["Foo", "FooOptions"]);
+        assert_eq!(last, (46, 46));
+        assert!(!existing.join("Old.lua").exists());
+        assert!(existing.join("Foo.lua").exists());
+        assert!(!from_directory.join("Foo").exists());
+        assert_eq!(fs::read_dir(&to_directory).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_verify_addon_checksum() {
+        let tempdir = tempdir().unwrap();
+        let addon = Addon::empty("Foo");
+        fs::write(tempdir.path().join("Foo"), "ajour").unwrap();
+
+        verify_addon_checksum(
+            &addon,
+            tempdir.path(),
+            ChecksumAlgorithm::Md5,
+            "8B539F2E631044BCA3CC59A4BBA15C68",
+        )
+        .unwrap();
+        verify_addon_checksum(
+            &addon,
+            tempdir.path(),
+            ChecksumAlgorithm::Sha256,
+            "54a6f1b1bb85dc0e729d1d36a0013ec26c43c25643408d55d8a2c5b354665fa1",
+        )
+        .unwrap();
+
+        let result = verify_addon_checksum(&addon, tempdir.path(), ChecksumAlgorithm::Md5, "00");
+        assert!(matches!(
+            result,
+            Err(FilesystemError::ChecksumMismatch { actual, .. })
+                if actual == "8b539f2e631044bca3cc59a4bba15c68"
+        ));
+    }
+
+    #[test]
+    fn test_install_addons() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let addons: Vec<_> = ["A", "B", "C", "Missing"]
+            .iter()
+            .map(|id| Addon::empty(id))
+            .collect();
+        for id in &["A", "B", "C"] {
+            let toc = format!("{}/{}.toc", id, id);
+            write_zip(&from_directory.join(id), &[(&toc, b"## Title: Test")]);
+        }
+
+        let results =
+            async_std::task::block_on(install_addons(&addons, &from_directory, &to_directory, 2));
+
+        let ids: Vec<_> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["A", "B", "C", "Missing"]);
+        assert!(results[..3]
+            .iter()
+            .all(|(_, r)| r.as_ref().unwrap().len() == 1));
+        assert!(results[3].1.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_addon_preserves_unix_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let mut writer = ZipWriter::new(fs::File::create(from_directory.join("Foo")).unwrap());
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("Foo/Foo.toc", options).unwrap();
+        writer.write_all(b"## Title: Foo").unwrap();
+        writer
+            .start_file("Foo/tool.sh", options.unix_permissions(0o755))
+            .unwrap();
+        writer.write_all(b"#!/bin/sh").unwrap();
+        writer
+            .start_file("Foo/locked.lua", options.unix_permissions(0o444))
+            .unwrap();
+        writer.finish().unwrap();
+
+        async_std::task::block_on(install_addon(
+            &Addon::empty("Foo"),
+            &from_directory,
+            &to_directory,
+        ))
+        .unwrap();
+
+        let mode = |name: &str| {
+            fs::metadata(to_directory.join("Foo").join(name))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode("tool.sh"), 0o755);
+        assert_eq!(mode("Foo.toc") & 0o111, 0);
+        assert_eq!(mode("locked.lua"), 0o644);
+    }
+
+    #[test]
+    fn test_install_addon_skips_symlinks() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let mut writer = ZipWriter::new(fs::File::create(from_directory.join("Foo")).unwrap());
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("Foo/Foo.toc", options).unwrap();
+        writer.write_all(b"## Title: Foo").unwrap();
+        writer.start_file("Foo/ssh", options).unwrap();
+        writer.write_all(b"/root/.ssh").unwrap();
+        writer.finish().unwrap();
+
+        // The writer only stores permission bits, so mark the last central
+        // directory entry as a symlink by hand.
+        let zip_path = from_directory.join("Foo");
+        let mut bytes = fs::read(&zip_path).unwrap();
+        let pos = bytes.windows(4).rposition(|w| w == b"PK\x01\x02").unwrap();
+        bytes[pos + 38..pos + 42].copy_from_slice(&(0o120_777_u32 << 16).to_le_bytes());
+        fs::write(&zip_path, bytes).unwrap();
+
+        let folders = async_std::task::block_on(install_addon(
+            &Addon::empty("Foo"),
+            &from_directory,
+            &to_directory,
+        ))
+        .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert!(to_directory.join("Foo").join("Foo.toc").exists());
+        assert!(fs::symlink_metadata(to_directory.join("Foo").join("ssh")).is_err());
+    }
+
+    #[test]
+    fn test_install_addon_keeps_archive() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let addon = Addon::empty("Foo");
+        write_zip(
+            &from_directory.join("Foo"),
+            &[("Foo/Foo.toc", b"## Title: Foo")],
+        );
+
+        let options = InstallOptions {
+            cleanup: false,
+            ..Default::default()
+        };
+        let folders = async_std::task::block_on(install_addon_with_options(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &options,
+            |_, _| {},
+        ))
+        .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert!(from_directory.join("Foo").exists());
+    }
+
+    #[test]
+    fn test_install_addon_collecting_errors() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        // `Foo/data` is written as a file, so nothing can be written inside of it.
+        let entries: &[(&str, &[u8])] = &[
+            ("Foo/Foo.toc", b"## Title: Foo"),
+            ("Foo/data", b"not a directory"),
+            ("Foo/data/locked.lua", b""),
+            ("Foo/Foo.lua", b"-- rest"),
+        ];
+        let addon = Addon::empty("Foo");
+
+        write_zip(&from_directory.join("Foo"), entries);
+        assert!(
+            async_std::task::block_on(install_addon(&addon, &from_directory, &to_directory))
+                .is_err()
+        );
+        assert!(!to_directory.join("Foo").exists());
+
+        write_zip(&from_directory.join("Foo"), entries);
+        let (folders, failures) = async_std::task::block_on(install_addon_collecting_errors(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &InstallOptions::default(),
+        ))
+        .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].id, "Foo");
+        assert!(to_directory.join("Foo").join("Foo.lua").exists());
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].0.ends_with("Foo/data/locked.lua"));
+    }
+
+    #[test]
+    fn test_install_local_archive() {
+        let tempdir = tempdir().unwrap();
+        let downloads = tempdir.path().join("Downloads");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&downloads).unwrap();
+
+        let archive_path = downloads.join("Foo-2.0-beta.zip");
+        write_zip(
+            &archive_path,
+            &[
+                ("Foo/Foo.toc", b"## Title: Foo\n## X-Curse-Project-ID: 1234"),
+                ("Foo/Foo.lua", b"-- beta"),
+            ],
+        );
+
+        let folders =
+            async_std::task::block_on(install_local_archive(&archive_path, &to_directory)).unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].id, "Foo");
+        assert_eq!(folders[0].repository_identifiers.curse, Some(1234));
+        assert!(to_directory.join("Foo").join("Foo.lua").exists());
+        assert!(archive_path.exists());
+
+        assert!(async_std::task::block_on(install_local_archive(
+            &downloads.join("Missing.zip"),
+            &to_directory
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_install_addon_pinned() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        addon.pin();
+        write_zip(
+            &from_directory.join("Foo"),
+            &[("Foo/Foo.toc", b"## Title: Foo")],
+        );
+
+        let result =
+            async_std::task::block_on(install_addon(&addon, &from_directory, &to_directory));
+        assert!(matches!(result, Err(FilesystemError::AddonPinned { .. })));
+        assert!(!to_directory.join("Foo").exists());
+
+        let options = InstallOptions {
+            force: true,
+            ..Default::default()
+        };
+        let folders = async_std::task::block_on(install_addon_with_options(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &options,
+            |_, _| {},
+        ))
+        .unwrap();
+        assert_eq!(folders.len(), 1);
+    }
+
+    #[test]
+    fn test_install_addon_flat_archive() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let addon = Addon::empty("Flat");
+        write_zip(
+            &from_directory.join("Flat"),
+            &[
+                ("Flat_Mainline.toc", b"## Title: Flat"),
+                ("Flat.toc", b"## Title: Flat"),
+                ("Flat.lua", b"print('flat')"),
+                ("Libs/Lib.lua", b"print('lib')"),
+            ],
+        );
+
+        let folders =
+            async_std::task::block_on(install_addon(&addon, &from_directory, &to_directory))
+                .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].id, "Flat");
+        assert!(to_directory.join("Flat").join("Flat.lua").exists());
+        assert!(to_directory
+            .join("Flat")
+            .join("Libs")
+            .join("Lib.lua")
+            .exists());
+        assert_eq!(fs::read_dir(&to_directory).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_install_addon_subset_of_folders() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let mut addon = Addon::empty("Suite");
+        for id in &["Suite", "SuiteModule"] {
+            let path = to_directory.join(id);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("custom.lua"), "-- hand edited").unwrap();
+            addon.folders.push(AddonFolder {
+                id: id.to_string(),
+                path,
+                ..Default::default()
+            });
+        }
+        write_zip(
+            &from_directory.join("Suite"),
+            &[
+                ("Suite/Suite.toc", b"## Title: Suite"),
+                ("SuiteModule/SuiteModule.toc", b"## Title: Suite Module"),
+            ],
+        );
+
+        let options = InstallOptions {
+            folders: Some(vec!["Suite".to_string()]),
+            ..Default::default()
+        };
+        let folders = async_std::task::block_on(install_addon_with_options(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &options,
+            |_, _| {},
+        ))
+        .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].id, "Suite");
+        assert!(!to_directory.join("Suite").join("custom.lua").exists());
+        assert!(to_directory.join("SuiteModule").join("custom.lua").exists());
+        assert!(!to_directory
+            .join("SuiteModule")
+            .join("SuiteModule.toc")
+            .exists());
+    }
+
+    #[test]
+    fn test_swap_staged_folders() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+        let addon = Addon::empty("Foo");
+
+        for folder in &["Foo", "Bar", "Dropped"] {
+            fs::create_dir_all(root.join(folder)).unwrap();
+            fs::write(root.join(folder).join("old.lua"), "").unwrap();
+        }
+        for folder in &["Foo", "Bar"] {
+            fs::create_dir_all(root.join("staged").join(folder)).unwrap();
+            fs::write(root.join("staged").join(folder).join("new.lua"), "").unwrap();
+        }
+
+        let swap = |folder: &str, staged: &str| StagedSwap {
+            staged: root.join("staged").join(staged),
+            path: root.join(folder),
+            existing: Some(root.join(folder)),
+            quarantine: None,
+        };
+
+        // A later swap failing puts back the folders already swapped, and keeps
+        // the dropped folder.
+        let result = swap_staged_folders(
+            &addon,
+            &[swap("Foo", "Foo"), swap("Bar", "Missing")],
+            &[root.join("Dropped")],
+        );
+        assert!(result.is_err());
+        for folder in &["Foo", "Bar", "Dropped"] {
+            assert!(root.join(folder).join("old.lua").exists());
+            assert!(!root.join(folder).join("new.lua").exists());
+        }
+        assert!(root.join("staged").join("Foo").join("new.lua").exists());
+        assert_eq!(fs::read_dir(root).unwrap().count(), 4);
+
+        swap_staged_folders(
+            &addon,
+            &[swap("Foo", "Foo"), swap("Bar", "Bar")],
+            &[root.join("Dropped")],
+        )
+        .unwrap();
+        for folder in &["Foo", "Bar"] {
+            assert!(root.join(folder).join("new.lua").exists());
+            assert!(!root.join(folder).join("old.lua").exists());
+        }
+        assert!(!root.join("Dropped").exists());
+        assert_eq!(fs::read_dir(root).unwrap().count(), 3);
+
+        copy_dir(&root.join("Foo"), &root.join("Copy")).unwrap();
+        assert!(root.join("Copy").join("new.lua").exists());
+    }
+
+    #[test]
+    fn test_install_addon_quarantine() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        let quarantine = tempdir.path().join("quarantine");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        // `Foo` is the addon's own folder, `Bar` belongs to someone else.
+        for folder in &["Foo", "Bar"] {
+            fs::create_dir_all(to_directory.join(folder)).unwrap();
+            fs::write(to_directory.join(folder).join("old.lua"), "").unwrap();
+        }
+
+        let mut addon = Addon::empty("Foo");
+        addon.folders = vec![AddonFolder {
+            id: "Foo".to_string(),
+            path: to_directory.join("Foo"),
+            ..Default::default()
+        }];
+        write_zip(
+            &from_directory.join("Foo"),
+            &[
+                ("Foo/Foo.toc", b"## Title: Foo"),
+                ("Bar/Bar.toc", b"## Title: Bar"),
+            ],
+        );
+
+        let options = InstallOptions {
+            quarantine: Some(quarantine.clone()),
+            ..Default::default()
+        };
+        async_std::task::block_on(install_addon_with_options(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &options,
+            |_, _| {},
+        ))
+        .unwrap();
+
+        assert!(!to_directory.join("Foo").join("old.lua").exists());
+        assert!(!to_directory.join("Bar").join("old.lua").exists());
+        assert!(to_directory.join("Bar").join("Bar.toc").exists());
+
+        let entries = read_quarantine(&quarantine).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].addon, "Foo");
+        assert_eq!(entries[0].original, to_directory.join("Bar"));
+        assert!(entries[0].quarantined.join("old.lua").exists());
+        assert_eq!(fs::read_dir(&to_directory).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_install_addon_with_stats() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let addon = Addon::empty("Foo");
+        write_zip(
+            &from_directory.join("Foo"),
+            &[
+                ("Foo/Foo.toc", b"## Title: Foo"),
+                ("Foo/Media/Font.ttf", b"0123456789012345678901234567890"),
+                ("Foo_Options/Foo_Options.toc", b"## Title: Options"),
+                ("Skipped/Skipped.toc", b"## Title: Skipped"),
+            ],
+        );
+
+        let options = InstallOptions {
+            folders: Some(vec!["Foo".to_string(), "Foo_Options".to_string()]),
+            ..Default::default()
+        };
+        let (folders, stats) = async_std::task::block_on(install_addon_with_stats(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &options,
+        ))
+        .unwrap();
+
+        assert_eq!(folders.len(), 2);
+        assert_eq!(
+            stats,
+            InstallStats {
+                files: 3,
+                folders: 3,
+                total_bytes: 13 + 31 + 17,
+                largest_file: Some((Path::new("Foo").join("Media").join("Font.ttf"), 31)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_install_addon_merge() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        let profiles = to_directory.join("Data").join("Profiles");
+        fs::create_dir_all(&from_directory).unwrap();
+        fs::create_dir_all(&profiles).unwrap();
+        fs::write(to_directory.join("Data").join("Data.toc"), "## Version: 1").unwrap();
+        fs::write(profiles.join("mine.lua"), "-- user profile").unwrap();
+
+        let mut addon = Addon::empty("Data");
+        addon.folders.push(AddonFolder {
+            id: "Data".to_string(),
+            path: to_directory.join("Data"),
+            ..Default::default()
+        });
+        write_zip(
+            &from_directory.join("Data"),
+            &[
+                ("Data/Data.toc", b"## Version: 2"),
+                ("Data/Profiles/default.lua", b"-- default profile"),
+            ],
+        );
+
+        let options = InstallOptions {
+            merge: true,
+            ..Default::default()
+        };
+        async_std::task::block_on(install_addon_with_options(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &options,
+            |_, _| {},
+        ))
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(to_directory.join("Data").join("Data.toc")).unwrap(),
+            "## Version: 2"
+        );
+        assert!(profiles.join("default.lua").exists());
+        assert!(profiles.join("mine.lua").exists());
+    }
+
+    #[test]
+    fn test_install_addon_preserve() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        let folder = to_directory.join("Foo");
+        fs::create_dir_all(&from_directory).unwrap();
+        fs::create_dir_all(folder.join("Profiles")).unwrap();
+        fs::write(folder.join("Foo.toc"), "## Version: 1").unwrap();
+        fs::write(folder.join("Old.lua"), "").unwrap();
+        fs::write(folder.join("Config.user.lua"), "-- mine").unwrap();
+        fs::write(folder.join("Profiles").join("me.user.lua"), "-- me").unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        addon.folders.push(AddonFolder {
+            id: "Foo".to_string(),
+            path: folder.clone(),
+            ..Default::default()
+        });
+        write_zip(
+            &from_directory.join("Foo"),
+            &[
+                ("Foo/Foo.toc", b"## Version: 2"),
+                ("Foo/Config.user.lua", b"-- default"),
+            ],
+        );
+
+        let options = InstallOptions {
+            preserve: vec!["**/*.user.lua".to_string()],
+            ..Default::default()
+        };
+        async_std::task::block_on(install_addon_with_options(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &options,
+            |_, _| {},
+        ))
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(folder.join("Foo.toc")).unwrap(),
+            "## Version: 2"
+        );
+        assert!(!folder.join("Old.lua").exists());
+        assert_eq!(
+            fs::read_to_string(folder.join("Config.user.lua")).unwrap(),
+            "-- default"
+        );
+        assert_eq!(
+            fs::read_to_string(folder.join("Profiles").join("me.user.lua")).unwrap(),
+            "-- me"
+        );
+    }
+
+    #[test]
+    fn test_verify_against_manifest() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        write_zip(
+            &from_directory.join("Foo"),
+            &[
+                ("Foo/Foo.toc", b"## Title: Foo"),
+                ("Foo/Foo.lua", b"print('foo')"),
+                ("Foo_Libs/Lib.lua", b"-- lib"),
+            ],
+        );
+
+        let options = InstallOptions {
+            manifest: true,
+            ..Default::default()
+        };
+        let mut addon = Addon::empty("Foo");
+        async_std::task::block_on(install_addon_with_options(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &options,
+            |_, _| {},
+        ))
+        .unwrap();
+
+        addon.folders = ["Foo", "Foo_Libs"]
+            .iter()
+            .map(|id| AddonFolder {
+                id: id.to_string(),
+                path: to_directory.join(id),
+                ..Default::default()
+            })
+            .collect();
+        assert!(verify_against_manifest(&addon).unwrap().is_empty());
+
+        fs::write(to_directory.join("Foo").join("Foo.lua"), "").unwrap();
+        fs::remove_file(to_directory.join("Foo_Libs").join("Lib.lua")).unwrap();
+        assert_eq!(
+            verify_against_manifest(&addon).unwrap(),
+            vec![
+                to_directory.join("Foo").join("Foo.lua"),
+                to_directory.join("Foo_Libs").join("Lib.lua"),
+            ]
+        );
+
+        fs::remove_file(to_directory.join("Foo").join(MANIFEST_FILE)).unwrap();
+        assert!(matches!(
+            verify_against_manifest(&addon),
+            Err(FilesystemError::FileDoesntExist { .. })
+        ));
+    }
+
+    #[test]
+    fn test_install_addon_nested_archives() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+        fs::create_dir_all(&to_directory).unwrap();
+
+        let inner = tempdir.path().join("Inner.zip");
+        write_zip(&inner, &[("Foo/Foo.toc", b"## Version: 1")]);
+        let inner = fs::read(&inner).unwrap();
+
+        let install = |nested_archives: bool| {
+            write_zip(&from_directory.join("Foo"), &[("Foo/Foo.zip", &inner)]);
+
+            let options = InstallOptions {
+                nested_archives,
+                ..Default::default()
+            };
+            async_std::task::block_on(install_addon_with_options(
+                &Addon::empty("Foo"),
+                &from_directory,
+                &to_directory,
+                &options,
+                |_, _| {},
+            ))
+            .unwrap()
+        };
+
+        assert!(install(false).is_empty());
+        assert!(to_directory.join("Foo").join("Foo.zip").exists());
+
+        let folders = install(true);
+        assert_eq!(folders.len(), 1);
+        assert!(to_directory.join("Foo").join("Foo.toc").exists());
+        assert!(!to_directory.join("Foo").join("Foo.zip").exists());
+    }
+
+    #[test]
+    fn test_install_addon_staging_directory() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        let staging_directory = tempdir.path().join("staging");
+        fs::create_dir_all(&from_directory).unwrap();
+        fs::create_dir_all(&to_directory).unwrap();
+
+        write_zip(
+            &from_directory.join("Foo"),
+            &[("Foo/Foo.toc", b"## Version: 1"), ("Foo.txt", b"readme")],
+        );
+
+        let options = InstallOptions {
+            staging_directory: Some(staging_directory.clone()),
+            ..Default::default()
+        };
+        let folders = async_std::task::block_on(install_addon_with_options(
+            &Addon::empty("Foo"),
+            &from_directory,
+            &to_directory,
+            &options,
+            |_, _| {},
+        ))
+        .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert!(to_directory.join("Foo").join("Foo.toc").exists());
+        assert!(to_directory.join("Foo.txt").exists());
+        assert_eq!(fs::read_dir(&to_directory).unwrap().count(), 2);
+        assert_eq!(fs::read_dir(&staging_directory).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_install_addon_keeps_shared_folders() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+        fs::create_dir_all(to_directory.join("Foo")).unwrap();
+        fs::create_dir_all(to_directory.join("Libs")).unwrap();
+
+        let folder = |id: &str| AddonFolder {
+            id: id.to_string(),
+            path: to_directory.join(id),
+            ..Default::default()
+        };
+        let mut addon = Addon::empty("Foo");
+        addon.folders = vec![folder("Foo"), folder("Libs")];
+        let mut other = Addon::empty("Bar");
+        other.folders = vec![folder("Bar"), folder("Libs")];
+
+        let install = |installed_addons: Option<Vec<Addon>>| {
+            write_zip(
+                &from_directory.join("Foo"),
+                &[("Foo/Foo.toc", b"## Version: 2")],
+            );
+
+            let options = InstallOptions {
+                installed_addons,
+                ..Default::default()
+            };
+            async_std::task::block_on(install_addon_with_options(
+                &addon,
+                &from_directory,
+                &to_directory,
+                &options,
+                |_, _| {},
+            ))
+            .unwrap();
+        };
+
+        install(Some(vec![addon.clone(), other.clone()]));
+        assert!(to_directory.join("Libs").exists());
+
+        install(None);
+        assert!(!to_directory.join("Libs").exists());
+    }
+
+    #[test]
+    fn test_install_addon_provenance() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        addon.set_repository(
+            crate::repository::RepositoryPackage::from_repo_id(
+                Flavor::Retail,
+                RepositoryKind::WowI,
+                "1234".to_string(),
+            )
+            .unwrap(),
+        );
+
+        let install = |provenance: bool| {
+            write_zip(
+                &from_directory.join("Foo"),
+                &[("Foo/Foo.toc", b"## Version: 1.2.3")],
+            );
+            let options = InstallOptions {
+                provenance,
+                ..Default::default()
+            };
+            async_std::task::block_on(install_addon_with_options(
+                &addon,
+                &from_directory,
+                &to_directory,
+                &options,
+                |_, _| {},
+            ))
+            .unwrap();
+        };
+
+        install(false);
+        assert_eq!(read_provenance(&to_directory.join("Foo")).unwrap(), None);
+
+        install(true);
+        let provenance = read_provenance(&to_directory.join("Foo")).unwrap().unwrap();
+        assert_eq!(provenance.source, RepositoryKind::WowI);
+        assert_eq!(provenance.id, "1234");
+        assert_eq!(provenance.version.as_deref(), Some("1.2.3"));
+    }
+
+    #[test]
+    fn test_install_addon_checks_flavor() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let install = |flavor: Flavor, entries: &[(&str, &[u8])]| {
+            write_zip(&from_directory.join("Foo"), entries);
+            let options = InstallOptions {
+                flavor: Some(flavor),
+                ..Default::default()
+            };
+            async_std::task::block_on(install_addon_with_options(
+                &Addon::empty("Foo"),
+                &from_directory,
+                &to_directory,
+                &options,
+                |_, _| {},
+            ))
+        };
+
+        let retail_only: &[(&str, &[u8])] = &[("Foo/Foo.toc", b"## Interface: 90105")];
+        let result = install(Flavor::ClassicEra, retail_only);
+        assert!(matches!(
+            result,
+            Err(FilesystemError::IncompatibleFlavor { interfaces, .. }) if interfaces == vec![90105]
+        ));
+        assert!(!to_directory.join("Foo").exists());
+
+        assert!(install(Flavor::RetailPtr, retail_only).is_ok());
+
+        let multi_toc: &[(&str, &[u8])] = &[
+            ("Foo/Foo.toc", b"## Interface: 90105"),
+            ("Foo/Foo-BCC.toc", b"## Interface: 20502"),
+        ];
+        assert!(install(Flavor::ClassicTbc, multi_toc).is_ok());
+    }
+
+    #[test]
+    fn test_install_addon_flavor_folders_only() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let install = |flavor: Flavor, entries: &[(&str, &[u8])]| {
+            write_zip(&from_directory.join("Foo"), entries);
+            let options = InstallOptions {
+                flavor: Some(flavor),
+                flavor_folders_only: true,
+                ..Default::default()
+            };
+            async_std::task::block_on(install_addon_with_options(
+                &Addon::empty("Foo"),
+                &from_directory,
+                &to_directory,
+                &options,
+                |_, _| {},
+            ))
+            .unwrap()
+        };
+
+        let multi_flavor: &[(&str, &[u8])] = &[
+            ("Foo/Foo.toc", b"## Interface: 90105"),
+            ("Foo_Classic/Foo_Classic.toc", b"## Interface: 11401"),
+            ("Foo_Bar/Foo_Bar.toc", b"## Interface: 11401"),
+            ("Foo_Libs/Lib.lua", b""),
+        ];
+
+        install(Flavor::Retail, multi_flavor);
+        assert!(to_directory.join("Foo").exists());
+        assert!(!to_directory.join("Foo_Classic").exists());
+        assert!(!to_directory.join("Foo_Bar").exists());
+        assert!(to_directory.join("Foo_Libs").exists());
+
+        fs::remove_dir_all(&to_directory).unwrap();
+        install(Flavor::ClassicEra, multi_flavor);
+        assert!(!to_directory.join("Foo").exists());
+        assert!(to_directory.join("Foo_Classic").exists());
+        assert!(to_directory.join("Foo_Bar").exists());
+
+        // Nothing is built for retail by name, so everything is installed.
+        fs::remove_dir_all(&to_directory).unwrap();
+        install(
+            Flavor::Retail,
+            &[
+                ("Foo_Classic/Foo_Classic.toc", b"## Interface: 90105"),
+                ("Foo_BCC/Foo_BCC.toc", b"## Interface: 90105"),
+            ],
+        );
+        assert!(to_directory.join("Foo_Classic").exists());
+        assert!(to_directory.join("Foo_BCC").exists());
+    }
+
+    #[test]
+    fn test_install_addon_backslash_separators() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        write_zip(
+            &from_directory.join("Foo"),
+            &[
+                ("Foo\\Foo.toc", b"## Title: Foo"),
+                ("Foo\\Libs\\Lib.lua", b"print('lib')"),
+            ],
+        );
+
+        let folders = async_std::task::block_on(install_addon(
+            &Addon::empty("Foo"),
+            &from_directory,
+            &to_directory,
+        ))
+        .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].id, "Foo");
+        assert!(to_directory
+            .join("Foo")
+            .join("Libs")
+            .join("Lib.lua")
+            .exists());
+        assert_eq!(fs::read_dir(&to_directory).unwrap().count(), 1);
+
+        write_zip(&from_directory.join("Foo"), &[("..\\evil.txt", b"pwned")]);
+        let result = async_std::task::block_on(install_addon(
+            &Addon::empty("Foo"),
+            &from_directory,
+            &to_directory,
+        ));
+        assert!(matches!(
+            result,
+            Err(FilesystemError::UnsafeArchivePath { .. })
+        ));
+    }
+
+    #[test]
+    fn test_install_addon_absolute_entries() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+        fs::create_dir_all(&to_directory).unwrap();
+
+        write_zip(
+            &from_directory.join("Foo"),
+            &[
+                ("/Foo/Foo.toc", b"## Title: Foo"),
+                ("C:\\Foo\\Foo.lua", b"print('foo')"),
+            ],
+        );
+
+        let folders = async_std::task::block_on(install_addon(
+            &Addon::empty("Foo"),
+            &from_directory,
+            &to_directory,
+        ))
+        .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].id, "Foo");
+        assert!(to_directory.join("Foo").join("Foo.toc").exists());
+        assert!(to_directory.join("Foo").join("Foo.lua").exists());
+        assert!(!Path::new("/Foo").join("Foo.toc").exists());
+    }
+
+    #[test]
+    fn test_install_addon_rejects_path_traversal() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+        fs::create_dir_all(&to_directory).unwrap();
+
+        let addon = Addon::empty("Evil");
+        write_zip(
+            &from_directory.join("Evil"),
+            &[
+                ("Evil/Evil.toc", b"## Title: Evil"),
+                ("../evil.txt", b"pwned"),
+            ],
+        );
+
+        let result =
+            async_std::task::block_on(install_addon(&addon, &from_directory, &to_directory));
+
+        assert!(matches!(
+            result,
+            Err(FilesystemError::UnsafeArchivePath { name }) if name == "../evil.txt"
+        ));
+        assert!(!to_directory.join("evil.txt").exists());
+        assert!(!tempdir.path().join("evil.txt").exists());
+        assert!(!to_directory.join("Evil").exists());
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_delete_addons_dry_run() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
 
-/// Deletes all saved varaible files correlating to `[AddonFolder]`.
-pub fn delete_saved_variables(addon_folders: &[AddonFolder], wtf_path: &Path) -> Result<()> {
-    for entry in WalkDir::new(&wtf_path)
-        .into_iter()
-        .filter_map(std::result::Result::ok)
-    {
-        let path = entry.path();
-        let parent_name = path
-            .parent()
-            .and_then(|a| a.file_name())
-            .and_then(|a| a.to_str());
+        fs::create_dir_all(root.join("Foo")).unwrap();
+        fs::create_dir_all(root.join("FooOptions")).unwrap();
 
-        if parent_name == Some("SavedVariables") {
-            let file_name = path
-                .file_name()
-                .and_then(|a| a.to_str())
-                .map(|a| a.trim_end_matches(".bak").trim_end_matches(".lua"));
+        let folders: Vec<_> = ["Foo", "FooOptions", "Missing", "Foo"]
+            .iter()
+            .map(|id| AddonFolder {
+                id: id.to_string(),
+                path: root.join(id),
+                ..Default::default()
+            })
+            .collect();
 
-            // NOTE: Will reject "Foobar_<invalid utf8>".
-            if let Some(file_name_str) = file_name {
-                for folder in addon_folders {
-                    if file_name_str == folder.id {
-                        remove_file(path)?;
-                    }
-                }
-            }
-        }
+        let paths = delete_addons_dry_run(&folders).unwrap();
+
+        assert_eq!(paths, vec![root.join("Foo"), root.join("FooOptions")]);
+        assert!(root.join("Foo").exists());
+        assert!(root.join("FooOptions").exists());
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_case_insensitive_folders() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+        fs::create_dir_all(to_directory.join("myaddon")).unwrap();
+        fs::write(to_directory.join("myaddon").join("old.lua"), "").unwrap();
 
-/// Unzips an `Addon` archive, and once that is done, it moves the content
-/// to the `to_directory`.
-/// At the end it will cleanup and remove the archive.
-pub async fn install_addon(
-    addon: &Addon,
-    from_directory: &Path,
-    to_directory: &Path,
-) -> Result<Vec<AddonFolder>> {
-    let zip_path = from_directory.join(&addon.primary_folder_id);
-    let mut zip_file = std::fs::File::open(&zip_path)?;
-    let mut archive = zip::ZipArchive::new(&mut zip_file)?;
+        let mut addon = Addon::empty("MyAddon");
+        addon.folders = vec![AddonFolder {
+            id: "MyAddon".to_string(),
+            path: to_directory.join("MyAddon"),
+            ..Default::default()
+        }];
 
-    // Remove all existing top level addon folders.
-    for folder in addon.folders.iter() {
-        let path = &folder.path;
-        if path.exists() {
-            remove_dir_all(path)?;
+        write_zip(
+            &from_directory.join("MyAddon"),
+            &[("MyAddon/MyAddon.toc", b"## Title: MyAddon")],
+        );
+        let options = InstallOptions {
+            case_insensitive: true,
+            cleanup: false,
+            ..Default::default()
+        };
+        async_std::task::block_on(install_addon_with_options(
+            &addon,
+            &from_directory,
+            &to_directory,
+            &options,
+            |_, _| {},
+        ))
+        .unwrap();
+
+        let entries: Vec<_> = fs::read_dir(&to_directory)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec!["MyAddon"]);
+        assert!(!to_directory.join("MyAddon").join("old.lua").exists());
+
+        fs::rename(to_directory.join("MyAddon"), to_directory.join("MYADDON")).unwrap();
+        delete_addons_with_case(&addon.folders, true).unwrap();
+        assert_eq!(fs::read_dir(&to_directory).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_addon_size() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+
+        fs::create_dir_all(root.join("Foo").join("Libs")).unwrap();
+        fs::create_dir_all(root.join("FooOptions")).unwrap();
+        fs::write(root.join("Foo").join("Foo.lua"), [0; 100]).unwrap();
+        fs::write(root.join("Foo").join("Libs").join("Lib.lua"), [0; 20]).unwrap();
+        fs::write(root.join("FooOptions").join("Options.lua"), [0; 3]).unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        addon.folders = ["Foo", "FooOptions", "Missing"]
+            .iter()
+            .map(|id| AddonFolder {
+                id: id.to_string(),
+                path: root.join(id),
+                ..Default::default()
+            })
+            .collect();
+
+        assert_eq!(addon_size(&addon).unwrap(), 123);
+    }
+
+    #[test]
+    fn test_backup_and_restore_addon() {
+        let tempdir = tempdir().unwrap();
+        let addons_dir = tempdir.path().join("AddOns");
+        let backup_dir = tempdir.path().join("Backups");
+
+        fs::create_dir_all(addons_dir.join("Foo").join("Libs")).unwrap();
+        fs::create_dir_all(addons_dir.join("FooOptions")).unwrap();
+        fs::write(addons_dir.join("Foo").join("Foo.toc"), "## Title: Foo").unwrap();
+        fs::write(addons_dir.join("Foo").join("Libs").join("Lib.lua"), "old").unwrap();
+        fs::write(addons_dir.join("FooOptions").join("FooOptions.toc"), "").unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        addon.folders = ["Foo", "FooOptions", "Missing"]
+            .iter()
+            .map(|id| AddonFolder {
+                id: id.to_string(),
+                path: addons_dir.join(id),
+                ..Default::default()
+            })
+            .collect();
+
+        let archive_path = backup_addon(&addon, &backup_dir).unwrap();
+        let file_name = archive_path.file_name().unwrap().to_str().unwrap();
+        assert!(file_name.starts_with("Foo-") && file_name.ends_with(".zip"));
+
+        // Simulate a broken update.
+        fs::write(addons_dir.join("Foo").join("Libs").join("Lib.lua"), "new").unwrap();
+        fs::remove_dir_all(addons_dir.join("FooOptions")).unwrap();
+
+        let folders =
+            async_std::task::block_on(restore_addon_backup(&addon, &archive_path, &addons_dir))
+                .unwrap();
+
+        assert_eq!(folders.len(), 2);
+        assert_eq!(
+            fs::read_to_string(addons_dir.join("Foo").join("Libs").join("Lib.lua")).unwrap(),
+            "old"
+        );
+        assert!(addons_dir
+            .join("FooOptions")
+            .join("FooOptions.toc")
+            .exists());
+        assert!(archive_path.exists());
+    }
+
+    #[test]
+    fn test_prune_backups() {
+        let tempdir = tempdir().unwrap();
+        let backup_dir = tempdir.path();
+
+        for name in &[
+            "Foo-2021-01-01_12-00-00.zip",
+            "Foo-2021-03-01_12-00-00.zip",
+            "Foo-2021-02-01_12-00-00.zip",
+            "Foo-Bar-2021-01-01_12-00-00.zip",
+            "Foo-latest.zip",
+            "Baz-2021-01-01_12-00-00.txt",
+        ] {
+            fs::write(backup_dir.join(name), "").unwrap();
         }
+
+        assert_eq!(
+            prune_backups(backup_dir, 2).unwrap(),
+            vec![backup_dir.join("Foo-2021-01-01_12-00-00.zip")]
+        );
+        assert!(prune_backups(backup_dir, 2).unwrap().is_empty());
+
+        assert_eq!(
+            prune_backups(backup_dir, 0).unwrap(),
+            vec![
+                backup_dir.join("Foo-2021-02-01_12-00-00.zip"),
+                backup_dir.join("Foo-2021-03-01_12-00-00.zip"),
+                backup_dir.join("Foo-Bar-2021-01-01_12-00-00.zip"),
+            ]
+        );
+        assert!(backup_dir.join("Foo-latest.zip").exists());
+        assert!(backup_dir.join("Baz-2021-01-01_12-00-00.txt").exists());
     }
 
-    // Get all new top level folders
-    let new_top_level_folders = archive
-        .file_names()
-        .filter_map(|name| name.split('/').next())
-        .collect::<HashSet<_>>();
+    #[test]
+    fn test_cleanup_stale_staging() {
+        let tempdir = tempdir().unwrap();
+        let to_directory = tempdir.path();
+
+        for folder in &[
+            "Foo",
+            ".ajour-staging-Foo",
+            ".ajour-staging-old-Foo",
+            ".ajour-staging-old-Bar",
+        ] {
+            fs::create_dir_all(to_directory.join(folder)).unwrap();
+            fs::write(to_directory.join(folder).join("Foo.toc"), "").unwrap();
+        }
+
+        assert_eq!(
+            cleanup_stale_staging(to_directory).unwrap(),
+            vec![
+                to_directory.join(".ajour-staging-Foo"),
+                to_directory.join(".ajour-staging-old-Bar"),
+                to_directory.join(".ajour-staging-old-Foo"),
+            ]
+        );
+
+        let mut folders: Vec<_> = fs::read_dir(to_directory)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        folders.sort();
+        assert_eq!(folders, vec!["Bar", "Foo"]);
+        assert!(to_directory.join("Bar").join("Foo.toc").exists());
+    }
+
+    #[test]
+    fn test_installed_version() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+
+        fs::create_dir_all(root.join("Foo")).unwrap();
+        fs::create_dir_all(root.join("Bar")).unwrap();
+        fs::create_dir_all(root.join("Baz")).unwrap();
+        fs::write(root.join("Foo").join("Foo.toc"), "## Version: 1.2.3").unwrap();
+        fs::write(root.join("Foo").join("Foo-BCC.toc"), "## Version: 0.1").unwrap();
+        fs::write(root.join("Bar").join("Bar_Mainline.toc"), "## Version: v2").unwrap();
+        fs::write(root.join("Baz").join("Baz.toc"), "## Title: Baz").unwrap();
+
+        assert_eq!(
+            installed_version(&root.join("Foo")).unwrap().as_deref(),
+            Some("1.2.3")
+        );
+        assert_eq!(
+            installed_version(&root.join("Bar")).unwrap().as_deref(),
+            Some("v2")
+        );
+        assert_eq!(installed_version(&root.join("Baz")).unwrap(), None);
+        assert!(installed_version(&root.join("Missing")).is_err());
+    }
+
+    #[test]
+    fn test_needs_repair() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+
+        fs::create_dir_all(root.join("Foo")).unwrap();
+        fs::create_dir_all(root.join("Foo_Options")).unwrap();
+        fs::write(root.join("Foo").join("Foo.toc"), "").unwrap();
+        fs::write(root.join("Foo_Options").join("Foo_Options-BCC.toc"), "").unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        addon.folders = ["Foo", "Foo_Options"]
+            .iter()
+            .map(|id| AddonFolder {
+                id: id.to_string(),
+                path: root.join(id),
+                ..Default::default()
+            })
+            .collect();
+        assert!(!needs_repair(&addon).unwrap());
+
+        fs::remove_file(root.join("Foo_Options").join("Foo_Options-BCC.toc")).unwrap();
+        assert!(needs_repair(&addon).unwrap());
+
+        fs::remove_dir_all(root.join("Foo_Options")).unwrap();
+        assert!(needs_repair(&addon).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_post_install_command() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+        let args_path = root.join("args");
+
+        let command = root.join("post_install.sh");
+        fs::write(
+            &command,
+            format!("#!/bin/sh\necho \"$@\" > {:?}\nexit 1\n", args_path),
+        )
+        .unwrap();
+        fs::set_permissions(&command, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let addon_folders: Vec<_> = ["Foo", "Foo_Options"]
+            .iter()
+            .map(|id| AddonFolder {
+                id: id.to_string(),
+                path: root.join(id),
+                ..Default::default()
+            })
+            .collect();
+
+        // A failing command doesn't panic nor fail anything.
+        run_post_install_command(&command, "Foo", &addon_folders);
+        run_post_install_command(&root.join("missing"), "Foo", &addon_folders);
+
+        assert_eq!(
+            fs::read_to_string(&args_path).unwrap().trim(),
+            format!(
+                "Foo {} {}",
+                root.join("Foo").display(),
+                root.join("Foo_Options").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_scan_integrity() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+        let folder = root.join("Foo");
+
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("Foo.toc"), "").unwrap();
+        fs::write(
+            folder.join("Foo.lua"),
+            r#"
+            -- function in a comment
+            --[==[ if in a long comment ]==]
+            local s = "do" .. 'repeat' .. [[function]]
+            local function foo(t)
+                for i = 1, #t do
+                    if t[i] then print(i) end
+                end
+                repeat until true
+            end
+            "#,
+        )
+        .unwrap();
+
+        let mut addon = Addon::empty("Foo");
+        addon.folders = vec![AddonFolder {
+            id: "Foo".to_string(),
+            path: folder.clone(),
+            ..Default::default()
+        }];
+        assert!(scan_integrity(&addon).unwrap().is_empty());
+        assert!(!needs_repair(&addon).unwrap());
+
+        fs::write(folder.join("Empty.lua"), "").unwrap();
+        fs::write(
+            folder.join("Cut.lua"),
+            "local function foo()\n  if bar then",
+        )
+        .unwrap();
+
+        let mut issues = scan_integrity(&addon).unwrap();
+        issues.sort_by_key(|issue| issue.to_string());
+        assert_eq!(
+            issues,
+            vec![
+                IntegrityIssue::UnclosedBlocks {
+                    path: folder.join("Cut.lua"),
+                    count: 2,
+                },
+                IntegrityIssue::Empty {
+                    path: folder.join("Empty.lua"),
+                },
+            ]
+        );
+        assert!(needs_repair(&addon).unwrap());
+    }
+
+    #[test]
+    fn test_dedup_shared_libs() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+
+        let addons = ["Foo", "Bar"]
+            .iter()
+            .map(|id| {
+                let lib = root.join(id).join("Libs").join("LibStub");
+                fs::create_dir_all(lib.join("Sub")).unwrap();
+                fs::write(lib.join("LibStub.lua"), "-- LibStub").unwrap();
+                fs::write(lib.join("Sub").join("Sub.lua"), "-- Sub").unwrap();
+                fs::write(root.join(id).join(format!("{}.toc", id)), *id).unwrap();
+
+                let mut addon = Addon::empty(id);
+                addon.folders = vec![AddonFolder {
+                    id: id.to_string(),
+                    path: root.join(id),
+                    ..Default::default()
+                }];
+                addon
+            })
+            .collect::<Vec<_>>();
+
+        let report = dedup_shared_libs(&addons).unwrap();
+        assert_eq!(
+            report.duplicates,
+            vec![DuplicateFolders {
+                paths: vec![root.join("Bar").join("Libs"), root.join("Foo").join("Libs"),],
+                size: 16,
+            }]
+        );
+        assert_eq!(report.savings(), 16);
+    }
+
+    #[test]
+    fn test_primary_toc_path() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("Foo");
+        fs::create_dir_all(&path).unwrap();
 
-    // Remove all new top level addon folders.
-    for folder in new_top_level_folders {
-        let path = to_directory.join(&folder);
+        fs::write(path.join("Bar.toc"), "").unwrap();
+        assert_eq!(primary_toc_path(&path, "Foo"), Some(path.join("Bar.toc")));
 
-        if path.exists() {
-            let _ = std::fs::remove_dir_all(path);
+        fs::write(path.join("Foo_Mainline.toc"), "").unwrap();
+        assert_eq!(
+            primary_toc_path(&path, "Foo"),
+            Some(path.join("Foo_Mainline.toc"))
+        );
+
+        fs::write(path.join("Foo.toc"), "").unwrap();
+        assert_eq!(primary_toc_path(&path, "Foo"), Some(path.join("Foo.toc")));
+
+        let path = tempdir.path().join("BigFolderName");
+        fs::create_dir_all(&path).unwrap();
+        assert_eq!(primary_toc_path(&path, "BigFolderName"), None);
+
+        fs::write(path.join("Short_Mainline.toc"), "").unwrap();
+        assert_eq!(
+            primary_toc_path(&path, "BigFolderName"),
+            Some(path.join("Short_Mainline.toc"))
+        );
+
+        fs::write(path.join("Short.toc"), "").unwrap();
+        assert_eq!(
+            primary_toc_path(&path, "BigFolderName"),
+            Some(path.join("Short.toc"))
+        );
+
+        let path = tempdir.path().join("插件名");
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("插件名.toc"), "").unwrap();
+        fs::write(path.join("其他插件.toc"), "").unwrap();
+        assert_eq!(
+            primary_toc_path(&path, "插件名"),
+            Some(path.join("插件名.toc"))
+        );
+    }
+
+    #[test]
+    fn test_strip_toc_flavor_suffix() {
+        assert_eq!(strip_toc_flavor_suffix("Foo_Mainline"), "Foo");
+        assert_eq!(strip_toc_flavor_suffix("Foo-BCC"), "Foo");
+        assert_eq!(strip_toc_flavor_suffix("插件名"), "插件名");
+        assert_eq!(strip_toc_flavor_suffix("插件名_Classic"), "插件名");
+        assert_eq!(strip_toc_flavor_suffix("Über"), "Über");
+    }
+
+    #[test]
+    fn test_detect_toc_files() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+
+        fs::create_dir_all(root.join("Foo").join("Libs").join("Lib")).unwrap();
+        fs::create_dir_all(root.join("Bar")).unwrap();
+        fs::write(root.join("Root.toc"), "").unwrap();
+        fs::write(root.join("Foo").join("Foo.toc"), "").unwrap();
+        fs::write(root.join("Foo").join("Foo_Mainline.toc"), "").unwrap();
+        fs::write(root.join("Foo").join("Foo.lua"), "").unwrap();
+        fs::write(
+            root.join("Foo").join("Libs").join("Lib").join("Lib.toc"),
+            "",
+        )
+        .unwrap();
+        fs::write(root.join("Bar").join("Bar.toc"), "").unwrap();
+
+        assert_eq!(
+            detect_toc_files(root),
+            vec![
+                root.join("Bar").join("Bar.toc"),
+                root.join("Foo").join("Foo.toc"),
+                root.join("Foo").join("Foo_Mainline.toc"),
+            ]
+        );
+        assert!(detect_toc_files(&root.join("Missing")).is_empty());
+    }
+
+    #[test]
+    fn test_scan_addons_parallel() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+
+        for id in &["Foo", "Bar", "Baz"] {
+            fs::create_dir_all(root.join(id)).unwrap();
+            fs::write(root.join(id).join(format!("{}.toc", id)), "## Title: Test").unwrap();
         }
+        fs::write(root.join("Foo").join("Foo_Mainline.toc"), "## Title: Test").unwrap();
+        fs::create_dir_all(root.join("Empty")).unwrap();
+
+        let ids: Vec<_> = scan_addons_parallel(root)
+            .into_iter()
+            .map(|f| f.id)
+            .collect();
+        assert_eq!(ids, vec!["Bar", "Baz", "Foo"]);
+        assert!(scan_addons_parallel(&root.join("Missing")).is_empty());
     }
 
-    let mut toc_files = vec![];
+    #[test]
+    fn test_verify_install() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
 
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        #[allow(deprecated)]
-        let path = to_directory.join(file.sanitized_name());
+        fs::create_dir_all(root.join("Foo")).unwrap();
+        fs::write(root.join("Foo").join("Foo_Mainline.toc"), "").unwrap();
 
-        if let Some(ext) = path.extension() {
-            if let Ok(remainder) = path.strip_prefix(to_directory) {
-                if ext == "toc" && remainder.components().count() == 2 {
-                    toc_files.push(path.clone());
-                }
-            }
+        let folder = |id: &str| AddonFolder {
+            id: id.to_string(),
+            path: root.join(id),
+            ..Default::default()
+        };
+        assert!(verify_install(&[folder("Foo")]).is_ok());
+
+        assert!(matches!(
+            verify_install(&[folder("Foo"), folder("Bar")]),
+            Err(FilesystemError::FileDoesntExist { .. })
+        ));
+
+        fs::create_dir_all(root.join("Bar")).unwrap();
+        assert!(matches!(
+            verify_install(&[folder("Foo"), folder("Bar")]),
+            Err(FilesystemError::MissingToc { id, .. }) if id == "Bar"
+        ));
+    }
+
+    #[test]
+    fn test_ignored_folders() {
+        // Ids are unique to this test, as the ignore list is global.
+        set_ignored_folders(vec!["HandMaintainedUI".to_string()]);
+
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+
+        let mut addon = Addon::empty("HandMaintained");
+        for id in &["HandMaintained", "HandMaintainedUI"] {
+            let path = to_directory.join(id);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("custom.lua"), "-- hand edited").unwrap();
+            addon.folders.push(AddonFolder {
+                id: id.to_string(),
+                path,
+                ..Default::default()
+            });
         }
+        write_zip(
+            &from_directory.join("HandMaintained"),
+            &[
+                ("HandMaintained/HandMaintained.toc", b"## Title: Suite"),
+                ("HandMaintainedUI/HandMaintainedUI.toc", b"## Title: UI"),
+            ],
+        );
 
-        if file.is_dir() {
-            std::fs::create_dir_all(&path)?;
-        } else {
-            if let Some(p) = path.parent() {
-                if !p.exists() {
-                    std::fs::create_dir_all(&p)?;
-                }
+        let folders =
+            async_std::task::block_on(install_addon(&addon, &from_directory, &to_directory))
+                .unwrap();
+
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].id, "HandMaintained");
+        assert!(to_directory
+            .join("HandMaintainedUI")
+            .join("custom.lua")
+            .exists());
+
+        assert!(find_orphaned_folders(&to_directory, &folders)
+            .unwrap()
+            .is_empty());
+        assert!(find_orphaned_libraries(&to_directory, &folders)
+            .unwrap()
+            .is_empty());
+
+        delete_addons(&addon.folders, false).unwrap();
+        assert!(!to_directory.join("HandMaintained").exists());
+        assert!(to_directory.join("HandMaintainedUI").exists());
+    }
+
+    #[test]
+    fn test_find_trash_program() {
+        let tempdir = tempdir().unwrap();
+        let empty = tempdir.path().join("empty");
+        let bin = tempdir.path().join("bin");
+        fs::create_dir_all(&empty).unwrap();
+        fs::create_dir_all(bin.join("gio")).unwrap();
+
+        let path = std::env::join_paths([&empty, &bin]).unwrap();
+        assert_eq!(find_trash_program(&path), None);
+
+        fs::write(bin.join("kioclient5"), "").unwrap();
+        assert_eq!(find_trash_program(&path), Some(bin.join("kioclient5")));
+
+        fs::write(empty.join("gio"), "").unwrap();
+        assert_eq!(find_trash_program(&path), Some(empty.join("gio")));
+    }
+
+    #[test]
+    fn test_find_orphaned_folders() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path();
+
+        for (folder, toc) in &[
+            ("Known", Some("Known.toc")),
+            ("Orphan", Some("Orphan-Mainline.toc")),
+            ("LibStub", None),
+            (".ajour-staging-Foo", None),
+        ] {
+            fs::create_dir_all(root.join(folder)).unwrap();
+            if let Some(toc) = toc {
+                fs::write(root.join(folder).join(toc), "## Title: Test").unwrap();
             }
-            let mut outfile = std::fs::File::create(&path)?;
-            std::io::copy(&mut file, &mut outfile)?;
         }
+
+        let known = vec![AddonFolder {
+            id: "Known".to_string(),
+            ..Default::default()
+        }];
+
+        let orphans = find_orphaned_folders(root, &known).unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].id, "Orphan");
+
+        let libraries = find_orphaned_libraries(root, &known).unwrap();
+        assert_eq!(libraries, vec![root.join("LibStub")]);
     }
 
-    // Cleanup
-    std::fs::remove_file(&zip_path)?;
+    #[test]
+    fn test_uninstall_addon() {
+        let tempdir = tempdir().unwrap();
+        let addons_dir = tempdir.path().join("AddOns");
+        let wtf = tempdir.path().join("WTF");
+        let sv = wtf.join("Account").join("ACCOUNT").join("SavedVariables");
+        fs::create_dir_all(addons_dir.join("Foo")).unwrap();
+        fs::create_dir_all(&sv).unwrap();
+        fs::write(sv.join("Foo.lua"), "").unwrap();
 
-    let mut addon_folders: Vec<_> = toc_files.iter().filter_map(|p| parse_toc_path(p)).collect();
-    addon_folders.sort();
-    // Needed since multi-toc can now insert folder name more than once
-    addon_folders.dedup();
+        let mut addon = Addon::empty("Foo");
+        addon.folders = vec![AddonFolder {
+            id: "Foo".to_string(),
+            path: addons_dir.join("Foo"),
+            ..Default::default()
+        }];
 
-    Ok(addon_folders)
-}
+        uninstall_addon(&addon, None).unwrap();
+        assert!(!addons_dir.join("Foo").exists());
+        assert!(sv.join("Foo.lua").exists());
 
-#[cfg(test)]
-mod test {
-    use std::fs;
+        uninstall_addon(&addon, Some(&wtf)).unwrap();
+        assert!(!sv.join("Foo.lua").exists());
 
-    use tempfile::tempdir;
+        // A directory can't be removed as a file, so this step fails.
+        fs::create_dir_all(sv.join("Foo.lua")).unwrap();
+        let result = uninstall_addon(&addon, Some(&wtf));
+        assert!(matches!(
+            result,
+            Err(FilesystemError::Uninstall { errors, .. }) if errors.len() == 1
+        ));
+    }
 
-    use super::*;
+    #[test]
+    fn test_uninstall_addons() {
+        let tempdir = tempdir().unwrap();
+        let addons_dir = tempdir.path().join("AddOns");
+        let wtf = tempdir.path().join("WTF");
+        let sv = wtf.join("Account").join("ACCOUNT").join("SavedVariables");
+        fs::create_dir_all(&sv).unwrap();
+
+        let addons = ["Foo", "Bar"]
+            .iter()
+            .map(|id| {
+                fs::create_dir_all(addons_dir.join(id)).unwrap();
+                fs::write(sv.join(format!("{}.lua", id)), "").unwrap();
+
+                let mut addon = Addon::empty(id);
+                addon.folders = vec![AddonFolder {
+                    id: id.to_string(),
+                    path: addons_dir.join(id),
+                    ..Default::default()
+                }];
+                addon
+            })
+            .collect::<Vec<_>>();
+        fs::write(sv.join("Baz.lua"), "").unwrap();
+
+        uninstall_addons(&addons, Some(&wtf)).unwrap();
+        assert!(!addons_dir.join("Foo").exists());
+        assert!(!addons_dir.join("Bar").exists());
+        assert!(!sv.join("Foo.lua").exists());
+        assert!(!sv.join("Bar.lua").exists());
+        assert!(sv.join("Baz.lua").exists());
+    }
+
+    #[test]
+    fn test_find_stale_sv_backups() {
+        let tempdir = tempdir().unwrap();
+        let wtf = tempdir.path();
+        let sv = wtf.join("Account").join("ACCOUNT").join("SavedVariables");
+        fs::create_dir_all(&sv).unwrap();
+
+        let now = std::time::SystemTime::now();
+        let write = |name: &str, age: u64| {
+            let file = fs::File::create(sv.join(name)).unwrap();
+            file.set_modified(now - std::time::Duration::from_secs(age))
+                .unwrap();
+        };
+        write("Stale.lua", 0);
+        write("Stale.lua.bak", 60);
+        write("Fresh.lua", 60);
+        write("Fresh.lua.bak", 0);
+        write("Orphan.lua.bak", 60);
+
+        assert_eq!(
+            find_stale_sv_backups(wtf).unwrap(),
+            vec![sv.join("Stale.lua.bak")]
+        );
+    }
 
     #[test]
     fn test_delete_saved_variables() {
@@ -192,7 +4984,7 @@ mod test {
             files.push(path);
         }
 
-        delete_saved_variables(&folders, root).unwrap();
+        delete_saved_variables(&folders, root, None, None).unwrap();
 
         let mut exists = 0;
         for file in files {
@@ -203,4 +4995,156 @@ mod test {
 
         assert_eq!(exists, 1);
     }
+
+    #[test]
+    fn test_backup_and_restore_saved_variables() {
+        let folders = vec![AddonFolder {
+            id: "AddonA".to_string(),
+            ..Default::default()
+        }];
+
+        let tempdir = tempdir().unwrap();
+        let wtf = tempdir.path();
+        let sv = wtf.join("Account").join("ACCOUNT").join("SavedVariables");
+        fs::create_dir_all(&sv).unwrap();
+        fs::write(sv.join("AddonA.lua"), "A = true").unwrap();
+        fs::write(sv.join("AddonA.lua.bak"), "A = false").unwrap();
+        fs::write(sv.join("AddonB.lua"), "B = true").unwrap();
+
+        let backup_path = backup_saved_variables(&folders, wtf).unwrap();
+
+        assert!(backup_path.starts_with(wtf.join("_ajour_sv_backup")));
+        assert!(!sv.join("AddonA.lua").exists());
+        assert!(!sv.join("AddonA.lua.bak").exists());
+        assert!(sv.join("AddonB.lua").exists());
+
+        // Backups must not be picked up as saved variables themselves.
+        delete_saved_variables(&folders, wtf, None, None).unwrap();
+
+        // Newer config is kept, unless asked to overwrite.
+        fs::write(sv.join("AddonA.lua"), "A = newer").unwrap();
+        restore_saved_variables(&folders, &backup_path, wtf, false).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(sv.join("AddonA.lua")).unwrap(),
+            "A = newer"
+        );
+        assert_eq!(
+            fs::read_to_string(sv.join("AddonA.lua.bak")).unwrap(),
+            "A = false"
+        );
+
+        restore_saved_variables(&folders, &backup_path, wtf, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(sv.join("AddonA.lua")).unwrap(),
+            "A = true"
+        );
+    }
+
+    #[test]
+    fn test_delete_saved_variables_scoped() {
+        let folders = vec![AddonFolder {
+            id: "AddonA".to_string(),
+            ..Default::default()
+        }];
+
+        let tempdir = tempdir().unwrap();
+        let wtf = tempdir.path();
+        let account = wtf.join("Account").join("ACCOUNT");
+        let other_account = wtf.join("Account").join("OTHER");
+
+        let sv_dirs = [
+            account.join("SavedVariables"),
+            account.join("Realm").join("Alice").join("SavedVariables"),
+            account.join("Realm").join("Bob").join("SavedVariables"),
+            other_account.join("SavedVariables"),
+        ];
+        for dir in sv_dirs.iter() {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("AddonA.lua"), "").unwrap();
+        }
+        let exists = || {
+            sv_dirs
+                .iter()
+                .map(|dir| dir.join("AddonA.lua").exists())
+                .collect::<Vec<_>>()
+        };
+
+        delete_saved_variables(&folders, wtf, Some("ACCOUNT"), Some("Alice")).unwrap();
+        assert_eq!(exists(), vec![true, false, true, true]);
+
+        delete_saved_variables(&folders, wtf, Some("ACCOUNT"), None).unwrap();
+        assert_eq!(exists(), vec![false, false, false, true]);
+    }
+
+    #[test]
+    fn test_find_saved_variables() {
+        let folders = vec![AddonFolder {
+            id: "AddonA".to_string(),
+            ..Default::default()
+        }];
+
+        let tempdir = tempdir().unwrap();
+        let wtf = tempdir.path();
+        let account = wtf.join("Account").join("ACCOUNT");
+        let bob = account.join("Realm").join("Bob").join("SavedVariables");
+        let alice = account.join("Realm").join("Alice").join("SavedVariables");
+        let account_sv = account.join("SavedVariables");
+        for dir in [&bob, &alice, &account_sv].iter() {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("AddonA.lua"), "").unwrap();
+            fs::write(dir.join("AddonB.lua"), "").unwrap();
+        }
+        fs::write(alice.join("AddonA.lua.bak"), "").unwrap();
+
+        let files = find_saved_variables(&folders, wtf).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                account_sv.join("AddonA.lua"),
+                alice.join("AddonA.lua"),
+                alice.join("AddonA.lua.bak"),
+                bob.join("AddonA.lua"),
+            ]
+        );
+
+        // Nothing is removed.
+        assert!(files.iter().all(|path| path.exists()));
+    }
+
+    #[test]
+    fn test_delete_saved_variables_with_progress() {
+        let folders = vec![AddonFolder {
+            id: "AddonA".to_string(),
+            ..Default::default()
+        }];
+
+        let tempdir = tempdir().unwrap();
+        let wtf = tempdir.path();
+        let sv = wtf.join("SavedVariables");
+        fs::create_dir_all(&sv).unwrap();
+        fs::write(sv.join("AddonA.lua"), "").unwrap();
+
+        // A cancelled scan leaves everything in place.
+        let cancel = AtomicBool::new(true);
+        let result =
+            delete_saved_variables_with_progress(&folders, wtf, None, None, |_| {}, &cancel);
+        assert!(matches!(result, Err(FilesystemError::Cancelled)));
+        assert!(sv.join("AddonA.lua").exists());
+
+        let mut reported = vec![];
+        cancel.store(false, Ordering::Relaxed);
+        delete_saved_variables_with_progress(
+            &folders,
+            wtf,
+            None,
+            None,
+            |scanned| reported.push(scanned),
+            &cancel,
+        )
+        .unwrap();
+        assert!(!sv.join("AddonA.lua").exists());
+        // Root, SavedVariables and AddonA.lua.
+        assert_eq!(reported, vec![3]);
+    }
 }
@@ -9,11 +9,27 @@ use std::sync::Mutex;
 
 mod addon;
 pub mod backup;
+mod queue;
 mod save;
 #[cfg(feature = "gui")]
 mod theme;
 
-pub use addon::{delete_addons, delete_saved_variables, install_addon};
+pub(crate) use addon::primary_toc_path;
+pub use addon::{
+    addon_size, backup_addon, backup_saved_variables, cleanup_stale_staging, dedup_shared_libs,
+    delete_addons, delete_addons_dry_run, delete_addons_with_case, delete_saved_variables,
+    delete_saved_variables_with_progress, detect_toc_files, find_orphaned_folders,
+    find_orphaned_libraries, find_saved_variables, find_stale_sv_backups, install_addon,
+    install_addon_collecting_errors, install_addon_with_options, install_addon_with_progress,
+    install_addon_with_stats, install_addons, install_local_archive, installed_version,
+    is_folder_ignored, needs_repair, prune_backups, read_provenance, read_quarantine,
+    restore_addon_backup, restore_saved_variables, scan_addons_parallel, scan_integrity,
+    set_ignored_folders, set_post_install_command, uninstall_addon, uninstall_addons,
+    verify_addon_checksum, verify_against_manifest, verify_install, ChecksumAlgorithm, DedupReport,
+    DuplicateFolders, InstallOptions, InstallStats, IntegrityIssue, Provenance, QuarantineEntry,
+    DEFAULT_CASE_INSENSITIVE, DEFAULT_INSTALL_CONCURRENCY,
+};
+pub use queue::{InstallQueue, QueueStatus};
 pub use save::PersistentData;
 #[cfg(feature = "gui")]
 pub use theme::{import_theme, load_user_themes};
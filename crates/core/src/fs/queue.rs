@@ -0,0 +1,268 @@
+use super::{install_addon_with_options, InstallOptions};
+use crate::{
+    addon::Addon, error::FilesystemError, network::download_addon, repository::GlobalReleaseChannel,
+};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::future::join_all;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Status of an addon in an `InstallQueue`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueStatus {
+    Queued,
+    Downloading,
+    Installing,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+/// Queue of addons installed up to `max_concurrent` at a time, which can be
+/// reordered and cancelled while it's running.
+///
+/// Every status change is sent together with the `primary_folder_id` of the
+/// addon to the receiver returned by `InstallQueue::new`.
+#[derive(Debug, Clone)]
+pub struct InstallQueue {
+    pending: Arc<Mutex<VecDeque<Addon>>>,
+    running: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    sender: UnboundedSender<(String, QueueStatus)>,
+    from_directory: PathBuf,
+    to_directory: PathBuf,
+    global_release_channel: Option<GlobalReleaseChannel>,
+    max_concurrent: usize,
+}
+
+impl InstallQueue {
+    /// Creates a queue installing the already downloaded archives in
+    /// `from_directory` into `to_directory`, see `InstallQueue::download` to
+    /// download them first. A `max_concurrent` of `0` is treated as `1`.
+    pub fn new(
+        from_directory: &Path,
+        to_directory: &Path,
+        max_concurrent: usize,
+    ) -> (Self, UnboundedReceiver<(String, QueueStatus)>) {
+        let (sender, receiver) = mpsc::unbounded();
+
+        let queue = InstallQueue {
+            pending: Default::default(),
+            running: Default::default(),
+            sender,
+            from_directory: from_directory.to_path_buf(),
+            to_directory: to_directory.to_path_buf(),
+            global_release_channel: None,
+            max_concurrent: max_concurrent.max(1),
+        };
+
+        (queue, receiver)
+    }
+
+    /// Downloads every addon into `from_directory` before installing it.
+    pub fn download(mut self, global_release_channel: GlobalReleaseChannel) -> Self {
+        self.global_release_channel = Some(global_release_channel);
+        self
+    }
+
+    /// Adds `addon` to the end of the queue.
+    pub fn enqueue(&self, addon: Addon) {
+        let id = addon.primary_folder_id.clone();
+        self.pending.lock().unwrap().push_back(addon);
+        self.send(&id, QueueStatus::Queued);
+    }
+
+    /// Returns the ids of all addons which haven't started yet, in order.
+    pub fn pending(&self) -> Vec<String> {
+        self.pending
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|addon| addon.primary_folder_id.clone())
+            .collect()
+    }
+
+    /// Moves the not yet started addon `id` to `index` of the pending addons.
+    /// Returns `false` if it isn't pending.
+    pub fn move_to(&self, id: &str, index: usize) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+
+        match pending.iter().position(|a| a.primary_folder_id == id) {
+            Some(position) => {
+                let addon = pending.remove(position).unwrap();
+                let index = index.min(pending.len());
+                pending.insert(index, addon);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancels the addon `id`. A not yet started addon is dropped from the
+    /// queue, a running install is aborted, leaving the installed version in
+    /// place. Returns `false` if the addon is neither pending nor running.
+    pub fn cancel(&self, id: &str) -> bool {
+        let removed = {
+            let mut pending = self.pending.lock().unwrap();
+            let position = pending.iter().position(|a| a.primary_folder_id == id);
+            position.and_then(|position| pending.remove(position))
+        };
+
+        if removed.is_some() {
+            self.send(id, QueueStatus::Cancelled);
+            return true;
+        }
+
+        match self.running.lock().unwrap().get(id) {
+            Some(cancel) => {
+                cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Installs all queued addons, including those enqueued while running, and
+    /// returns once the queue is empty.
+    pub async fn run(&self) {
+        join_all((0..self.max_concurrent).map(|_| self.worker())).await;
+    }
+
+    async fn worker(&self) {
+        // The lock is only held while taking the next addon.
+        let next = || self.pending.lock().unwrap().pop_front();
+
+        while let Some(addon) = next() {
+            let id = addon.primary_folder_id.clone();
+
+            let cancel = Arc::new(AtomicBool::new(false));
+            self.running
+                .lock()
+                .unwrap()
+                .insert(id.clone(), cancel.clone());
+
+            let status = self.install(addon, cancel).await;
+
+            self.running.lock().unwrap().remove(&id);
+            self.send(&id, status);
+        }
+    }
+
+    /// Downloads and installs `addon`, returning its final status.
+    async fn install(&self, addon: Addon, cancel: Arc<AtomicBool>) -> QueueStatus {
+        let id = addon.primary_folder_id.clone();
+
+        if let Some(global_release_channel) = self.global_release_channel {
+            self.send(&id, QueueStatus::Downloading);
+
+            if let Err(error) =
+                download_addon(&addon, global_release_channel, &self.from_directory).await
+            {
+                return QueueStatus::Failed(error.to_string());
+            }
+
+            if cancel.load(Ordering::Relaxed) {
+                let _ = std::fs::remove_file(self.from_directory.join(&id));
+                return QueueStatus::Cancelled;
+            }
+        }
+
+        self.send(&id, QueueStatus::Installing);
+
+        let from_directory = self.from_directory.clone();
+        let to_directory = self.to_directory.clone();
+        let options = InstallOptions {
+            cancel: Some(cancel),
+            ..Default::default()
+        };
+
+        // Extraction is blocking, so every install gets its own thread.
+        let result = async_std::task::spawn_blocking(move || {
+            async_std::task::block_on(install_addon_with_options(
+                &addon,
+                &from_directory,
+                &to_directory,
+                &options,
+                |_, _| {},
+            ))
+        })
+        .await;
+
+        match result {
+            Ok(_) => QueueStatus::Done,
+            Err(FilesystemError::Cancelled) => QueueStatus::Cancelled,
+            Err(error) => QueueStatus::Failed(error.to_string()),
+        }
+    }
+
+    fn send(&self, id: &str, status: QueueStatus) {
+        // The receiver might have been dropped, if no one listens for updates.
+        let _ = self.sender.unbounded_send((id.to_string(), status));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::io::Write;
+    use tempfile::tempdir;
+    use zip::write::{FileOptions, ZipWriter};
+
+    fn write_zip(path: &Path, id: &str) {
+        let mut writer = ZipWriter::new(fs::File::create(path).unwrap());
+        writer
+            .start_file(format!("{0}/{0}.toc", id), FileOptions::default())
+            .unwrap();
+        writer.write_all(b"## Title: Test").unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_install_queue() {
+        let tempdir = tempdir().unwrap();
+        let from_directory = tempdir.path().join("download");
+        let to_directory = tempdir.path().join("AddOns");
+        fs::create_dir_all(&from_directory).unwrap();
+        fs::create_dir_all(&to_directory).unwrap();
+
+        let (queue, mut receiver) = InstallQueue::new(&from_directory, &to_directory, 1);
+        for id in &["Foo", "Bar", "Baz"] {
+            write_zip(&from_directory.join(id), id);
+            queue.enqueue(Addon::empty(id));
+        }
+
+        assert!(queue.cancel("Bar"));
+        assert!(!queue.cancel("Bar"));
+        assert!(queue.move_to("Baz", 0));
+        assert_eq!(queue.pending(), vec!["Baz", "Foo"]);
+
+        async_std::task::block_on(queue.run());
+
+        assert!(to_directory.join("Foo").exists());
+        assert!(!to_directory.join("Bar").exists());
+        assert!(to_directory.join("Baz").exists());
+
+        let mut statuses = vec![];
+        while let Ok(Some((id, status))) = receiver.try_next() {
+            if status != QueueStatus::Queued {
+                statuses.push((id, status));
+            }
+        }
+
+        let expected = vec![
+            ("Bar", QueueStatus::Cancelled),
+            ("Baz", QueueStatus::Installing),
+            ("Baz", QueueStatus::Done),
+            ("Foo", QueueStatus::Installing),
+            ("Foo", QueueStatus::Done),
+        ]
+        .into_iter()
+        .map(|(id, status)| (id.to_string(), status))
+        .collect::<Vec<_>>();
+        assert_eq!(statuses, expected);
+    }
+}
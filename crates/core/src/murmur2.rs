@@ -39,4 +39,19 @@ mod tests {
         let res = calculate_hash(data, 1);
         assert_eq!(res, 851_628_572);
     }
+
+    #[test]
+    fn test_hash_smhasher_verification() {
+        // SMHasher's verification: hash keys `[0]`, `[0, 1]`, .. of up to 255
+        // bytes with seed `256 - len`, then hash the concatenated results with
+        // seed 0. The published value for MurmurHash2 is 0x27864C1E.
+        let mut hashes = vec![];
+        let mut key = vec![];
+        for len in 0..256 {
+            hashes.extend_from_slice(&calculate_hash(&key, 256 - len as u32).to_le_bytes());
+            key.push(len as u8);
+        }
+
+        assert_eq!(calculate_hash(&hashes, 0), 0x2786_4C1E);
+    }
 }
@@ -1,17 +1,26 @@
 use crate::addon::Addon;
 use crate::error::DownloadError;
-use crate::repository::GlobalReleaseChannel;
+use crate::fs::install_addon;
+use crate::repository::{local, GlobalReleaseChannel};
 use async_std::{
-    fs::{create_dir_all, File},
-    io::copy,
+    fs::{create_dir_all, metadata, read_to_string, remove_file, rename, write, File, OpenOptions},
+    io::{copy, prelude::WriteExt},
 };
+use chrono::{DateTime, Utc};
 use isahc::config::RedirectPolicy;
-use isahc::http::header::CONTENT_LENGTH;
+use isahc::http::header::{
+    HeaderMap, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+    RETRY_AFTER,
+};
+use isahc::http::{StatusCode, Uri};
 use isahc::prelude::*;
 use isahc::{HttpClient, Request, Response};
 use once_cell::sync::Lazy;
-use serde::Serialize;
-use std::path::Path;
+use retry::delay::jitter;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// Shared `HttpClient`.
 static HTTP_CLIENT: Lazy<HttpClient> = Lazy::new(|| {
@@ -78,12 +87,98 @@ pub(crate) async fn post_json_async<T: ToString, D: Serialize>(
         .await?)
 }
 
+/// How often `download_addon_with_retry` attempts a download before giving up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts before giving up, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every following retry.
+    pub base_delay: Duration,
+    /// Upper bound of the delay between two attempts. A `Retry-After` asking
+    /// to wait longer than this gives up right away.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait before attempt `attempt + 1`, or `None` if the
+    /// download failed with an `error` which isn't worth retrying.
+    fn delay(&self, error: &DownloadError, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        match error {
+            DownloadError::TooManyRequests {
+                retry_after: Some(retry_after),
+                ..
+            } => Some(*retry_after).filter(|retry_after| *retry_after <= self.max_delay),
+            DownloadError::TooManyRequests { .. }
+            | DownloadError::ContentLength { .. }
+            | DownloadError::Interrupted { .. } => Some(self.backoff(attempt)),
+            DownloadError::InvalidStatusCode { code, .. } if code.is_server_error() => {
+                Some(self.backoff(attempt))
+            }
+            DownloadError::Isahc(error)
+                if error.is_network() || error.is_timeout() || error.is_server() =>
+            {
+                Some(self.backoff(attempt))
+            }
+            _ => None,
+        }
+    }
+
+    /// Exponential backoff with jitter, so downloads which failed together
+    /// don't retry in lockstep.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        delay / 2 + jitter(delay / 2)
+    }
+}
+
 /// Function to download a zip archive for a `Addon`.
 /// Note: Addon needs to have a `remote_url` to the file.
+///
+/// Failed downloads are retried with the default `RetryPolicy`.
 pub async fn download_addon(
     addon: &Addon,
     global_release_channel: GlobalReleaseChannel,
     to_directory: &Path,
+) -> Result<(), DownloadError> {
+    download_addon_with_retry(
+        addon,
+        global_release_channel,
+        to_directory,
+        &RetryPolicy::default(),
+    )
+    .await
+}
+
+/// Like `download_addon`, but retries failed downloads according to `policy`.
+///
+/// Network errors, timeouts, truncated bodies, server errors and `429 Too Many
+/// Requests` are retried, the latter honoring its `Retry-After` header. Any
+/// other error fails right away. Once all attempts are used up the last error
+/// is returned, wrapped in `DownloadError::RetriesExhausted`.
+pub async fn download_addon_with_retry(
+    addon: &Addon,
+    global_release_channel: GlobalReleaseChannel,
+    to_directory: &Path,
+    policy: &RetryPolicy,
 ) -> Result<(), DownloadError> {
     let package =
         if let Some(relevant_package) = addon.relevant_release_package(global_release_channel) {
@@ -98,43 +193,357 @@ pub async fn download_addon(
             package.version,
             &addon.primary_folder_id
         );
-        let resp = request_async(package.download_url.clone(), vec![], None).await?;
-        let (parts, mut body) = resp.into_parts();
 
-        // If response length doesn't equal content length, full file wasn't downloaded
-        // so error out
-        {
-            let content_length = parts
-                .headers
-                .get(CONTENT_LENGTH)
-                .map(|v| v.to_str().unwrap_or_default())
-                .unwrap_or_default()
-                .parse::<u64>()
-                .unwrap_or_default();
-
-            let body_length = body.len().unwrap_or_default();
-
-            if body_length != content_length {
-                return Err(DownloadError::ContentLength {
-                    content_length,
-                    body_length,
+        if !to_directory.exists() {
+            create_dir_all(to_directory).await?;
+        }
+
+        let zip_path = to_directory.join(&addon.primary_folder_id);
+
+        // Packages of a local repository are copied instead of downloaded.
+        if let Some(archive_path) = local::archive_path(&package.download_url) {
+            async_std::fs::copy(&archive_path, &zip_path).await?;
+            return Ok(());
+        }
+
+        let mut attempt = 1;
+
+        loop {
+            let error = match download_archive(&package.download_url, &zip_path).await {
+                Ok(_) => break,
+                Err(error) => error,
+            };
+
+            let delay = match policy.delay(&error, attempt) {
+                Some(delay) => delay,
+                None if attempt > 1 => {
+                    return Err(DownloadError::RetriesExhausted {
+                        url: package.download_url.clone(),
+                        attempts: attempt,
+                        source: Box::new(error),
+                    })
+                }
+                None => return Err(error),
+            };
+
+            log::warn!(
+                "{} - download attempt {} failed, retrying in {:?}: {}",
+                &addon.primary_folder_id,
+                attempt,
+                delay,
+                error
+            );
+
+            async_std::task::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Downloads `url` to `path`, resuming a download interrupted in an earlier
+/// attempt where possible.
+///
+/// The download is written to a partial file next to `path`, which is renamed to
+/// `path` once complete. A partial file is resumed with a `Range` request if the
+/// server advertised `Accept-Ranges: bytes` for it and its `ETag` or
+/// `Last-Modified` is unchanged, otherwise the download starts over.
+async fn download_archive(url: &str, path: &Path) -> Result<(), DownloadError> {
+    let partial = PartialDownload::new(path);
+    let mut resume = partial.resume_from(url).await;
+
+    let (parts, mut body) = loop {
+        let range = resume
+            .as_ref()
+            .map(|(offset, _)| format!("bytes={}-", offset));
+        let mut headers = vec![];
+
+        if let (Some(range), Some((_, validator))) = (&range, &resume) {
+            headers.push((RANGE.as_str(), range.as_str()));
+            headers.push((IF_RANGE.as_str(), validator.as_str()));
+        }
+
+        let (parts, body) = request_async(url, headers, None).await?.into_parts();
+
+        // Start over if the server can't continue the partial download after all.
+        if let Some((offset, validator)) = &resume {
+            let resumable = parts.status == StatusCode::PARTIAL_CONTENT
+                && content_range_start(&parts.headers) == Some(*offset)
+                && [ETAG, LAST_MODIFIED].iter().any(|name| {
+                    parts.headers.get(name).map(|v| v == validator.as_str()) == Some(true)
                 });
+
+            if parts.status == StatusCode::RANGE_NOT_SATISFIABLE
+                || (parts.status == StatusCode::PARTIAL_CONTENT && !resumable)
+            {
+                log::debug!("can't resume download of {}, starting over", url);
+
+                partial.discard().await;
+                resume = None;
+                continue;
             }
         }
 
-        if !to_directory.exists() {
-            create_dir_all(to_directory).await?;
+        break (parts, body);
+    };
+
+    if parts.status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(DownloadError::TooManyRequests {
+            url: url.to_string(),
+            retry_after: parts
+                .headers
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_retry_after),
+        });
+    }
+
+    if !parts.status.is_success() {
+        return Err(DownloadError::InvalidStatusCode {
+            code: parts.status,
+            url: url.to_string(),
+        });
+    }
+
+    // If response length doesn't equal content length, full file wasn't downloaded
+    // so error out
+    {
+        let content_length = parts
+            .headers
+            .get(CONTENT_LENGTH)
+            .map(|v| v.to_str().unwrap_or_default())
+            .unwrap_or_default()
+            .parse::<u64>()
+            .unwrap_or_default();
+
+        let body_length = body.len().unwrap_or_default();
+
+        if body_length != content_length {
+            return Err(DownloadError::ContentLength {
+                content_length,
+                body_length,
+            });
         }
+    }
 
-        let zip_path = to_directory.join(&addon.primary_folder_id);
-        let mut file = File::create(&zip_path).await?;
+    let mut file = if parts.status == StatusCode::PARTIAL_CONTENT {
+        log::debug!("resuming download of {}", url);
+
+        OpenOptions::new().append(true).open(&partial.path).await?
+    } else {
+        partial
+            .start(url, resume_validator(&parts.headers).as_deref())
+            .await?;
+
+        File::create(&partial.path).await?
+    };
+
+    let copied = copy(&mut body, &mut file).await;
+    file.flush().await?;
+    drop(file);
 
-        copy(&mut body, &mut file).await?;
+    if let Err(error) = copied {
+        log::debug!("download of {} was interrupted: {}", url, error);
+
+        return Err(DownloadError::Interrupted {
+            url: url.to_string(),
+            downloaded: partial.len().await,
+        });
     }
 
+    rename(&partial.path, path).await?;
+    partial.discard().await;
+
     Ok(())
 }
 
+/// Partial file of an unfinished download, next to its destination.
+struct PartialDownload {
+    path: PathBuf,
+    /// Sidecar file holding the `PartialMeta` of the download.
+    meta_path: PathBuf,
+}
+
+/// What a partial file was downloaded from, to only resume the same file.
+#[derive(Debug, Serialize, Deserialize)]
+struct PartialMeta {
+    url: String,
+    validator: String,
+}
+
+impl PartialDownload {
+    fn new(path: &Path) -> Self {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        PartialDownload {
+            path: path.with_file_name(format!("{}.part", file_name)),
+            meta_path: path.with_file_name(format!("{}.part.json", file_name)),
+        }
+    }
+
+    /// Returns the offset and validator to resume the download of `url` from,
+    /// or `None` if there's nothing to resume.
+    async fn resume_from(&self, url: &str) -> Option<(u64, String)> {
+        let meta = read_to_string(&self.meta_path).await.ok()?;
+        let meta = serde_json::from_str::<PartialMeta>(&meta).ok()?;
+        let offset = self.len().await;
+
+        if meta.url == url && offset > 0 {
+            Some((offset, meta.validator))
+        } else {
+            None
+        }
+    }
+
+    /// Records a new download of `url`, which can only be resumed later if the
+    /// server gave a `validator` for it.
+    async fn start(&self, url: &str, validator: Option<&str>) -> Result<(), DownloadError> {
+        match validator {
+            Some(validator) => {
+                let meta = PartialMeta {
+                    url: url.to_string(),
+                    validator: validator.to_string(),
+                };
+
+                Ok(write(&self.meta_path, serde_json::to_vec(&meta)?).await?)
+            }
+            None => {
+                let _ = remove_file(&self.meta_path).await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn len(&self) -> u64 {
+        metadata(&self.path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or_default()
+    }
+
+    async fn discard(&self) {
+        let _ = remove_file(&self.path).await;
+        let _ = remove_file(&self.meta_path).await;
+    }
+}
+
+/// Returns the validator a download can be resumed with using `If-Range`, if the
+/// server supports range requests. Weak `ETag`s can't be used with `If-Range`,
+/// so `Last-Modified` is used instead.
+fn resume_validator(headers: &HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if !header(ACCEPT_RANGES)
+        .map(|v| v.split(',').any(|unit| unit.trim() == "bytes"))
+        .unwrap_or_default()
+    {
+        return None;
+    }
+
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+        .map(str::to_string)
+}
+
+/// Returns the first byte of a `Content-Range: bytes <start>-<end>/<size>` header.
+fn content_range_start(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.trim().strip_prefix("bytes ")?;
+
+    range.split('-').next()?.trim().parse().ok()
+}
+
+/// Parses a `Retry-After` header, given either in seconds or as a HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+
+    Some(
+        date.with_timezone(&Utc)
+            .signed_duration_since(Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Downloads the zip archive at `url` into `from_directory` and installs it into
+/// `to_directory`.
+///
+/// The returned `Addon` has the downloaded file name as `primary_folder_id` and
+/// the installed folders as its folders. Downloads which aren't a zip archive,
+/// like the html page of a 404, are refused before anything is extracted.
+pub async fn install_from_url(
+    url: &str,
+    from_directory: &Path,
+    to_directory: &Path,
+) -> Result<Addon, DownloadError> {
+    let mut addon = Addon::empty(&file_name_from_url(url));
+
+    log::debug!("{} - downloading from {}", &addon.primary_folder_id, url);
+
+    let resp = request_async(url, vec![], None).await?;
+
+    if !resp.status().is_success() {
+        return Err(DownloadError::InvalidStatusCode {
+            code: resp.status(),
+            url: url.to_string(),
+        });
+    }
+
+    if !from_directory.exists() {
+        create_dir_all(from_directory).await?;
+    }
+
+    let archive_path = from_directory.join(&addon.primary_folder_id);
+    let mut file = File::create(&archive_path).await?;
+
+    copy(&mut resp.into_body(), &mut file).await?;
+    file.flush().await?;
+
+    if !is_zip_archive(&archive_path)? {
+        let _ = remove_file(&archive_path).await;
+
+        return Err(DownloadError::NotZipArchive {
+            url: url.to_string(),
+        });
+    }
+
+    addon.folders = install_addon(&addon, from_directory, to_directory).await?;
+
+    Ok(addon)
+}
+
+/// Returns the last segment of the path of `url`, falling back to `addon` if
+/// it has none.
+fn file_name_from_url(url: &str) -> String {
+    url.parse::<Uri>()
+        .ok()
+        .and_then(|uri| {
+            uri.path()
+                .rsplit('/')
+                .next()
+                .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "addon".to_string())
+}
+
+/// Returns `true` if the file at `path` starts with the magic bytes of a zip
+/// archive, either a local file header or the end record of an empty archive.
+fn is_zip_archive(path: &Path) -> Result<bool, DownloadError> {
+    let mut magic = [0; 4];
+    let read = std::fs::File::open(path)?.read(&mut magic)?;
+
+    Ok(read == magic.len() && (&magic == b"PK\x03\x04" || &magic == b"PK\x05\x06"))
+}
+
 /// Download a file from the internet
 pub(crate) async fn download_file<T: ToString>(
     url: T,
@@ -176,3 +585,132 @@ pub(crate) async fn download_file<T: ToString>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name_from_url() {
+        assert_eq!(
+            file_name_from_url("https://example.com/files/MyAddon-1.2.zip?token=abc"),
+            "MyAddon-1.2.zip"
+        );
+        assert_eq!(file_name_from_url("https://example.com/"), "addon");
+        assert_eq!(file_name_from_url("https://example.com/a/.."), "addon");
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::default())
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn test_retry_policy_delay() {
+        let policy = RetryPolicy::default();
+        let url = "https://example.com/addon.zip".to_string();
+
+        let server_error = DownloadError::InvalidStatusCode {
+            code: StatusCode::BAD_GATEWAY,
+            url: url.clone(),
+        };
+        let delay = policy.delay(&server_error, 2).unwrap();
+        assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+        assert_eq!(policy.delay(&server_error, 3), None);
+
+        let not_found = DownloadError::InvalidStatusCode {
+            code: StatusCode::NOT_FOUND,
+            url: url.clone(),
+        };
+        assert_eq!(policy.delay(&not_found, 1), None);
+
+        let too_many_requests = |retry_after| DownloadError::TooManyRequests {
+            url: url.clone(),
+            retry_after,
+        };
+        assert_eq!(
+            policy.delay(&too_many_requests(Some(Duration::from_secs(5))), 1),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            policy.delay(&too_many_requests(Some(Duration::from_secs(3600))), 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_resume_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, "\"abc\"".parse().unwrap());
+        headers.insert(
+            LAST_MODIFIED,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(resume_validator(&headers), None);
+
+        headers.insert(ACCEPT_RANGES, "bytes".parse().unwrap());
+        assert_eq!(resume_validator(&headers).as_deref(), Some("\"abc\""));
+
+        headers.insert(ETAG, "W/\"abc\"".parse().unwrap());
+        assert_eq!(
+            resume_validator(&headers).as_deref(),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+
+        headers.insert(CONTENT_RANGE, "bytes 1024-2047/2048".parse().unwrap());
+        assert_eq!(content_range_start(&headers), Some(1024));
+
+        headers.insert(CONTENT_RANGE, "bytes */2048".parse().unwrap());
+        assert_eq!(content_range_start(&headers), None);
+    }
+
+    #[test]
+    fn test_partial_download() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let partial = PartialDownload::new(&tempdir.path().join("Foo"));
+        let url = "https://example.com/foo.zip";
+
+        async_std::task::block_on(async {
+            partial.start(url, Some("\"abc\"")).await.unwrap();
+            assert_eq!(partial.resume_from(url).await, None);
+
+            std::fs::write(&partial.path, b"PK\x03\x04").unwrap();
+            assert_eq!(
+                partial.resume_from(url).await,
+                Some((4, "\"abc\"".to_string()))
+            );
+            assert_eq!(
+                partial.resume_from("https://example.com/bar.zip").await,
+                None
+            );
+
+            partial.start(url, None).await.unwrap();
+            assert_eq!(partial.resume_from(url).await, None);
+
+            partial.discard().await;
+            assert!(!partial.path.exists());
+        });
+    }
+
+    #[test]
+    fn test_is_zip_archive() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let zip_path = tempdir.path().join("addon.zip");
+        std::fs::write(&zip_path, b"PK\x03\x04rest").unwrap();
+        assert!(is_zip_archive(&zip_path).unwrap());
+
+        let html_path = tempdir.path().join("404.html");
+        std::fs::write(&html_path, b"<!DOCTYPE html>").unwrap();
+        assert!(!is_zip_archive(&html_path).unwrap());
+
+        let empty_path = tempdir.path().join("empty");
+        std::fs::write(&empty_path, b"").unwrap();
+        assert!(!is_zip_archive(&empty_path).unwrap());
+    }
+}
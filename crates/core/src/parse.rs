@@ -1,12 +1,13 @@
 use crate::{
-    addon::{Addon, AddonFolder, AddonState},
-    cache::{self, AddonCache, AddonCacheEntry, ExternalReleaseId, FingerprintCache},
+    addon::{group_addon_folders, Addon, AddonFolder, AddonState},
+    cache::{self, AddonCache, AddonCacheEntry, ExternalReleaseId, FingerprintCache, TocCache},
     config::Flavor,
-    error::{CacheError, DownloadError, ParseError},
-    fs::PersistentData,
+    error::{CacheError, DownloadError, FilesystemError, ParseError},
+    fs::{primary_toc_path, PersistentData},
     murmur2::calculate_hash,
     repository::{
-        curse, git, hub, tukui, wowi, RepositoryIdentifiers, RepositoryKind, RepositoryPackage,
+        curse, git, hub, resolve_source, tukui, wowi, RepositoryIdentifiers, RepositoryKind,
+        RepositoryPackage,
     },
     utility::format_interface_into_game_version,
 };
@@ -15,9 +16,9 @@ use fancy_regex::Regex;
 use once_cell::sync::Lazy;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::SystemTime;
@@ -29,6 +30,24 @@ pub struct Fingerprint {
     pub modified: SystemTime,
 }
 
+/// Why an addon folder couldn't be read from its `.toc` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TocFailure {
+    /// The folder has no `.toc` file named after it.
+    Missing,
+    /// The `.toc` file exists, but couldn't be read.
+    Unreadable(String),
+}
+
+impl std::fmt::Display for TocFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TocFailure::Missing => write!(f, "Missing .toc file"),
+            TocFailure::Unreadable(reason) => write!(f, "Unreadable .toc file ({})", reason),
+        }
+    }
+}
+
 pub struct ParsingPatterns {
     pub initial_inclusion_regex: Regex,
     pub extra_inclusion_regex: Regex,
@@ -69,18 +88,7 @@ pub async fn read_addon_directory<P: AsRef<Path>>(
     }
 
     // All addon dirs gathered in a `Vec<String>`.
-    let all_dirs: Vec<String> = root_dir
-        .read_dir()
-        .unwrap()
-        .filter_map(|entry| {
-            let entry = entry.unwrap();
-            if entry.file_type().unwrap().is_dir() {
-                Some(entry.file_name().to_str().unwrap().to_string())
-            } else {
-                None
-            }
-        })
-        .collect();
+    let all_dirs = addon_dir_names(root_dir);
 
     log::debug!(
         "{} - {} folders in AddOns directory to parse",
@@ -114,7 +122,8 @@ pub async fn read_addon_directory<P: AsRef<Path>>(
 
     // Parse all addon folders from `.toc` file in each directory and assign it's
     // respective fingerprint
-    let mut addon_folders = parse_addon_folders(root_dir, flavor, &all_dirs, &fingerprints).await;
+    let (mut addon_folders, failures) =
+        parse_addon_folders(root_dir, flavor, &all_dirs, &fingerprints).await;
 
     // Get all cached entries
     let cache_entries = get_cache_entries(flavor, addon_cache, &addon_folders).await?;
@@ -135,17 +144,18 @@ pub async fn read_addon_directory<P: AsRef<Path>>(
         &cache_entries,
     );
 
-    // Any remaining addon folders are unknown, we will show them 1:1 in Ajour
-    let unknown_addons = addon_folders
+    // Any remaining addon folders are unknown, we group bundled libraries and
+    // modules under the addon they belong to.
+    let unknown_folders = addon_folders
         .into_iter()
         // Blacklist this addon since it's created by Ajour / Companion app and
         // doesn't need to be managed
         .filter(|f| f.id != "WeakAurasCompanion")
-        .map(|f| {
-            let mut addon = Addon::empty(&f.id);
-            addon.folders = vec![f];
+        .collect::<Vec<_>>();
+    let unknown_addons = group_addon_folders(unknown_folders)
+        .into_iter()
+        .map(|mut addon| {
             addon.state = AddonState::Unknown;
-
             addon
         })
         .collect::<Vec<_>>();
@@ -156,8 +166,32 @@ pub async fn read_addon_directory<P: AsRef<Path>>(
         unknown_addons.len()
     );
 
+    // Folders without a readable `.toc` file are listed with the reason, instead
+    // of silently disappearing.
+    let unreadable_addons = failures
+        .into_iter()
+        .map(|(id, failure)| {
+            log::warn!("{} - skipping addon folder {}: {}", flavor, id, failure);
+
+            let mut addon = Addon::empty(&id);
+            addon.folders = vec![AddonFolder {
+                id: id.clone(),
+                title: id.clone(),
+                path: root_dir.join(&id),
+                ..Default::default()
+            }];
+            addon.state = AddonState::Error(failure.to_string());
+            addon
+        })
+        .collect::<Vec<_>>();
+
     // Concats the different repo addons, and returns.
-    let concatenated = [&known_addons[..], &unknown_addons[..]].concat();
+    let concatenated = [
+        &known_addons[..],
+        &unknown_addons[..],
+        &unreadable_addons[..],
+    ]
+    .concat();
 
     log::debug!(
         "{} - {} addons successfully parsed",
@@ -168,6 +202,25 @@ pub async fn read_addon_directory<P: AsRef<Path>>(
     Ok(concatenated)
 }
 
+/// Returns the names of all directories in `root_dir` which can be addons.
+/// Hidden directories, like `.git` or the `.ajour-staging-*` directories of an
+/// install in progress, never are.
+fn addon_dir_names(root_dir: &Path) -> Vec<String> {
+    root_dir
+        .read_dir()
+        .unwrap()
+        .filter_map(|entry| {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                Some(entry.file_name().to_str().unwrap().to_string())
+            } else {
+                None
+            }
+        })
+        .filter(|name| !name.starts_with('.'))
+        .collect()
+}
+
 async fn fingerprint_all_dirs(
     root_dir: &Path,
     flavor: Flavor,
@@ -263,15 +316,19 @@ async fn fingerprint_all_dirs(
     Ok(new_fingerprints)
 }
 
+/// Parses the `.toc` file of every folder in `all_dirs`. Returns the parsed
+/// addon folders, and the ids of the folders which failed with the reason.
 async fn parse_addon_folders(
     root_dir: &Path,
     flavor: Flavor,
     all_dirs: &[String],
     fingerprints: &[Fingerprint],
-) -> Vec<AddonFolder> {
-    let mut addon_folders: Vec<_> = all_dirs
+) -> (Vec<AddonFolder>, Vec<(String, TocFailure)>) {
+    let toc_cache = std::sync::Mutex::new(TocCache::load_current());
+
+    let results: Vec<_> = all_dirs
         .par_iter()
-        .filter_map(|id| {
+        .map(|id| {
             // Generate .toc path.
             let toc_path = {
                 let toc_with_flavor = || -> Option<PathBuf> {
@@ -296,35 +353,53 @@ async fn parse_addon_folders(
                     None
                 };
 
-                toc_with_flavor().unwrap_or_else(|| root_dir.join(&id).join(format!("{}.toc", id)))
+                // The `.toc` file doesn't have to be named after the folder.
+                match toc_with_flavor().or_else(|| primary_toc_path(&root_dir.join(&id), id)) {
+                    Some(toc_path) => toc_path,
+                    None => return Err((id.clone(), TocFailure::Missing)),
+                }
             };
 
-            if !toc_path.exists() {
-                return None;
-            }
-
             // We add fingerprint to the addon.
-            let mut addon_folder = parse_toc_path(&toc_path)?;
+            let mut addon_folder = parse_toc_path_cached(&toc_path, &toc_cache)
+                .map_err(|reason| (id.clone(), TocFailure::Unreadable(reason)))?;
             addon_folder.fingerprint = fingerprints
                 .iter()
                 .find(|f| &f.title == id)
                 .map(|f| f.hash)
                 .flatten();
+            // Show the game version of the flavor being read.
+            addon_folder.interface = addon_folder.interface_for_flavor(flavor);
 
-            Some(addon_folder)
+            Ok(addon_folder)
         })
         .collect();
 
+    let mut addon_folders = vec![];
+    let mut failures = vec![];
+    for result in results {
+        match result {
+            Ok(addon_folder) => addon_folders.push(addon_folder),
+            Err(failure) => failures.push(failure),
+        }
+    }
+
+    // Persist parsed `.toc` files, dropping the ones which no longer exist.
+    if let Ok(toc_cache) = toc_cache.into_inner() {
+        let _ = toc_cache.save_merged();
+    }
+
     // Ensure addon folders are sorted alphabetically
     addon_folders.sort_by(|a, b| a.id.cmp(&b.id));
 
     log::debug!(
-        "{} - {} addon folders successfully parsed from '.toc'",
+        "{} - {} addon folders successfully parsed from '.toc', {} failed",
         flavor,
-        addon_folders.len()
+        addon_folders.len(),
+        failures.len()
     );
 
-    addon_folders
+    (addon_folders, failures)
 }
 
 async fn get_cache_entries(
@@ -674,6 +749,47 @@ fn build_addons(
         })
         .collect();
 
+    // A folder matching both a Curse package and a Tukui or WowI package only
+    // keeps the package of the source picked by `resolve_source`.
+    let mut resolved_folders = HashSet::new();
+    for r in repo_packages
+        .clone()
+        .iter()
+        .filter(|r| r.kind == RepositoryKind::Tukui || r.kind == RepositoryKind::WowI)
+    {
+        let folder = addon_folders.iter().find(|f| match r.kind {
+            RepositoryKind::Tukui => f.repository_identifiers.tukui.as_ref() == Some(&r.id),
+            RepositoryKind::WowI => f.repository_identifiers.wowi.as_ref() == Some(&r.id),
+            _ => false,
+        });
+        let folder = match folder {
+            Some(folder) => folder,
+            None => continue,
+        };
+
+        let curse_ids: Vec<_> = repo_packages
+            .iter()
+            .filter(|p| {
+                p.kind == RepositoryKind::Curse && p.metadata.modules().contains(&folder.id)
+            })
+            .map(|p| p.id.clone())
+            .collect();
+        if curse_ids.is_empty() {
+            continue;
+        }
+
+        let source = resolve_source(flavor, &folder.id, &[RepositoryKind::Curse, r.kind]);
+        if source == Some(r.kind) {
+            repo_packages
+                .retain(|p| !(p.kind == RepositoryKind::Curse && curse_ids.contains(&p.id)));
+        } else {
+            repo_packages.retain(|p| !(p.kind == r.kind && p.id == r.id));
+        }
+
+        log::debug!("{} - {} resolved to {:?}", flavor, folder.id, source);
+        resolved_folders.insert(folder.id.clone());
+    }
+
     let curse_addons: Vec<_> = repo_packages
         .clone()
         .iter()
@@ -754,13 +870,20 @@ fn build_addons(
         })
         .collect();
 
-    let concatenated_addons = [
+    let mut concatenated_addons = [
         &cached_addons[..],
         &curse_addons[..],
         &tukui_and_wowi_addons[..],
     ]
     .concat();
 
+    for addon in concatenated_addons
+        .iter_mut()
+        .filter(|a| a.folders.iter().any(|f| resolved_folders.contains(&f.id)))
+    {
+        addon.source = addon.repository_kind();
+    }
+
     log::debug!(
         "{} - {} addons built from curse packages",
         flavor,
@@ -1060,66 +1183,155 @@ static RE_PARSING_PATTERNS: Lazy<ParsingPatterns> = Lazy::new(|| {
     }
 });
 
+/// Decodes the contents of a `.toc` file. A UTF-8 BOM is stripped and UTF-16
+/// files with a BOM are transcoded, everything else is read as UTF-8, replacing
+/// invalid sequences.
+fn decode_toc(bytes: &[u8]) -> String {
+    let decode_utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|c| from_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Same as `parse_toc_path`, but returns the `AddonFolder` from `toc_cache` if
+/// the file wasn't modified since it was last parsed. Newly parsed files are
+/// added to the cache.
+pub(crate) fn parse_toc_path_cached(
+    toc_path: &Path,
+    toc_cache: &std::sync::Mutex<TocCache>,
+) -> Result<AddonFolder, String> {
+    let modified = toc_path.metadata().and_then(|m| m.modified()).ok();
+
+    if let Some(modified) = modified {
+        if let Some(addon_folder) = toc_cache
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(toc_path, modified))
+        {
+            return Ok(addon_folder);
+        }
+    }
+
+    let addon_folder = read_toc_path(toc_path)?;
+
+    if let (Some(modified), Ok(mut cache)) = (modified, toc_cache.lock()) {
+        cache.insert(toc_path.to_path_buf(), modified, addon_folder.clone());
+    }
+
+    Ok(addon_folder)
+}
+
 /// Helper function to parse a given TOC file
 /// (`DirEntry`) into a `Addon` struct.
 ///
 /// TOC format summary:
 /// https://wowwiki.fandom.com/wiki/TOC_format
 pub fn parse_toc_path(toc_path: &Path) -> Option<AddonFolder> {
-    //direntry
-    let file = if let Ok(file) = File::open(toc_path) {
-        file
-    } else {
-        return None;
+    read_toc_path(toc_path).ok()
+}
+
+/// Same as `parse_toc_path`, but returns why the file couldn't be parsed.
+fn read_toc_path(toc_path: &Path) -> Result<AddonFolder, String> {
+    let contents = match std::fs::read(toc_path) {
+        Ok(bytes) => decode_toc(&bytes),
+        Err(error) => return Err(error.to_string()),
     };
-    let reader = BufReader::new(file);
 
-    let path = toc_path.parent()?.to_path_buf();
-    let id = path.file_name()?.to_str()?.to_string();
+    let path = toc_path
+        .parent()
+        .ok_or_else(|| format!("{:?} isn't in an addon folder", toc_path))?
+        .to_path_buf();
+    let id = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("folder name of {:?} isn't valid UTF-8", path))?
+        .to_string();
     let mut title: Option<String> = None;
     let mut interface: Option<String> = None;
+    let mut interfaces: Vec<u32> = Vec::new();
+    let mut flavor_interfaces: HashMap<Flavor, Vec<u32>> = HashMap::new();
+    let mut flavor_interface_values: Vec<u32> = Vec::new();
     let mut author: Option<String> = None;
     let mut notes: Option<String> = None;
     let mut version: Option<String> = None;
     let mut dependencies: Vec<String> = Vec::new();
+    let mut optional_dependencies: Vec<String> = Vec::new();
     let mut wowi_id: Option<String> = None;
     let mut tukui_id: Option<String> = None;
     let mut curse_id: Option<i32> = None;
+    let mut wago_id: Option<String> = None;
+
+    // Lines can end with `\n`, `\r\n` or even a lone `\r` in mixed line endings,
+    // so values are trimmed of any leftover `\r` and surrounding whitespace.
+    for line in contents.split(|c| c == '\n' || c == '\r') {
+        for cap in RE_TOC_LINE.captures_iter(line) {
+            let value = cap["value"].trim();
 
-    for line in reader.lines().filter_map(|l| l.ok()) {
-        for cap in RE_TOC_LINE.captures_iter(line.as_str()) {
             match &cap["key"] {
                 // Note: Coloring is possible via UI escape sequences.
                 // Since we don't want any color modifications, we will trim it away.
-                "Title" => {
-                    title = Some(
-                        RE_TOC_TITLE
-                            .replace_all(&cap["value"], "$1")
-                            .trim()
-                            .to_string(),
-                    )
+                "Title" => title = Some(RE_TOC_TITLE.replace_all(value, "$1").trim().to_string()),
+                // Single `.toc` files for several flavors list each flavor's
+                // interfaces separately, eg. `## Interface-Classic: 11403`.
+                key if key.starts_with("Interface-") => {
+                    let suffix = key.trim_start_matches("Interface-");
+
+                    if let Some(flavor) = flavor_from_toc_suffix(suffix) {
+                        let values = value
+                            .split(',')
+                            .filter_map(|v| v.trim().parse().ok())
+                            .collect::<Vec<u32>>();
+
+                        flavor_interface_values.extend(values.iter().copied());
+                        flavor_interfaces
+                            .entry(flavor)
+                            .or_insert_with(Vec::new)
+                            .extend(values);
+                    }
                 }
+                // Multi flavor builds can list several comma separated interfaces.
                 "Interface" => {
-                    interface = Some(format_interface_into_game_version(cap["value"].trim()));
+                    let values: Vec<_> = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|v| !v.is_empty())
+                        .collect();
+
+                    interfaces = values.iter().filter_map(|v| v.parse().ok()).collect();
+                    interface = Some(
+                        values
+                            .into_iter()
+                            .map(format_interface_into_game_version)
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    );
                 }
-                "Author" => author = Some(cap["value"].trim().to_string()),
-                "Notes" => {
-                    notes = Some(
-                        RE_TOC_TITLE
-                            .replace_all(&cap["value"], "$1")
-                            .trim()
-                            .to_string(),
-                    )
-                }
-                "Version" => version = Some(cap["value"].trim().to_owned()),
+                "Author" => author = Some(value.to_string()),
+                "Notes" => notes = Some(RE_TOC_TITLE.replace_all(value, "$1").trim().to_string()),
+                "Version" => version = Some(value.to_owned()),
                 // Names that must be loaded before this addon can be loaded.
                 "Dependencies" | "RequiredDeps" => {
-                    dependencies.append(&mut split_dependencies_into_vec(&cap["value"]));
+                    dependencies.append(&mut split_dependencies_into_vec(value));
+                }
+                // Names that will be loaded before this addon, if they are installed.
+                "OptionalDeps" => {
+                    optional_dependencies.append(&mut split_dependencies_into_vec(value));
                 }
-                "X-Tukui-ProjectID" => tukui_id = Some(cap["value"].to_string()),
-                "X-WoWI-ID" => wowi_id = Some(cap["value"].to_string()),
+                "X-Tukui-ProjectID" => tukui_id = Some(value.to_string()),
+                "X-WoWI-ID" => wowi_id = Some(value.to_string()),
+                "X-Wago-ID" => wago_id = Some(value.to_string()),
                 "X-Curse-Project-ID" => {
-                    if let Ok(id) = cap["value"].to_string().parse::<i32>() {
+                    if let Ok(id) = value.parse::<i32>() {
                         curse_id = Some(id)
                     }
                 }
@@ -1128,26 +1340,157 @@ pub fn parse_toc_path(toc_path: &Path) -> Option<AddonFolder> {
         }
     }
 
+    // Flavor specific interfaces are supported as well.
+    for value in flavor_interface_values {
+        if !interfaces.contains(&value) {
+            interfaces.push(value);
+        }
+    }
+
     let repository_identifiers = RepositoryIdentifiers {
         wowi: wowi_id,
         tukui: tukui_id,
         curse: curse_id,
         git: None,
+        wago: wago_id,
     };
 
-    Some(AddonFolder::new(
+    let toc_flavor = toc_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| toc_flavor_from_stem(stem, &id));
+
+    // Titles made up entirely of color codes end up empty, fallback to the id.
+    let title = title
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| id.clone());
+    let author = author.filter(|a| !a.is_empty());
+    let notes = notes.filter(|n| !n.is_empty());
+
+    Ok(AddonFolder::new(
         id.clone(),
-        title.unwrap_or(id),
+        title,
         interface,
+        interfaces,
+        flavor_interfaces,
+        toc_flavor,
         path,
         author,
         notes,
         version,
         repository_identifiers,
         dependencies,
+        optional_dependencies,
     ))
 }
 
+/// Returns the flavor a flavor specific `.toc` file stem, eg. `Foo_Mainline`
+/// or `Foo-BCC`, belongs to.
+fn toc_flavor_from_stem(stem: &str, id: &str) -> Option<Flavor> {
+    let suffix = stem.strip_prefix(id)?;
+    let suffix = suffix
+        .strip_prefix('-')
+        .or_else(|| suffix.strip_prefix('_'))?;
+
+    flavor_from_toc_suffix(suffix)
+}
+
+/// Returns the flavor a `.toc` suffix, eg. `Mainline` or `BCC`, stands for.
+pub(crate) fn flavor_from_toc_suffix(suffix: &str) -> Option<Flavor> {
+    match suffix.to_lowercase().as_str() {
+        "mainline" | "retail" => Some(Flavor::Retail),
+        "classic" | "vanilla" => Some(Flavor::ClassicEra),
+        "bcc" | "tbc" => Some(Flavor::ClassicTbc),
+        _ => None,
+    }
+}
+
+/// A library the packager pulls in from another repository, declared under
+/// `externals:` in a `.pkgmeta` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct External {
+    /// Path the library is placed at, relative to the addon folder, eg. `Libs/LibStub`.
+    pub path: String,
+    pub url: String,
+    pub tag: Option<String>,
+}
+
+/// The parts of a `.pkgmeta` file, as used by the common addon packager, which
+/// tell the addon's own code apart from bundled libraries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PkgMeta {
+    /// Name of the primary folder, if it differs from the repository name.
+    pub package_as: Option<String>,
+    pub externals: Vec<External>,
+    /// Folders moved out of the package into their own top level folder, by
+    /// path inside the package.
+    pub move_folders: HashMap<String, String>,
+    /// Paths left out of the package.
+    pub ignore: Vec<String>,
+}
+
+impl PkgMeta {
+    /// Returns `true` if `path`, relative to the addon folder, is part of an
+    /// external library rather than the addon's own code.
+    pub fn is_external(&self, path: &Path) -> bool {
+        self.externals
+            .iter()
+            .any(|external| path.starts_with(&external.path))
+    }
+}
+
+#[derive(Deserialize)]
+struct RawPkgMeta {
+    #[serde(default, rename = "package-as")]
+    package_as: Option<String>,
+    #[serde(default)]
+    externals: BTreeMap<String, RawExternal>,
+    #[serde(default, rename = "move-folders")]
+    move_folders: HashMap<String, String>,
+    #[serde(default)]
+    ignore: Vec<String>,
+}
+
+/// An external is either given by its url only, or with further settings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawExternal {
+    Url(String),
+    Detailed { url: String, tag: Option<String> },
+}
+
+/// Parses the `.pkgmeta` file at `path`.
+pub fn parse_pkgmeta(path: &Path) -> Result<PkgMeta, ParseError> {
+    if !path.is_file() {
+        return Err(ParseError::InvalidFile {
+            path: path.to_path_buf(),
+        });
+    }
+
+    let raw: RawPkgMeta =
+        serde_yaml::from_str(&std::fs::read_to_string(path)?).map_err(FilesystemError::from)?;
+
+    let externals = raw
+        .externals
+        .into_iter()
+        .map(|(path, external)| match external {
+            RawExternal::Url(url) => External {
+                path,
+                url,
+                tag: None,
+            },
+            RawExternal::Detailed { url, tag } => External { path, url, tag },
+        })
+        .collect();
+
+    Ok(PkgMeta {
+        package_as: raw.package_as,
+        externals,
+        move_folders: raw.move_folders,
+        ignore: raw.ignore,
+    })
+}
+
 /// Helper function to split a comma separated string into `Vec<String>`.
 fn split_dependencies_into_vec(value: &str) -> Vec<String> {
     if value.is_empty() {
@@ -1199,4 +1542,382 @@ mod tests {
         let title = RE_TOC_TITLE.replace_all("|cff1784d1ElvUI |cff83F3F7Absorb Tags", "$1");
         assert_eq!(title, "ElvUI Absorb Tags");
     }
+
+    #[test]
+    fn test_parse_toc_encodings() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+        let toc_path = addon_dir.join("Foo.toc");
+        let toc = "## Interface: 90105\r\n## Title: Föö\r\n## Author: Bär\r\n";
+
+        let utf8_bom = [&[0xEF, 0xBB, 0xBF], toc.as_bytes()].concat();
+        let utf16_le = [0xFF, 0xFE]
+            .iter()
+            .copied()
+            .chain(toc.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect::<Vec<u8>>();
+        let utf16_be = [0xFE, 0xFF]
+            .iter()
+            .copied()
+            .chain(toc.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect::<Vec<u8>>();
+
+        for bytes in &[utf8_bom, utf16_le, utf16_be] {
+            std::fs::write(&toc_path, bytes).unwrap();
+
+            let addon_folder = parse_toc_path(&toc_path).unwrap();
+            assert_eq!(addon_folder.title, "Föö");
+            assert_eq!(addon_folder.author.as_deref(), Some("Bär"));
+            assert_eq!(addon_folder.interfaces, vec![90105]);
+        }
+
+        // Invalid UTF-8 is replaced instead of dropping the line.
+        std::fs::write(&toc_path, b"## Title: Foo\xFF\n## Author: Bar").unwrap();
+        let addon_folder = parse_toc_path(&toc_path).unwrap();
+        assert_eq!(addon_folder.title, "Foo\u{FFFD}");
+        assert_eq!(addon_folder.author.as_deref(), Some("Bar"));
+    }
+
+    #[test]
+    fn test_parse_toc_path_cached() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+
+        let toc_path = addon_dir.join("Foo.toc");
+        std::fs::write(&toc_path, "## Title: Foo").unwrap();
+        let modified = toc_path.metadata().unwrap().modified().unwrap();
+
+        let toc_cache = std::sync::Mutex::new(TocCache::default());
+        assert_eq!(
+            parse_toc_path_cached(&toc_path, &toc_cache).unwrap().title,
+            "Foo"
+        );
+
+        // Unchanged files are served from the cache.
+        let mut cached = parse_toc_path(&toc_path).unwrap();
+        cached.title = "Cached".to_string();
+        toc_cache
+            .lock()
+            .unwrap()
+            .insert(toc_path.clone(), modified, cached.clone());
+        assert_eq!(
+            parse_toc_path_cached(&toc_path, &toc_cache).unwrap().title,
+            "Cached"
+        );
+
+        // A different modified time invalidates the entry.
+        toc_cache.lock().unwrap().insert(
+            toc_path.clone(),
+            modified - std::time::Duration::from_secs(1),
+            cached,
+        );
+        assert_eq!(
+            parse_toc_path_cached(&toc_path, &toc_cache).unwrap().title,
+            "Foo"
+        );
+    }
+
+    #[test]
+    fn test_parse_toc_multiple_interfaces() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+
+        let toc_path = addon_dir.join("Foo_Mainline.toc");
+        std::fs::write(
+            &toc_path,
+            "## Interface: 100207, 90105, 20502,11401\n## Title: Foo",
+        )
+        .unwrap();
+
+        let addon_folder = parse_toc_path(&toc_path).unwrap();
+        assert_eq!(addon_folder.interfaces, vec![100207, 90105, 20502, 11401]);
+        assert_eq!(
+            addon_folder.interface.as_deref(),
+            Some("10.2.7, 9.1.5, 2.5.2, 1.14.1")
+        );
+        assert_eq!(addon_folder.toc_flavor, Some(Flavor::Retail));
+
+        let toc_path = addon_dir.join("Foo-BCC.toc");
+        std::fs::write(&toc_path, "## Interface: 20502").unwrap();
+        assert_eq!(
+            parse_toc_path(&toc_path).unwrap().toc_flavor,
+            Some(Flavor::ClassicTbc)
+        );
+
+        let toc_path = addon_dir.join("Foo.toc");
+        std::fs::write(&toc_path, "## Interface: 90105").unwrap();
+        assert_eq!(parse_toc_path(&toc_path).unwrap().toc_flavor, None);
+    }
+
+    #[test]
+    fn test_parse_toc_repository_identifiers() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+
+        let toc_path = addon_dir.join("Foo.toc");
+        std::fs::write(
+            &toc_path,
+            "## Title: Foo\r\n## X-Curse-Project-ID: 12345\r\n## X-WoWI-ID: 678 \r\n## X-Wago-ID: aNDmy96o\r\n",
+        )
+        .unwrap();
+
+        let identifiers = parse_toc_path(&toc_path).unwrap().repository_identifiers;
+        assert_eq!(identifiers.curse, Some(12345));
+        assert_eq!(identifiers.wowi.as_deref(), Some("678"));
+        assert_eq!(identifiers.wago.as_deref(), Some("aNDmy96o"));
+        assert_eq!(identifiers.tukui, None);
+    }
+
+    #[test]
+    fn test_parse_toc_flavor_interfaces() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+
+        let toc_path = addon_dir.join("Foo.toc");
+        std::fs::write(
+            &toc_path,
+            "## Interface: 90105\n## Interface-Classic: 11401, 11402\n## Interface-BCC: 20502\n## Interface-Mainline: 90105\n## Interface-Wrath: 30400\n",
+        )
+        .unwrap();
+
+        let addon_folder = parse_toc_path(&toc_path).unwrap();
+        assert_eq!(
+            addon_folder.flavor_interfaces.get(&Flavor::ClassicEra),
+            Some(&vec![11401, 11402])
+        );
+        assert_eq!(
+            addon_folder.flavor_interfaces.get(&Flavor::ClassicTbc),
+            Some(&vec![20502])
+        );
+        assert_eq!(
+            addon_folder.flavor_interfaces.get(&Flavor::Retail),
+            Some(&vec![90105])
+        );
+        assert_eq!(addon_folder.flavor_interfaces.len(), 3);
+        assert_eq!(addon_folder.interfaces, vec![90105, 11401, 11402, 20502]);
+
+        assert_eq!(
+            addon_folder.interface_for_flavor(Flavor::ClassicTbc),
+            Some("2.5.2".to_string())
+        );
+        assert_eq!(
+            addon_folder.interface_for_flavor(Flavor::ClassicEraPtr),
+            Some("1.14.1, 1.14.2".to_string())
+        );
+
+        std::fs::write(&toc_path, "## Interface: 90105").unwrap();
+        let addon_folder = parse_toc_path(&toc_path).unwrap();
+        assert_eq!(
+            addon_folder.interface_for_flavor(Flavor::ClassicTbc),
+            addon_folder.interface
+        );
+    }
+
+    #[test]
+    fn test_parse_toc_metadata() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+
+        let toc_path = addon_dir.join("Foo.toc");
+        std::fs::write(
+            &toc_path,
+            "## Title: |cff1784d1Foo|r Bar\n## Author: Someone\n## Notes: |cffffd200Does things|r",
+        )
+        .unwrap();
+
+        let addon_folder = parse_toc_path(&toc_path).unwrap();
+        assert_eq!(addon_folder.title, "Foo Bar");
+        assert_eq!(addon_folder.author.as_deref(), Some("Someone"));
+        assert_eq!(addon_folder.notes.as_deref(), Some("Does things"));
+
+        std::fs::write(&toc_path, "## Title: |cff1784d1|r\n## Author: ").unwrap();
+
+        let addon_folder = parse_toc_path(&toc_path).unwrap();
+        assert_eq!(addon_folder.title, "Foo");
+        assert_eq!(addon_folder.author, None);
+    }
+
+    #[test]
+    fn test_parse_toc_dependencies() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+
+        let toc_path = addon_dir.join("Foo.toc");
+        std::fs::write(
+            &toc_path,
+            "## Dependencies: LibStub\n## RequiredDeps: Ace3\n## OptionalDeps: Masque, LibSharedMedia-3.0",
+        )
+        .unwrap();
+
+        let addon_folder = parse_toc_path(&toc_path).unwrap();
+        assert_eq!(addon_folder.dependencies, vec!["LibStub", "Ace3"]);
+        assert_eq!(
+            addon_folder.optional_dependencies,
+            vec!["Masque", "LibSharedMedia-3.0"]
+        );
+    }
+
+    #[test]
+    fn test_parse_toc_line_endings() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+
+        let toc_path = addon_dir.join("Foo.toc");
+        std::fs::write(
+            &toc_path,
+            "## Title: Foo Bar\r\n## Version: 1.2.3\r\n## Author: Someone \r## X-WoWI-ID: 1234\n## Interface: 90100\r\n",
+        )
+        .unwrap();
+
+        let addon_folder = parse_toc_path(&toc_path).unwrap();
+        assert_eq!(addon_folder.title, "Foo Bar");
+        assert_eq!(addon_folder.version.as_deref(), Some("1.2.3"));
+        assert_eq!(addon_folder.author.as_deref(), Some("Someone"));
+        assert_eq!(
+            addon_folder.repository_identifiers.wowi.as_deref(),
+            Some("1234")
+        );
+        assert_eq!(addon_folder.interface.as_deref(), Some("9.1.0"));
+        assert_eq!(addon_folder.interfaces, vec![90100]);
+    }
+
+    #[test]
+    fn test_parse_toc_other_name() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("BigFolderName");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+
+        let toc_path = addon_dir.join("Short.toc");
+        std::fs::write(&toc_path, "## Title: Short\n## Version: 1.0.0").unwrap();
+        assert_eq!(
+            primary_toc_path(&addon_dir, "BigFolderName"),
+            Some(toc_path.clone())
+        );
+
+        let addon_folder = parse_toc_path(&toc_path).unwrap();
+        assert_eq!(addon_folder.id, "BigFolderName");
+        assert_eq!(addon_folder.title, "Short");
+        assert_eq!(addon_folder.version.as_deref(), Some("1.0.0"));
+    }
+
+    #[test]
+    fn test_fingerprint_addon_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let addon_dir = tempdir.path().join("Foo");
+        std::fs::create_dir_all(&addon_dir).unwrap();
+
+        std::fs::write(
+            addon_dir.join("Foo.toc"),
+            "## Interface: 90105\n## Title: Foo\n\nFoo.lua\n# Unused.lua\n",
+        )
+        .unwrap();
+        std::fs::write(addon_dir.join("Foo.lua"), "print( 'foo' )\n").unwrap();
+        // Not referenced by the `.toc`, so it must not be part of the fingerprint.
+        std::fs::write(addon_dir.join("Unused.lua"), "print('unused')").unwrap();
+
+        // CurseForge hashes every included file with murmur2 and seed 1, after
+        // removing spaces, tabs and line breaks. The folder fingerprint is the
+        // hash of those hashes, sorted and concatenated as decimal strings.
+        let toc = calculate_hash(b"##Interface:90105##Title:FooFoo.lua#Unused.lua", 1);
+        let lua = calculate_hash(b"print('foo')", 1);
+        let expected = calculate_hash(format!("{}{}", toc.min(lua), toc.max(lua)).as_bytes(), 1);
+        assert_eq!(fingerprint_addon_dir(&addon_dir).unwrap(), expected);
+
+        // Whitespace is normalized away.
+        std::fs::write(addon_dir.join("Foo.lua"), "print('foo')\r\n\t").unwrap();
+        assert_eq!(fingerprint_addon_dir(&addon_dir).unwrap(), expected);
+
+        std::fs::write(addon_dir.join("Foo.lua"), "print('bar')").unwrap();
+        assert_ne!(fingerprint_addon_dir(&addon_dir).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_addon_dir_names() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+
+        for folder in &[
+            "Foo",
+            "Bar",
+            ".git",
+            ".ajour-staging-Foo",
+            ".ajour-staging-old-Bar",
+        ] {
+            std::fs::create_dir_all(root.join(folder)).unwrap();
+        }
+        std::fs::write(root.join("addons.txt"), "").unwrap();
+
+        let mut all_dirs = addon_dir_names(root);
+        all_dirs.sort();
+        assert_eq!(all_dirs, vec!["Bar", "Foo"]);
+
+        // A `.toc` which can't be read fails with the reason.
+        let toc_path = root.join("Foo").join("Foo.toc");
+        std::fs::create_dir_all(&toc_path).unwrap();
+        assert_eq!(
+            read_toc_path(&toc_path),
+            Err(std::fs::read(&toc_path).unwrap_err().to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_pkgmeta() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join(".pkgmeta");
+
+        std::fs::write(
+            &path,
+            "package-as: Foo\n\
+             externals:\n  \
+               Libs/LibStub: https://repos.wowace.com/wow/libstub/trunk\n  \
+               Libs/AceDB-3.0:\n    \
+                 url: https://repos.wowace.com/wow/ace3/trunk/AceDB-3.0\n    \
+                 tag: latest\n\
+             move-folders:\n  \
+               Foo/Modules/Bar: Foo_Bar\n\
+             ignore:\n  \
+               - README.md\n",
+        )
+        .unwrap();
+
+        let pkgmeta = parse_pkgmeta(&path).unwrap();
+        assert_eq!(pkgmeta.package_as.as_deref(), Some("Foo"));
+        assert_eq!(
+            pkgmeta.externals,
+            vec![
+                External {
+                    path: "Libs/AceDB-3.0".to_string(),
+                    url: "https://repos.wowace.com/wow/ace3/trunk/AceDB-3.0".to_string(),
+                    tag: Some("latest".to_string()),
+                },
+                External {
+                    path: "Libs/LibStub".to_string(),
+                    url: "https://repos.wowace.com/wow/libstub/trunk".to_string(),
+                    tag: None,
+                },
+            ]
+        );
+        assert_eq!(
+            pkgmeta
+                .move_folders
+                .get("Foo/Modules/Bar")
+                .map(String::as_str),
+            Some("Foo_Bar")
+        );
+        assert_eq!(pkgmeta.ignore, vec!["README.md"]);
+
+        assert!(pkgmeta.is_external(Path::new("Libs/LibStub/LibStub.lua")));
+        assert!(!pkgmeta.is_external(Path::new("Libs/LibStubby.lua")));
+        assert!(!pkgmeta.is_external(Path::new("Foo.lua")));
+
+        assert!(parse_pkgmeta(&tempdir.path().join("missing")).is_err());
+    }
 }
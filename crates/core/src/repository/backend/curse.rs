@@ -94,6 +94,7 @@ pub(crate) fn metadata_from_curse_package(flavor: Flavor, package: Package) -> R
                 date_time,
                 file_id: Some(file.id),
                 modules,
+                changelog: None,
             };
 
             match file.release_type {
@@ -142,6 +143,7 @@ pub(crate) fn metadata_from_fingerprint_info(
                 date_time,
                 file_id: Some(file.id),
                 modules,
+                changelog: None,
             };
 
             match file.release_type {
@@ -313,6 +313,7 @@ mod github {
                 date_time,
                 file_id: None,
                 modules: vec![],
+                changelog: Some(release.body.clone()),
             };
 
             remote_packages.insert(release_channel, remote_package);
@@ -356,9 +357,33 @@ mod gitlab {
     #[derive(Debug, Clone)]
     pub struct Gitlab {
         pub url: Uri,
+        /// Base url of the instance hosting `url`, eg. `https://gitlab.com`.
+        pub base_url: String,
         pub flavor: Flavor,
     }
 
+    impl Gitlab {
+        /// Base url of the API of the instance hosting `url`.
+        pub(crate) fn api_url(&self) -> String {
+            format!("{}/api/v4", self.base_url.trim_end_matches('/'))
+        }
+
+        /// Path of the project on its instance, eg. `group/sub/bar`, without the
+        /// path of the instance itself.
+        pub(crate) fn project_path(&self) -> &str {
+            let prefix = self
+                .base_url
+                .parse::<Uri>()
+                .map(|base| base.path().trim_end_matches('/').to_string())
+                .unwrap_or_default();
+            let path = self.url.path();
+
+            path.strip_prefix(prefix.as_str())
+                .unwrap_or(path)
+                .trim_start_matches('/')
+        }
+    }
+
     /// Tries to find asset by looking for a `release.json` file in the assets.
     /// If we find any, we download and parse it.
     /// If we don't find it we return, to use our fallback solution.
@@ -437,9 +462,7 @@ mod gitlab {
     #[async_trait]
     impl Backend for Gitlab {
         async fn get_metadata(&self) -> Result<RepositoryMetadata, RepositoryError> {
-            let mut path = self.url.path();
-            // Remove leading slash
-            path = path.trim_start_matches('/');
+            let path = self.project_path();
 
             // Encode entire path for API
             let encoded = urlencoding::encode(path);
@@ -450,7 +473,7 @@ mod gitlab {
                 url: self.url.to_string(),
             })?;
 
-            let url = format!("https://gitlab.com/api/v4/projects/{}/releases", &encoded);
+            let url = format!("{}/projects/{}/releases", self.api_url(), &encoded);
 
             let mut resp = request_async(&url, vec![], None).await?;
 
@@ -480,6 +503,7 @@ mod gitlab {
                     date_time,
                     file_id: None,
                     modules: vec![],
+                    changelog: Some(release.description.clone()),
                 };
 
                 remote_packages.insert(ReleaseChannel::Stable, remote_package);
@@ -508,16 +532,16 @@ mod gitlab {
         ) -> Result<Option<String>, RepositoryError> {
             let tag_name = tag_name.ok_or(RepositoryError::GitChangelogTagName)?;
 
-            let mut path = self.url.path();
-            // Remove leading slash
-            path = path.trim_start_matches('/');
+            let path = self.project_path();
 
             // Encode entire path for API
             let encoded = urlencoding::encode(path);
 
             let url = format!(
-                "https://gitlab.com/api/v4/projects/{}/releases/{}",
-                encoded, tag_name
+                "{}/projects/{}/releases/{}",
+                self.api_url(),
+                encoded,
+                tag_name
             );
 
             let mut resp = request_async(&url, vec![], None).await?;
@@ -553,3 +577,29 @@ mod gitlab {
         pub url: String,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gitlab_api_url() {
+        let gitlab = Gitlab {
+            url: "https://gitlab.com/foo/bar".parse().unwrap(),
+            base_url: "https://gitlab.com".to_string(),
+            flavor: Flavor::Retail,
+        };
+        assert_eq!(gitlab.api_url(), "https://gitlab.com/api/v4");
+        assert_eq!(gitlab.project_path(), "foo/bar");
+
+        let gitlab = Gitlab {
+            url: "http://example.org:8080/gitlab/group/sub/bar"
+                .parse()
+                .unwrap(),
+            base_url: "http://example.org:8080/gitlab".to_string(),
+            flavor: Flavor::Retail,
+        };
+        assert_eq!(gitlab.api_url(), "http://example.org:8080/gitlab/api/v4");
+        assert_eq!(gitlab.project_path(), "group/sub/bar");
+    }
+}
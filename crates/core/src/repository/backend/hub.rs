@@ -64,6 +64,7 @@ pub(crate) fn metadata_from_hub_package(flavor: Flavor, package: HubPackage) ->
                 date_time,
                 file_id: Some(release.id),
                 modules: vec![],
+                changelog: None,
             };
 
             if release.prerelease {
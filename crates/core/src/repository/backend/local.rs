@@ -0,0 +1,210 @@
+use super::*;
+use crate::addon::Addon;
+use crate::error::RepositoryError;
+use crate::repository::{ReleaseChannel, RemotePackage, RepositoryPackage};
+use crate::utility::compare_versions;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Scheme of the `download_url` of packages served from a local directory.
+const URL_SCHEME: &str = "file://";
+
+/// Repository backed by a local directory of addon archives named
+/// `<id>-<version>.zip`, eg. `DBM-Core-9.1.5.zip`, for offline setups.
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub dir: PathBuf,
+    pub id: String,
+}
+
+#[async_trait]
+impl Backend for Local {
+    async fn get_metadata(&self) -> Result<RepositoryMetadata, RepositoryError> {
+        let archives = read_archives(&self.dir)?;
+
+        archives
+            .get(&self.id)
+            .map(|archives| metadata_from_archives(&self.id, archives))
+            .ok_or(RepositoryError::LocalMissingPackage {
+                id: self.id.clone(),
+            })
+    }
+
+    async fn get_changelog(
+        &self,
+        _file_id: Option<i64>,
+        _tag_name: Option<String>,
+    ) -> Result<Option<String>, RepositoryError> {
+        Ok(None)
+    }
+}
+
+/// An addon archive found in a local repository directory.
+#[derive(Debug, Clone)]
+struct Archive {
+    version: String,
+    path: PathBuf,
+    modified: Option<DateTime<Utc>>,
+}
+
+/// Returns an installable `Addon` for every addon id with an archive in `dir`,
+/// linked to its newest version, sorted by id.
+///
+/// Installing one copies its archive instead of downloading it, and then goes
+/// through `install_addon` like any other source.
+pub fn fetch_addons(dir: &Path) -> Result<Vec<Addon>, RepositoryError> {
+    let addons = read_archives(dir)?
+        .iter()
+        .map(|(id, archives)| {
+            let package = RepositoryPackage::from_local_dir(dir, id.clone())
+                .with_metadata(metadata_from_archives(id, archives));
+
+            let mut addon = Addon::empty(id);
+            addon.set_repository(package);
+            addon
+        })
+        .collect();
+
+    Ok(addons)
+}
+
+/// Returns the archive path of a package served from a local directory, or
+/// `None` if `download_url` is a remote url.
+pub(crate) fn archive_path(download_url: &str) -> Option<PathBuf> {
+    download_url.strip_prefix(URL_SCHEME).map(PathBuf::from)
+}
+
+/// Reads the archives in `dir`, grouped by addon id. Files which aren't named
+/// like an addon archive are skipped.
+fn read_archives(dir: &Path) -> Result<BTreeMap<String, Vec<Archive>>, RepositoryError> {
+    let mut archives: BTreeMap<String, Vec<Archive>> = BTreeMap::new();
+
+    for entry in dir.read_dir()?.filter_map(Result::ok) {
+        let path = entry.path();
+
+        if path.extension().and_then(|ext| ext.to_str()) != Some("zip") || !path.is_file() {
+            continue;
+        }
+
+        let (id, version) = match path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(split_archive_name)
+        {
+            Some(split) => split,
+            None => continue,
+        };
+
+        let modified = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .map(DateTime::<Utc>::from);
+
+        archives.entry(id).or_default().push(Archive {
+            version,
+            path,
+            modified,
+        });
+    }
+
+    Ok(archives)
+}
+
+/// Splits an archive file stem, `<id>-<version>`, at the first `-` followed by
+/// a version, so ids can contain dashes themselves, eg. `DBM-Core-9.1.5`.
+fn split_archive_name(stem: &str) -> Option<(String, String)> {
+    let split = stem.match_indices('-').map(|(idx, _)| idx).find(|idx| {
+        let version = stem[idx + 1..].trim_start_matches(|c: char| c == 'v' || c == 'V');
+        version.starts_with(|c: char| c.is_ascii_digit())
+    })?;
+
+    let (id, version) = (&stem[..split], &stem[split + 1..]);
+
+    if id.is_empty() {
+        return None;
+    }
+
+    Some((id.to_string(), version.to_string()))
+}
+
+/// Builds the metadata of addon `id` out of its newest archive.
+fn metadata_from_archives(id: &str, archives: &[Archive]) -> RepositoryMetadata {
+    let mut metadata = RepositoryMetadata::empty();
+    metadata.title = Some(id.to_string());
+
+    if let Some(newest) = archives
+        .iter()
+        .max_by(|a, b| compare_versions(&a.version, &b.version))
+    {
+        let package = RemotePackage {
+            version: newest.version.clone(),
+            download_url: format!("{}{}", URL_SCHEME, newest.path.display()),
+            file_id: None,
+            date_time: newest.modified,
+            modules: vec![],
+            changelog: None,
+        };
+
+        metadata
+            .remote_packages
+            .insert(ReleaseChannel::Stable, package);
+    }
+
+    metadata
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_addons() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+
+        for name in &[
+            "Foo-1.9.0.zip",
+            "Foo-1.10.0.zip",
+            "DBM-Core-v9.1.5.zip",
+            "NoVersion.zip",
+            "Bar-1.0.0.txt",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let addons = fetch_addons(dir).unwrap();
+        let packages: Vec<_> = addons
+            .iter()
+            .map(|a| {
+                let package = a.fallback_release_package().unwrap();
+                (
+                    a.primary_folder_id.as_str(),
+                    package.version,
+                    archive_path(&package.download_url),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            packages,
+            vec![
+                (
+                    "DBM-Core",
+                    "v9.1.5".to_string(),
+                    Some(dir.join("DBM-Core-v9.1.5.zip"))
+                ),
+                (
+                    "Foo",
+                    "1.10.0".to_string(),
+                    Some(dir.join("Foo-1.10.0.zip"))
+                ),
+            ]
+        );
+        assert_eq!(archive_path("https://example.com/Foo-1.0.0.zip"), None);
+    }
+}
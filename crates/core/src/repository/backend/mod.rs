@@ -7,12 +7,14 @@ use dyn_clone::{clone_trait_object, DynClone};
 pub mod curse;
 pub mod git;
 pub mod hub;
+pub mod local;
 pub mod tukui;
 pub mod wowi;
 
 pub use curse::Curse;
 pub use git::{Github, Gitlab};
 pub use hub::Hub;
+pub use local::Local;
 pub use tukui::Tukui;
 pub use wowi::WowI;
 
@@ -1,17 +1,25 @@
 use super::*;
+use crate::addon::{Addon, AddonFolder};
 use crate::config::Flavor;
 use crate::error::{DownloadError, RepositoryError};
 use crate::network::request_async;
-use crate::repository::{ReleaseChannel, RemotePackage, RepositoryKind, RepositoryPackage};
-use crate::utility::{regex_html_tags_to_newline, regex_html_tags_to_space, truncate};
+use crate::repository::{
+    ReleaseChannel, RemotePackage, RepositoryIdentifiers, RepositoryKind, RepositoryPackage,
+};
+use crate::utility::{
+    flavor_from_interface, game_version_to_interface, regex_html_tags_to_newline,
+    regex_html_tags_to_space, truncate,
+};
 
 use async_trait::async_trait;
 use chrono::{NaiveDateTime, TimeZone, Utc};
 use futures::future::join_all;
 use isahc::AsyncReadResponseExt;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 
 use std::collections::HashMap;
+use std::path::Path;
 
 #[derive(Debug, Clone)]
 pub struct Tukui {
@@ -34,33 +42,25 @@ impl Backend for Tukui {
         _file_id: Option<i64>,
         _tag_name: Option<String>,
     ) -> Result<Option<String>, RepositoryError> {
-        let url = changelog_endpoint(&self.id, &self.flavor);
-
-        match self.flavor {
-            Flavor::Retail | Flavor::RetailBeta | Flavor::RetailPtr => {
-                // Only TukUI and ElvUI main addons has changelog which can be fetched.
-                // The others is embeded into a page.
-                if &self.id == "-1" || &self.id == "-2" {
-                    let mut resp = request_async(&url, vec![], None).await?;
-
-                    if resp.status().is_success() {
-                        let changelog: String = resp.text().await?;
-
-                        let c = regex_html_tags_to_newline()
-                            .replace_all(&changelog, "\n")
-                            .to_string();
-                        let c = regex_html_tags_to_space().replace_all(&c, "").to_string();
-                        let c = truncate(&c, 2500).to_string();
-
-                        return Ok(Some(c));
-                    }
-                }
-            }
-            Flavor::ClassicTbc
-            | Flavor::ClassicPtr
-            | Flavor::ClassicBeta
-            | Flavor::ClassicEra
-            | Flavor::ClassicEraPtr => {}
+        let (_, package) = fetch_remote_package(&self.id, &self.flavor).await?;
+
+        let url = match package.changelog_url {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+
+        let mut resp = request_async(&url, vec![], None).await?;
+
+        if resp.status().is_success() {
+            let changelog: String = resp.text().await?;
+
+            let c = regex_html_tags_to_newline()
+                .replace_all(&changelog, "\n")
+                .to_string();
+            let c = regex_html_tags_to_space().replace_all(&c, "").to_string();
+            let c = truncate(&c, 2500).to_string();
+
+            return Ok(Some(c));
         }
 
         Ok(None)
@@ -74,10 +74,10 @@ pub(crate) fn metadata_from_tukui_package(package: TukuiPackage) -> RepositoryMe
         let version = package.version.clone();
         let download_url = package.url.clone();
 
-        let date_time = NaiveDateTime::parse_from_str(&package.lastupdate, "%Y-%m-%d %H:%M:%S")
+        let date_time = NaiveDateTime::parse_from_str(&package.last_update, "%Y-%m-%d %H:%M:%S")
             .map_or(
                 NaiveDateTime::parse_from_str(
-                    &format!("{} 00:00:00", &package.lastupdate),
+                    &format!("{} 00:00:00", &package.last_update),
                     "%Y-%m-%d %T",
                 ),
                 std::result::Result::Ok,
@@ -91,18 +91,21 @@ pub(crate) fn metadata_from_tukui_package(package: TukuiPackage) -> RepositoryMe
             date_time,
             file_id: None,
             modules: vec![],
+            changelog: None,
         };
 
         // Since Tukui does not support release channels, our default is 'stable'.
         remote_packages.insert(ReleaseChannel::Stable, package);
     }
 
-    let website_url = Some(package.web_url.clone());
-    let changelog_url = Some(format!("{}&changelog", package.web_url));
+    let changelog_url = package.changelog_url;
+    let website_url = Some(package.web_url);
     let game_version = package.patch;
     let title = package.name;
 
     let mut metadata = RepositoryMetadata::empty();
+    metadata.author = package.author;
+    metadata.notes = package.small_desc;
     metadata.website_url = website_url;
     metadata.changelog_url = changelog_url;
     metadata.game_version = game_version;
@@ -112,44 +115,86 @@ pub(crate) fn metadata_from_tukui_package(package: TukuiPackage) -> RepositoryMe
     metadata
 }
 
-/// Returns flavor `String` in Tukui format
-fn format_flavor(flavor: &Flavor) -> String {
-    let base_flavor = flavor.base_flavor();
-    match base_flavor {
-        Flavor::Retail => "retail".to_owned(),
-        Flavor::ClassicTbc => "tbc".to_owned(),
-        Flavor::ClassicEra => "classic".to_owned(),
-        _ => panic!("Unknown base flavor {}", base_flavor),
-    }
+/// Returns an `Addon` for the Tukui `package`, ready to be passed to `install_addon`.
+///
+/// The top level folders of the package are added as `folders` inside `addon_dir`,
+/// so the addon can later be updated and deleted. The first folder is the primary one.
+pub(crate) fn addon_from_tukui_package(
+    id: &str,
+    flavor: Flavor,
+    package: TukuiPackage,
+    addon_dir: &Path,
+) -> Result<Addon, RepositoryError> {
+    let directories = if package.directories.is_empty() {
+        vec![package.name.clone()]
+    } else {
+        package.directories.clone()
+    };
+
+    let mut addon = Addon::empty(&directories[0]);
+    addon.folders = directories
+        .iter()
+        .map(|directory| AddonFolder {
+            id: directory.clone(),
+            title: directory.clone(),
+            path: addon_dir.join(directory),
+            version: Some(package.version.clone()),
+            repository_identifiers: RepositoryIdentifiers {
+                tukui: Some(id.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .collect();
+
+    let metadata = metadata_from_tukui_package(package);
+    let repository =
+        RepositoryPackage::from_repo_id(flavor, RepositoryKind::Tukui, id.to_string())?
+            .with_metadata(metadata);
+    addon.set_repository(repository);
+
+    Ok(addon)
 }
 
-/// Return the tukui API endpoint.
-fn api_endpoint(id: &str, flavor: &Flavor) -> String {
-    format!(
-        "https://hub.wowup.io/tukui/{}/{}",
-        format_flavor(flavor),
-        id
-    )
+/// Fetches the Tukui addon `id` and returns it as an `Addon` which installs into `addon_dir`.
+pub async fn fetch_addon(
+    id: &str,
+    flavor: Flavor,
+    addon_dir: &Path,
+) -> Result<Addon, RepositoryError> {
+    let (id, package) = fetch_remote_package(id, &flavor).await?;
+
+    addon_from_tukui_package(&id, flavor, package, addon_dir)
 }
 
-fn changelog_endpoint(id: &str, flavor: &Flavor) -> String {
-    match flavor {
-        Flavor::Retail | Flavor::RetailPtr | Flavor::RetailBeta => match id {
-            "-1" => "https://www.tukui.org/ui/tukui/changelog".to_owned(),
-            "-2" => "https://www.tukui.org/ui/elvui/changelog".to_owned(),
-            _ => format!("https://www.tukui.org/addons.php?id={}&changelog", id),
-        },
-        Flavor::ClassicEra | Flavor::ClassicEraPtr => format!(
-            "https://www.tukui.org/classic-addons.php?id={}&changelog",
-            id
-        ),
-        Flavor::ClassicTbc | Flavor::ClassicPtr | Flavor::ClassicBeta => format!(
-            "https://www.tukui.org/classic-tbc-addons.php?id={}&changelog",
-            id
-        ),
+/// Base url of the Tukui API.
+const API_ENDPOINT: &str = "https://api.tukui.org/v1";
+
+/// Return the tukui API endpoint.
+///
+/// Tukui and ElvUI have their own endpoint, all other addons are only part of
+/// the addon list. Packages are no longer split by flavor.
+fn api_endpoint(id: &str) -> String {
+    match id {
+        "-1" | "tukui" => format!("{}/addon/tukui", API_ENDPOINT),
+        "-2" | "elvui" => format!("{}/addon/elvui", API_ENDPOINT),
+        _ => addon_list_endpoint(),
     }
 }
 
+/// Returns the endpoint of the list of all addons without their own endpoint.
+fn addon_list_endpoint() -> String {
+    format!("{}/addons", API_ENDPOINT)
+}
+
+/// Returns `true` if `id` has its own endpoint, rather than being part of the addon list.
+fn has_own_endpoint(id: &str) -> bool {
+    matches!(id, "-1" | "-2" | "tukui" | "elvui")
+}
+
+/// Fetches the packages of all `tukui_ids` supporting `flavor`.
+///
+/// The addon list is only downloaded once for all ids without their own endpoint.
 pub(crate) async fn batch_fetch_repo_packages(
     flavor: Flavor,
     tukui_ids: &[String],
@@ -160,16 +205,34 @@ pub(crate) async fn batch_fetch_repo_packages(
         return Ok(tukui_repo_packages);
     }
 
-    let fetch_tasks: Vec<_> = tukui_ids
+    let (own_ids, listed_ids): (Vec<_>, Vec<_>) =
+        tukui_ids.iter().partition(|id| has_own_endpoint(id));
+
+    let fetch_tasks: Vec<_> = own_ids
         .iter()
         .map(|id| tukui::fetch_remote_package(id, &flavor))
         .collect();
+    let mut packages: Vec<_> = join_all(fetch_tasks)
+        .await
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect();
+
+    if !listed_ids.is_empty() {
+        match request_json::<Vec<TukuiPackage>>(addon_list_endpoint()).await {
+            Ok(list) => packages.extend(listed_ids.iter().filter_map(|id| {
+                find_package(&list, id)
+                    .ok()
+                    .filter(|package| supports_flavor(package, flavor))
+                    .map(|package| (id.to_string(), package))
+            })),
+            Err(error) => log::error!("fetching the Tukui addon list failed: {}", error),
+        }
+    }
 
     tukui_repo_packages.extend(
-        join_all(fetch_tasks)
-            .await
+        packages
             .into_iter()
-            .filter_map(Result::ok)
             .map(|(id, package)| (id, tukui::metadata_from_tukui_package(package)))
             .filter_map(|(id, metadata)| {
                 RepositoryPackage::from_repo_id(flavor, RepositoryKind::Tukui, id)
@@ -186,32 +249,173 @@ pub(crate) async fn batch_fetch_repo_packages(
 pub(crate) async fn fetch_remote_package(
     id: &str,
     flavor: &Flavor,
-) -> Result<(String, TukuiPackage), DownloadError> {
-    let url = api_endpoint(id, flavor);
+) -> Result<(String, TukuiPackage), RepositoryError> {
+    let url = api_endpoint(id);
+
+    let package = if has_own_endpoint(id) {
+        request_json(url).await?
+    } else {
+        let packages: Vec<TukuiPackage> = request_json(url).await?;
+        find_package(&packages, id)?
+    };
+
+    if !supports_flavor(&package, *flavor) {
+        return Err(RepositoryError::TukuiUnsupportedFlavor {
+            id: id.to_string(),
+            flavor: *flavor,
+        });
+    }
+
+    Ok((id.to_string(), package))
+}
 
+/// Requests `url` from the Tukui API and deserializes the response.
+async fn request_json<T: DeserializeOwned + Unpin>(url: String) -> Result<T, RepositoryError> {
     let timeout = Some(30);
     let mut resp = request_async(&url, vec![], timeout).await?;
 
-    if resp.status().is_success() {
-        let package = resp.json().await?;
-        Ok((id.to_string(), package))
-    } else {
-        Err(DownloadError::InvalidStatusCode {
+    if !resp.status().is_success() {
+        return Err(DownloadError::InvalidStatusCode {
             code: resp.status(),
             url,
-        })
+        }
+        .into());
+    }
+
+    Ok(resp.json().await?)
+}
+
+/// Returns the package with `id` from the Tukui addon list.
+fn find_package(packages: &[TukuiPackage], id: &str) -> Result<TukuiPackage, RepositoryError> {
+    packages
+        .iter()
+        .find(|p| p.id.to_string() == id || p.slug == id)
+        .cloned()
+        .ok_or_else(|| RepositoryError::TukuiMissingPackage { id: id.to_string() })
+}
+
+/// Returns `true` if one of the game versions `package` lists, eg. `10.2.7,
+/// 1.15.2`, belongs to `flavor`. Packages without game versions are assumed to
+/// support every flavor.
+fn supports_flavor(package: &TukuiPackage, flavor: Flavor) -> bool {
+    match package.patch.as_deref() {
+        Some(patch) if !patch.trim().is_empty() => patch
+            .split(',')
+            .filter_map(game_version_to_interface)
+            .any(|interface| flavor_from_interface(interface) == Some(flavor.base_flavor())),
+        _ => true,
     }
 }
 
 #[derive(Clone, Debug, Deserialize)]
 /// Struct for applying tukui details to an `Addon`.
 pub struct TukuiPackage {
+    pub id: i64,
+    pub slug: String,
     pub name: String,
     pub version: String,
     pub url: String,
     pub web_url: String,
-    pub lastupdate: String,
+    pub last_update: String,
+    pub changelog_url: Option<String>,
     pub patch: Option<String>,
     pub author: Option<String>,
     pub small_desc: Option<String>,
+    /// Top level folders the package installs.
+    #[serde(default)]
+    pub directories: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ELVUI: &str = r#"{
+        "id": 2,
+        "slug": "elvui",
+        "author": "Elv",
+        "name": "ElvUI",
+        "url": "https://api.tukui.org/v1/download/dev/elvui/main",
+        "version": "13.52",
+        "changelog_url": "https://api.tukui.org/v1/changelog/elvui#13.52",
+        "ticket_url": "https://github.com/tukui-org/ElvUI/issues",
+        "git_url": "https://github.com/tukui-org/ElvUI",
+        "patch": "10.2.7",
+        "last_update": "2024-05-07",
+        "web_url": "https://www.tukui.org/elvui",
+        "small_desc": "A user interface replacement.",
+        "directories": ["ElvUI", "ElvUI_Libraries", "ElvUI_Options"]
+    }"#;
+
+    #[test]
+    fn test_addon_from_tukui_package() {
+        let package: TukuiPackage = serde_json::from_str(ELVUI).unwrap();
+        let addon_dir = Path::new("AddOns");
+
+        let addon = addon_from_tukui_package("-2", Flavor::Retail, package, addon_dir).unwrap();
+
+        assert_eq!(addon.primary_folder_id, "ElvUI");
+        assert_eq!(
+            addon
+                .folders
+                .iter()
+                .map(|f| f.path.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                addon_dir.join("ElvUI"),
+                addon_dir.join("ElvUI_Libraries"),
+                addon_dir.join("ElvUI_Options"),
+            ]
+        );
+        assert_eq!(addon.tukui_id(), Some("-2"));
+        assert_eq!(addon.game_version(), Some("10.2.7"));
+
+        let package = &addon.remote_packages()[&ReleaseChannel::Stable];
+        assert_eq!(package.version, "13.52");
+        assert_eq!(
+            package.download_url,
+            "https://api.tukui.org/v1/download/dev/elvui/main"
+        );
+        assert!(package.date_time.is_some());
+    }
+
+    #[test]
+    fn test_find_package() {
+        let mut other: TukuiPackage = serde_json::from_str(ELVUI).unwrap();
+        other.id = 7;
+        other.slug = "addon".to_string();
+        let packages = vec![serde_json::from_str(ELVUI).unwrap(), other];
+
+        assert_eq!(find_package(&packages, "7").unwrap().slug, "addon");
+        assert_eq!(find_package(&packages, "elvui").unwrap().id, 2);
+        assert!(matches!(
+            find_package(&packages, "8"),
+            Err(RepositoryError::TukuiMissingPackage { .. })
+        ));
+    }
+
+    #[test]
+    fn test_supports_flavor() {
+        let mut package: TukuiPackage = serde_json::from_str(ELVUI).unwrap();
+        assert!(supports_flavor(&package, Flavor::Retail));
+        assert!(supports_flavor(&package, Flavor::RetailPtr));
+        assert!(!supports_flavor(&package, Flavor::ClassicEra));
+
+        package.patch = Some("10.2.7, 1.15.2".to_string());
+        assert!(supports_flavor(&package, Flavor::ClassicEra));
+        assert!(!supports_flavor(&package, Flavor::ClassicTbc));
+
+        package.patch = None;
+        assert!(supports_flavor(&package, Flavor::ClassicTbc));
+    }
+
+    #[test]
+    fn test_api_endpoint() {
+        assert_eq!(api_endpoint("-2"), "https://api.tukui.org/v1/addon/elvui");
+        assert_eq!(
+            api_endpoint("tukui"),
+            "https://api.tukui.org/v1/addon/tukui"
+        );
+        assert_eq!(api_endpoint("12"), "https://api.tukui.org/v1/addons");
+    }
 }
@@ -60,6 +60,7 @@ pub(crate) fn metadata_from_wowi_package(package: WowIPackage) -> RepositoryMeta
             date_time: Some(date_time),
             file_id: None,
             modules: vec![],
+            changelog: None,
         };
 
         // Since WowI does not support release channels, our default is 'stable'.
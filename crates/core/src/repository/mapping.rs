@@ -0,0 +1,200 @@
+use super::RepositoryKind;
+use crate::addon::AddonFolder;
+use crate::error::FilesystemError;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A single entry of an addon id mapping, attributing one or more folders to
+/// their source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MappingEntry {
+    /// Folder names of the addon, the first one being its primary folder.
+    pub folders: Vec<String>,
+    pub source: RepositoryKind,
+    /// Id of the addon at `source`.
+    pub id: String,
+}
+
+/// Community maintained mapping of folder names to source ids, as a json list
+/// of `MappingEntry`'s:
+///
+/// ```json
+/// [{ "folders": ["Foo", "Foo_Options"], "source": "WowI", "id": "1234" }]
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Mapping {
+    entries: Vec<MappingEntry>,
+    /// Index into `entries` by lowercased folder name.
+    folders: HashMap<String, usize>,
+}
+
+impl Mapping {
+    /// Builds a mapping from `entries`. A folder listed by more than one entry
+    /// belongs to the first of them.
+    pub fn new(entries: Vec<MappingEntry>) -> Self {
+        let mut folders = HashMap::new();
+
+        for (idx, entry) in entries.iter().enumerate() {
+            for folder in &entry.folders {
+                folders.entry(folder.to_lowercase()).or_insert(idx);
+            }
+        }
+
+        Mapping { entries, folders }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        Ok(Mapping::new(serde_json::from_str(json)?))
+    }
+
+    /// Reads the mapping from the json file at `path`.
+    pub fn load(path: &Path) -> Result<Self, FilesystemError> {
+        if !path.is_file() {
+            return Err(FilesystemError::FileDoesntExist {
+                path: path.to_path_buf(),
+            });
+        }
+
+        Ok(Mapping::from_json(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Returns the entry listing the folder `id`. Folder names are compared
+    /// case-insensitively, like WoW does.
+    pub fn get(&self, id: &str) -> Option<&MappingEntry> {
+        self.folders
+            .get(&id.to_lowercase())
+            .map(|idx| &self.entries[*idx])
+    }
+}
+
+/// Installed folders attributed to a source by a `Mapping`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentifiedAddon {
+    pub primary_folder_id: String,
+    /// Ids of all installed folders belonging to the addon.
+    pub folders: Vec<String>,
+    pub source: RepositoryKind,
+    pub id: String,
+}
+
+/// Attributes the `folders` which have no repository identifiers in their `.toc`
+/// file to a source listed in `mapping`.
+///
+/// Folders of the same mapping entry are grouped into a single addon. Its
+/// primary folder is the first folder of the entry which is installed.
+pub fn identify_folders(folders: &[AddonFolder], mapping: &Mapping) -> Vec<IdentifiedAddon> {
+    let mut identified: Vec<IdentifiedAddon> = vec![];
+
+    for folder in folders.iter().filter(|f| is_unidentified(f)) {
+        let entry = match mapping.get(&folder.id) {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        match identified
+            .iter_mut()
+            .find(|a| a.source == entry.source && a.id == entry.id)
+        {
+            Some(addon) => addon.folders.push(folder.id.clone()),
+            None => identified.push(IdentifiedAddon {
+                primary_folder_id: folder.id.clone(),
+                folders: vec![folder.id.clone()],
+                source: entry.source,
+                id: entry.id.clone(),
+            }),
+        }
+    }
+
+    for addon in identified.iter_mut() {
+        let entry = mapping.get(&addon.primary_folder_id).unwrap();
+        let position = |id: &String| {
+            entry
+                .folders
+                .iter()
+                .position(|f| f.eq_ignore_ascii_case(id))
+        };
+
+        addon.folders.sort_by_key(position);
+        addon.primary_folder_id = addon.folders[0].clone();
+    }
+
+    identified.sort_by(|a, b| a.primary_folder_id.cmp(&b.primary_folder_id));
+    identified
+}
+
+fn is_unidentified(folder: &AddonFolder) -> bool {
+    let identifiers = &folder.repository_identifiers;
+
+    identifiers.curse.is_none()
+        && identifiers.tukui.is_none()
+        && identifiers.wowi.is_none()
+        && identifiers.git.is_none()
+        && identifiers.wago.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify_folders() {
+        let mapping = Mapping::from_json(
+            r#"[
+                { "folders": ["Foo", "Foo_Options"], "source": "WowI", "id": "1234" },
+                { "folders": ["Bar"], "source": "TownlongYak", "id": "42" },
+                { "folders": ["Baz"], "source": "Curse", "id": "7" }
+            ]"#,
+        )
+        .unwrap();
+
+        let folder = |id: &str| {
+            AddonFolder::new(
+                id.to_string(),
+                id.to_string(),
+                None,
+                vec![],
+                HashMap::new(),
+                None,
+                Path::new("AddOns").join(id),
+                None,
+                None,
+                None,
+                Default::default(),
+                vec![],
+                vec![],
+            )
+        };
+
+        let mut baz = folder("Baz");
+        baz.repository_identifiers.curse = Some(7);
+
+        let folders = vec![
+            folder("foo_options"),
+            folder("Foo"),
+            folder("Bar"),
+            baz,
+            folder("Unknown"),
+        ];
+
+        assert_eq!(
+            identify_folders(&folders, &mapping),
+            vec![
+                IdentifiedAddon {
+                    primary_folder_id: "Bar".to_string(),
+                    folders: vec!["Bar".to_string()],
+                    source: RepositoryKind::Hub,
+                    id: "42".to_string(),
+                },
+                IdentifiedAddon {
+                    primary_folder_id: "Foo".to_string(),
+                    folders: vec!["Foo".to_string(), "foo_options".to_string()],
+                    source: RepositoryKind::WowI,
+                    id: "1234".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -1,20 +1,27 @@
+use crate::addon::Addon;
 use crate::config::Flavor;
 use crate::error::{DownloadError, RepositoryError};
 
 use chrono::{DateTime, Utc};
 use isahc::http::uri::Uri;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
 
 mod backend;
 use backend::Backend;
 
-pub use backend::{curse, git, hub, tukui, wowi};
-use backend::{Curse, Github, Gitlab, Hub, Tukui, WowI};
+mod mapping;
+pub use mapping::{identify_folders, IdentifiedAddon, Mapping, MappingEntry};
+
+pub use backend::{curse, git, hub, local, tukui, wowi};
+use backend::{Curse, Github, Gitlab, Hub, Local, Tukui, WowI};
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Serialize, Deserialize)]
 pub enum RepositoryKind {
@@ -24,6 +31,8 @@ pub enum RepositoryKind {
     #[serde(alias = "TownlongYak")]
     Hub,
     Git(GitKind),
+    /// A local directory of addon archives, see `local::fetch_addons`.
+    Local,
 }
 
 impl std::fmt::Display for RepositoryKind {
@@ -40,6 +49,7 @@ impl std::fmt::Display for RepositoryKind {
                     GitKind::Github => "GitHub",
                     GitKind::Gitlab => "GitLab",
                 },
+                RepositoryKind::Local => "Local",
             }
         )
     }
@@ -89,18 +99,21 @@ impl RepositoryPackage {
                 }),
                 RepositoryKind::Git(GitKind::Github),
             ),
-            "gitlab.com" => (
-                Box::new(Gitlab {
-                    url: url.clone(),
-                    flavor,
-                }),
-                RepositoryKind::Git(GitKind::Gitlab),
-            ),
-            _ => {
-                return Err(RepositoryError::GitInvalidHost {
-                    host: host.to_string(),
-                })
-            }
+            _ => match gitlab_base_url(&url) {
+                Some(base_url) => (
+                    Box::new(Gitlab {
+                        url: url.clone(),
+                        base_url,
+                        flavor,
+                    }),
+                    RepositoryKind::Git(GitKind::Gitlab),
+                ),
+                None => {
+                    return Err(RepositoryError::GitInvalidHost {
+                        host: host.to_string(),
+                    })
+                }
+            },
         };
 
         Ok(RepositoryPackage {
@@ -111,6 +124,27 @@ impl RepositoryPackage {
         })
     }
 
+    /// Creates a Github repository package from an `owner/repo` shorthand.
+    pub fn from_github_repo(flavor: Flavor, repo: &str) -> Result<Self, RepositoryError> {
+        let invalid = || RepositoryError::GitInvalidUrl {
+            url: repo.to_string(),
+        };
+
+        let mut parts = repo.trim().trim_matches('/').split('/');
+        let (owner, name) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(owner), Some(name), None) if !owner.is_empty() && !name.is_empty() => {
+                (owner, name)
+            }
+            _ => return Err(invalid()),
+        };
+
+        let url = format!("https://github.com/{}/{}", owner, name)
+            .parse::<Uri>()
+            .map_err(|_| invalid())?;
+
+        Self::from_source_url(flavor, url)
+    }
+
     pub fn from_repo_id(
         flavor: Flavor,
         kind: RepositoryKind,
@@ -134,6 +168,7 @@ impl RepositoryPackage {
                 flavor,
             }),
             RepositoryKind::Git(_) => return Err(RepositoryError::GitWrongConstructor),
+            RepositoryKind::Local => return Err(RepositoryError::LocalWrongConstructor),
         };
 
         Ok(RepositoryPackage {
@@ -144,6 +179,19 @@ impl RepositoryPackage {
         })
     }
 
+    /// Creates a package for the archives of addon `id` in the local directory `dir`.
+    pub fn from_local_dir(dir: &Path, id: String) -> Self {
+        RepositoryPackage {
+            backend: Box::new(Local {
+                dir: dir.to_path_buf(),
+                id: id.clone(),
+            }),
+            id,
+            kind: RepositoryKind::Local,
+            metadata: Default::default(),
+        }
+    }
+
     pub(crate) fn with_metadata(mut self, metadata: RepositoryMetadata) -> Self {
         self.metadata = metadata;
 
@@ -245,6 +293,8 @@ pub struct RemotePackage {
     pub file_id: Option<i64>,
     pub date_time: Option<DateTime<Utc>>,
     pub modules: Vec<String>,
+    /// Release notes published with the package, if the repository has any.
+    pub changelog: Option<String>,
 }
 
 impl PartialOrd for RemotePackage {
@@ -390,13 +440,14 @@ impl FromStr for CompressionFormat {
     }
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 /// Struct which stores identifiers for the different repositories.
 pub struct RepositoryIdentifiers {
     pub wowi: Option<String>,
     pub tukui: Option<String>,
     pub curse: Option<i32>,
     pub git: Option<String>,
+    pub wago: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -459,3 +510,311 @@ pub async fn batch_refresh_repository_packages(
     ]
     .concat())
 }
+
+/// Order in which sources are preferred when an addon folder matches packages
+/// from more than one repository.
+pub const DEFAULT_SOURCE_PRIORITY: [RepositoryKind; 4] = [
+    RepositoryKind::Curse,
+    RepositoryKind::Tukui,
+    RepositoryKind::WowI,
+    RepositoryKind::Hub,
+];
+
+/// Base urls of self-hosted GitLab instances, set with `set_gitlab_instances`.
+static GITLAB_INSTANCES: Lazy<RwLock<Vec<String>>> = Lazy::new(Default::default);
+
+/// Sets the base urls of self-hosted GitLab instances, eg. `https://git.example.org`
+/// or `https://example.org/gitlab`, whose project urls are accepted as sources.
+/// gitlab.com is always accepted. Replaces the previously set urls.
+pub fn set_gitlab_instances(base_urls: Vec<String>) {
+    *GITLAB_INSTANCES.write().unwrap() = base_urls;
+}
+
+/// Returns the base url of the GitLab instance hosting the project at `url`, if
+/// it's gitlab.com or was set with `set_gitlab_instances`.
+fn gitlab_base_url(url: &Uri) -> Option<String> {
+    if url.host() == Some("gitlab.com") {
+        return Some("https://gitlab.com".to_string());
+    }
+
+    GITLAB_INSTANCES
+        .read()
+        .unwrap()
+        .iter()
+        .find_map(|base_url| {
+            let base = base_url.trim().parse::<Uri>().ok()?;
+            let authority = base.authority()?;
+            let prefix = base.path().trim_end_matches('/');
+
+            // The project path has to follow the instance's path, eg. a base url of
+            // `https://example.org/gitlab` doesn't match `https://example.org/foo/bar`.
+            let project = url.path().strip_prefix(prefix)?;
+            if url.authority() != Some(authority) || !project.starts_with('/') {
+                return None;
+            }
+
+            let scheme = base.scheme_str().unwrap_or("https");
+            Some(format!("{}://{}{}", scheme, authority, prefix))
+        })
+}
+
+/// Order in which sources are preferred, set with `set_source_priority`.
+static SOURCE_PRIORITY: Lazy<RwLock<Vec<RepositoryKind>>> = Lazy::new(Default::default);
+
+/// Sources already chosen per flavor, keyed by folder id.
+static CHOSEN_SOURCES: Lazy<RwLock<HashMap<Flavor, HashMap<String, RepositoryKind>>>> =
+    Lazy::new(Default::default);
+
+/// Sets the order in which sources are preferred, see `resolve_source`. An
+/// empty `priority` falls back to `DEFAULT_SOURCE_PRIORITY`.
+pub fn set_source_priority(priority: Vec<RepositoryKind>) {
+    *SOURCE_PRIORITY.write().unwrap() = priority;
+}
+
+/// Sets the sources previously chosen for each folder id, which are kept as
+/// long as they still match. Replaces the previously set sources.
+pub fn set_chosen_sources(sources: HashMap<Flavor, HashMap<String, RepositoryKind>>) {
+    *CHOSEN_SOURCES.write().unwrap() = sources;
+}
+
+/// Picks the source for folder `id` out of the `candidates` it matched.
+///
+/// A source chosen before, see `set_chosen_sources`, is kept if it's still a
+/// candidate, so an addon doesn't flip between sources on every refresh.
+/// Otherwise the first candidate in priority order wins, and candidates
+/// missing from the priority lose to those in it.
+pub fn resolve_source(
+    flavor: Flavor,
+    id: &str,
+    candidates: &[RepositoryKind],
+) -> Option<RepositoryKind> {
+    let chosen = CHOSEN_SOURCES
+        .read()
+        .unwrap()
+        .get(&flavor)
+        .and_then(|sources| sources.get(id).copied());
+    if let Some(chosen) = chosen.filter(|chosen| candidates.contains(chosen)) {
+        return Some(chosen);
+    }
+
+    let priority = SOURCE_PRIORITY.read().unwrap();
+    let priority = if priority.is_empty() {
+        &DEFAULT_SOURCE_PRIORITY[..]
+    } else {
+        &priority[..]
+    };
+
+    candidates
+        .iter()
+        .min_by_key(|kind| {
+            priority
+                .iter()
+                .position(|p| p == *kind)
+                .unwrap_or(usize::MAX)
+        })
+        .copied()
+}
+
+/// Changelogs already fetched, keyed by addon id and version.
+static CHANGELOGS: Lazy<Mutex<HashMap<(String, String), String>>> = Lazy::new(Default::default);
+
+/// Fetch the release notes of the version `addon` would update to.
+///
+/// If the repository has no changelog for that version, the release body of
+/// the remote package is used instead. Results are cached by addon id and
+/// version, so fetching the same changelog twice only hits the API once.
+pub async fn fetch_changelog(
+    addon: &Addon,
+    default_release_channel: GlobalReleaseChannel,
+) -> Result<String, RepositoryError> {
+    let id = addon.primary_folder_id.clone();
+    let missing = || RepositoryError::MissingChangelog { id: id.clone() };
+
+    let repo = addon.repository().ok_or_else(missing)?;
+    let package = addon.relevant_release_package(default_release_channel);
+
+    let version = package
+        .as_ref()
+        .map(|p| p.version.clone())
+        .or_else(|| addon.version().map(str::to_owned))
+        .unwrap_or_default();
+    let key = (id.clone(), version);
+
+    if let Some(changelog) = CHANGELOGS.lock().unwrap().get(&key) {
+        return Ok(changelog.clone());
+    }
+
+    let release_body = package
+        .and_then(|p| p.changelog)
+        .filter(|body| !body.trim().is_empty());
+
+    let changelog = match repo
+        .get_changelog(addon.release_channel, default_release_channel)
+        .await
+    {
+        Ok(Some(changelog)) => changelog,
+        Ok(None) => release_body.ok_or_else(missing)?,
+        Err(error) => release_body.ok_or(error)?,
+    };
+
+    CHANGELOGS.lock().unwrap().insert(key, changelog.clone());
+
+    Ok(changelog)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_github_repo() {
+        let package =
+            RepositoryPackage::from_github_repo(Flavor::Retail, "WeakAuras/WeakAuras2").unwrap();
+        assert_eq!(package.kind, RepositoryKind::Git(GitKind::Github));
+        assert_eq!(package.id, "https://github.com/WeakAuras/WeakAuras2");
+
+        assert!(RepositoryPackage::from_github_repo(Flavor::Retail, "WeakAuras").is_err());
+        assert!(RepositoryPackage::from_github_repo(Flavor::Retail, "a/b/c").is_err());
+        assert!(RepositoryPackage::from_github_repo(Flavor::Retail, "a b/c").is_err());
+    }
+
+    #[test]
+    fn test_gitlab_base_url() {
+        let base_url = |url: &str| gitlab_base_url(&url.parse().unwrap());
+
+        assert_eq!(
+            base_url("https://gitlab.com/foo/bar"),
+            Some("https://gitlab.com".to_string())
+        );
+        assert_eq!(base_url("https://gitlab.example.org/foo/bar"), None);
+
+        set_gitlab_instances(vec![
+            "https://git.example.org/".to_string(),
+            "http://example.org:8080/gitlab".to_string(),
+        ]);
+        assert_eq!(
+            base_url("https://git.example.org/group/sub/bar"),
+            Some("https://git.example.org".to_string())
+        );
+        assert_eq!(
+            base_url("http://example.org:8080/gitlab/foo/bar"),
+            Some("http://example.org:8080/gitlab".to_string())
+        );
+        assert_eq!(base_url("http://example.org:8080/gitlabfoo/bar"), None);
+        assert_eq!(base_url("http://example.org/gitlab/foo/bar"), None);
+
+        let package = RepositoryPackage::from_source_url(
+            Flavor::Retail,
+            "https://git.example.org/foo/bar".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(package.kind, RepositoryKind::Git(GitKind::Gitlab));
+        assert!(RepositoryPackage::from_source_url(
+            Flavor::Retail,
+            "https://gitlab.other.org/foo/bar".parse().unwrap(),
+        )
+        .is_err());
+
+        set_gitlab_instances(vec![]);
+    }
+
+    #[test]
+    fn test_resolve_source() {
+        let candidates = [RepositoryKind::WowI, RepositoryKind::Curse];
+
+        assert_eq!(
+            resolve_source(Flavor::Retail, "Foo", &candidates),
+            Some(RepositoryKind::Curse)
+        );
+        assert_eq!(resolve_source(Flavor::Retail, "Foo", &[]), None);
+
+        set_source_priority(vec![RepositoryKind::WowI, RepositoryKind::Curse]);
+        assert_eq!(
+            resolve_source(Flavor::Retail, "Foo", &candidates),
+            Some(RepositoryKind::WowI)
+        );
+
+        let mut sources = HashMap::new();
+        sources.insert("Foo".to_string(), RepositoryKind::Curse);
+        sources.insert("Bar".to_string(), RepositoryKind::Tukui);
+        set_chosen_sources(vec![(Flavor::Retail, sources)].into_iter().collect());
+
+        // A previous choice sticks, unless it's no longer a candidate.
+        assert_eq!(
+            resolve_source(Flavor::Retail, "Foo", &candidates),
+            Some(RepositoryKind::Curse)
+        );
+        assert_eq!(
+            resolve_source(Flavor::Retail, "Bar", &candidates),
+            Some(RepositoryKind::WowI)
+        );
+        assert_eq!(
+            resolve_source(Flavor::ClassicEra, "Foo", &candidates),
+            Some(RepositoryKind::WowI)
+        );
+
+        set_source_priority(vec![]);
+        set_chosen_sources(HashMap::new());
+    }
+
+    #[test]
+    fn test_fetch_changelog_release_body() {
+        let mut remote_packages = HashMap::new();
+        remote_packages.insert(
+            ReleaseChannel::Stable,
+            RemotePackage {
+                version: "1.2.0".to_string(),
+                download_url: "https://example.com/addon.zip".to_string(),
+                file_id: None,
+                date_time: None,
+                modules: vec![],
+                changelog: Some("- Fixed things".to_string()),
+            },
+        );
+        let metadata = RepositoryMetadata {
+            remote_packages,
+            ..Default::default()
+        };
+
+        let repo = RepositoryPackage::from_repo_id(
+            Flavor::Retail,
+            RepositoryKind::WowI,
+            "1234".to_string(),
+        )
+        .unwrap()
+        .with_metadata(metadata.clone());
+
+        let mut addon = Addon::empty("ChangelogTest");
+        addon.set_repository(repo);
+
+        let changelog =
+            async_std::task::block_on(fetch_changelog(&addon, GlobalReleaseChannel::Stable))
+                .unwrap();
+        assert_eq!(changelog, "- Fixed things");
+
+        // Served from the cache for the same id and version.
+        let mut metadata = metadata;
+        for package in metadata.remote_packages.values_mut() {
+            package.changelog = None;
+        }
+        let repo = RepositoryPackage::from_repo_id(
+            Flavor::Retail,
+            RepositoryKind::WowI,
+            "1234".to_string(),
+        )
+        .unwrap()
+        .with_metadata(metadata);
+        addon.set_repository(repo);
+
+        let changelog =
+            async_std::task::block_on(fetch_changelog(&addon, GlobalReleaseChannel::Stable))
+                .unwrap();
+        assert_eq!(changelog, "- Fixed things");
+
+        let addon = Addon::empty("NoRepository");
+        assert!(matches!(
+            async_std::task::block_on(fetch_changelog(&addon, GlobalReleaseChannel::Stable)),
+            Err(RepositoryError::MissingChangelog { .. })
+        ));
+    }
+}
@@ -103,3 +103,88 @@ pub fn parse_only_needed(
         })
         .collect())
 }
+
+/// An addon listed in a JSON manifest created by `export_addons`, which can be
+/// passed on to the install pipeline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddonImport {
+    pub source: RepositoryKind,
+    pub id: String,
+    pub version: Option<String>,
+}
+
+/// Exports all `addons` linked to a repository as a JSON manifest.
+pub fn export_addons(addons: &[Addon]) -> String {
+    let data = addons
+        .iter()
+        .filter_map(|addon| {
+            let repository = addon.repository()?;
+
+            Some(AddonImport {
+                source: repository.kind,
+                id: repository.id.clone(),
+                version: addon.version().map(str::to_string),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::to_string_pretty(&data).unwrap_or_default()
+}
+
+/// Parses a JSON manifest created by `export_addons`. Addons which can't be
+/// parsed, eg. from an unknown source, are skipped with a warning.
+pub fn import_addons(manifest: &str) -> Result<Vec<AddonImport>, error::FilesystemError> {
+    let entries = serde_json::from_str::<Vec<serde_json::Value>>(manifest)?;
+
+    Ok(entries
+        .into_iter()
+        .filter_map(
+            |entry| match serde_json::from_value::<AddonImport>(entry.clone()) {
+                Ok(import) => Some(import),
+                Err(e) => {
+                    log::warn!("skipping addon {} from manifest: {}", entry, e);
+                    None
+                }
+            },
+        )
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{GitKind, RepositoryPackage};
+
+    #[test]
+    fn test_export_import_addons() {
+        let mut linked = Addon::empty("WeakAuras");
+        linked.set_repository(
+            RepositoryPackage::from_github_repo(Flavor::Retail, "WeakAuras/WeakAuras2").unwrap(),
+        );
+        let unlinked = Addon::empty("Local");
+
+        let manifest = export_addons(&[linked, unlinked]);
+        let imports = import_addons(&manifest).unwrap();
+
+        assert_eq!(
+            imports,
+            vec![AddonImport {
+                source: RepositoryKind::Git(GitKind::Github),
+                id: "https://github.com/WeakAuras/WeakAuras2".to_string(),
+                version: None,
+            }]
+        );
+
+        let manifest = r#"[
+            {"source": "Curse", "id": "1234", "version": "1.0"},
+            {"source": "Unknown", "id": "5678", "version": null},
+            {"id": "missing source"}
+        ]"#;
+        let imports = import_addons(manifest).unwrap();
+
+        assert_eq!(imports.len(), 1);
+        assert_eq!(imports[0].source, RepositoryKind::Curse);
+
+        assert!(import_addons("not json").is_err());
+    }
+}
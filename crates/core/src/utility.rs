@@ -9,6 +9,7 @@ use retry::delay::Fibonacci;
 use retry::{retry, Error as RetryError, OperationResult};
 use serde::Deserialize;
 
+use std::cmp::Ordering;
 use std::ffi::OsStr;
 use std::fs;
 use std::io;
@@ -17,20 +18,60 @@ use std::path::{Path, PathBuf};
 /// Takes a `&str` and formats it into a proper
 /// World of Warcraft release version.
 ///
-/// Eg. 90001 would be 9.0.1.
+/// Eg. 90001 would be 9.0.1 and 100207 would be 10.2.7.
 pub fn format_interface_into_game_version(interface: &str) -> String {
-    if interface.len() == 5 {
-        let major = interface[..1].parse::<u8>();
-        let minor = interface[1..3].parse::<u8>();
-        let patch = interface[3..5].parse::<u8>();
-        if let (Ok(major), Ok(minor), Ok(patch)) = (major, minor, patch) {
-            return format!("{}.{}.{}", major, minor, patch);
+    if interface.len() == 5 || interface.len() == 6 {
+        if let Ok(interface) = interface.parse::<u32>() {
+            return interface_to_game_version(interface);
         }
     }
 
     interface.to_owned()
 }
 
+/// Formats a `.toc` interface number into a World of Warcraft release version.
+///
+/// The last two pairs of digits are the minor and patch version, everything
+/// before is the major version. Eg. 11302 would be 1.13.2 and 100207 10.2.7.
+pub fn interface_to_game_version(interface: u32) -> String {
+    let major = interface / 10_000;
+    let minor = interface / 100 % 100;
+    let patch = interface % 100;
+
+    format!("{}.{}.{}", major, minor, patch)
+}
+
+/// Parses a World of Warcraft release version back into a `.toc` interface
+/// number, the reverse of `interface_to_game_version`.
+///
+/// Eg. 1.13.2 would be 11302 and 10.2.7 100207. A missing patch version counts
+/// as `0`. Returns `None` for anything else.
+pub fn game_version_to_interface(game_version: &str) -> Option<u32> {
+    let mut parts = game_version.trim().split('.').map(str::parse::<u32>);
+
+    let major = parts.next()?.ok()?;
+    let minor = parts.next()?.ok()?;
+    let patch = parts.next().transpose().ok()?.unwrap_or_default();
+
+    if parts.next().is_some() || minor > 99 || patch > 99 {
+        return None;
+    }
+
+    Some(major * 10_000 + minor * 100 + patch)
+}
+
+/// Returns the base `Flavor` a `.toc` interface number belongs to.
+///
+/// Eg. 11401 would be ClassicEra, 20502 ClassicTbc and 90105 Retail.
+pub fn flavor_from_interface(version: u32) -> Option<Flavor> {
+    match version {
+        10_000..=19_999 => Some(Flavor::ClassicEra),
+        20_000..=29_999 => Some(Flavor::ClassicTbc),
+        90_000..=999_999 => Some(Flavor::Retail),
+        _ => None,
+    }
+}
+
 /// Takes a `&str` and strips any non-digit.
 /// This is used to unify and compare addon versions:
 ///
@@ -42,6 +83,137 @@ pub(crate) fn strip_non_digits(string: &str) -> String {
     stripped
 }
 
+/// Numeric parts of an addon version, parsed by `parse_version`.
+#[derive(Debug, Clone)]
+pub(crate) struct Version {
+    release: Vec<u64>,
+    pre_release: Option<Vec<u64>>,
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // A release is newer than any of its pre-releases.
+        cmp_numbers(&self.release, &other.release).then_with(|| {
+            match (&self.pre_release, &other.pre_release) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => cmp_numbers(a, b),
+            }
+        })
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares numbers part by part, treating missing parts as `0`, so `1.2`
+/// equals `1.2.0`.
+fn cmp_numbers(a: &[u64], b: &[u64]) -> Ordering {
+    (0..a.len().max(b.len()))
+        .map(|idx| {
+            let a = a.get(idx).copied().unwrap_or_default();
+            let b = b.get(idx).copied().unwrap_or_default();
+            a.cmp(&b)
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Parses the numbers out of an addon version, eg. `v1.10.0`, `2024.03.01` or
+/// `r123`. Numbers following `alpha`, `beta`, `rc` or `pre` make up the
+/// pre-release, eg. `1.2.0-beta2`. Any other text is ignored.
+///
+/// Returns `None` if `version` has no numbers, or they are out of range.
+pub(crate) fn parse_version(version: &str) -> Option<Version> {
+    let mut release = vec![];
+    let mut pre_release: Option<Vec<u64>> = None;
+    let mut chars = version.chars().peekable();
+
+    while let Some(c) = chars.peek().copied() {
+        if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                number.push(digit);
+            }
+
+            let number = number.parse().ok()?;
+            match pre_release.as_mut() {
+                Some(pre_release) => pre_release.push(number),
+                None => release.push(number),
+            }
+        } else if c.is_alphabetic() {
+            let mut word = String::new();
+            while let Some(letter) = chars.next_if(|c| c.is_alphabetic()) {
+                word.push(letter);
+            }
+
+            let is_pre_release = matches!(
+                word.to_lowercase().as_str(),
+                "alpha" | "beta" | "rc" | "pre"
+            );
+            if is_pre_release && !release.is_empty() && pre_release.is_none() {
+                pre_release = Some(vec![]);
+            }
+        } else {
+            chars.next();
+        }
+    }
+
+    if release.is_empty() {
+        return None;
+    }
+
+    Some(Version {
+        release,
+        pre_release,
+    })
+}
+
+/// Returns `true` if `version` is made of version numbers, eg. `v1.10.0`,
+/// `r123` or `1.2.0-beta2`, so ordering it with `parse_version` is meaningful.
+/// Identifiers like the commit hash `5ad3c1f` aren't, their digits are arbitrary.
+pub(crate) fn is_version_number(version: &str) -> bool {
+    let is_number_part = |part: &str| {
+        let word = part.trim_matches(|c: char| c.is_ascii_digit());
+        let has_digits = part.chars().any(|c| c.is_ascii_digit());
+
+        // Plain numbers and words, or numbers prefixed or suffixed by a marker.
+        word.is_empty()
+            || !has_digits
+            || matches!(
+                word.to_lowercase().as_str(),
+                "v" | "r" | "alpha" | "beta" | "rc" | "pre"
+            )
+    };
+
+    parse_version(version).is_some()
+        && version
+            .split(|c: char| !c.is_alphanumeric())
+            .all(is_number_part)
+}
+
+/// Compares two addon versions by their numbers, so `1.10.0` is newer than
+/// `1.9.0`. See `parse_version` for the supported formats. Versions without
+/// numbers are compared as strings.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (parse_version(a), parse_version(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Release {
     pub tag_name: String,
@@ -238,6 +410,68 @@ pub fn wow_path_resolution(path: Option<PathBuf>) -> Option<PathBuf> {
     None
 }
 
+/// Returns the `Interface/AddOns` folder of `flavor` in the World of Warcraft
+/// `root` folder, eg. `<root>/_classic_era_/Interface/AddOns`. The folder
+/// doesn't have to exist.
+///
+/// `Flavor::RetailBeta` uses the `_xptr_` folder, if only that one exists.
+pub fn addons_dir_for(root: &Path, flavor: Flavor) -> PathBuf {
+    let mut flavor_dir = root.join(flavor.folder_name());
+
+    if flavor == Flavor::RetailBeta && !flavor_dir.is_dir() && root.join("_xptr_").is_dir() {
+        flavor_dir = root.join("_xptr_");
+    }
+
+    flavor_dir.join("Interface").join("AddOns")
+}
+
+/// Returns every `Flavor` installed in the World of Warcraft `root` folder,
+/// together with its `Interface/AddOns` folder, see `addons_dir_for`.
+///
+/// `_xptr_` folders are returned as `Flavor::RetailBeta`, unless a `_beta_`
+/// folder exists as well.
+pub fn detect_flavors(root: &Path) -> Vec<(Flavor, PathBuf)> {
+    let mut flavors: Vec<_> = Flavor::ALL
+        .iter()
+        .filter(|flavor| root.join(flavor.folder_name()).is_dir())
+        .map(|flavor| (*flavor, addons_dir_for(root, *flavor)))
+        .collect();
+
+    if !flavors.iter().any(|(f, _)| *f == Flavor::RetailBeta) && root.join("_xptr_").is_dir() {
+        flavors.push((Flavor::RetailBeta, addons_dir_for(root, Flavor::RetailBeta)));
+    }
+
+    flavors
+}
+
+/// Returns the `WTF` folder belonging to the `<flavor>/Interface/AddOns` folder
+/// `addons_dir`, eg. `<root>/_retail_/WTF`.
+///
+/// Returns `None` if `addons_dir` isn't an `Interface/AddOns` folder or the `WTF`
+/// folder doesn't exist.
+pub fn wtf_path_from_addons(addons_dir: &Path) -> Option<PathBuf> {
+    let is_named = |path: &Path, name: &str| {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.eq_ignore_ascii_case(name))
+            .unwrap_or_default()
+    };
+
+    let interface_dir = addons_dir.parent()?;
+
+    if !is_named(addons_dir, "AddOns") || !is_named(interface_dir, "Interface") {
+        return None;
+    }
+
+    let wtf_path = interface_dir.parent()?.join("WTF");
+
+    if wtf_path.is_dir() {
+        Some(wtf_path)
+    } else {
+        None
+    }
+}
+
 /// Rename a file or directory to a new name, retrying if the operation fails because of permissions
 ///
 /// Will retry for ~30 seconds with longer and longer delays between each, to allow for virus scan
@@ -297,6 +531,47 @@ where
     })
 }
 
+/// Remove a directory and all of its content, retrying if the operation fails because of
+/// permissions
+///
+/// Only retries on Windows, where antivirus or the game briefly holding a file handle makes
+/// the removal fail intermittently. Will retry for ~30 seconds with longer and longer delays
+/// between each.
+#[cfg(windows)]
+pub fn remove_dir_all<P>(path: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    // 21 Fibonacci steps starting at 1 ms is ~28 seconds total
+    // See https://github.com/rust-lang/rustup/pull/1873 where this was used by Rustup to work around
+    // virus scanning file locks
+    let path = path.as_ref();
+
+    retry(
+        Fibonacci::from_millis(1).take(21),
+        || match fs::remove_dir_all(path) {
+            Ok(_) => OperationResult::Ok(()),
+            Err(e) => match e.kind() {
+                io::ErrorKind::PermissionDenied => OperationResult::Retry(e),
+                _ => OperationResult::Err(e),
+            },
+        },
+    )
+    .map_err(|e| match e {
+        RetryError::Operation { error, .. } => error,
+        RetryError::Internal(message) => io::Error::new(io::ErrorKind::Other, message),
+    })
+}
+
+/// Remove a directory and all of its content
+#[cfg(not(windows))]
+pub fn remove_dir_all<P>(path: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::remove_dir_all(path)
+}
+
 pub(crate) fn truncate(s: &str, max_chars: usize) -> &str {
     match s.char_indices().nth(max_chars) {
         None => s,
@@ -316,6 +591,50 @@ pub(crate) fn regex_html_tags_to_space() -> Regex {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_game_version_to_interface() {
+        assert_eq!(game_version_to_interface("1.13.2"), Some(11302));
+        assert_eq!(game_version_to_interface("10.2.7"), Some(100_207));
+        assert_eq!(game_version_to_interface("2.5"), Some(20500));
+        assert_eq!(game_version_to_interface("1.2.3.4"), None);
+        assert_eq!(game_version_to_interface("9.x"), None);
+        assert_eq!(game_version_to_interface("9"), None);
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(compare_versions("v1.2", "1.2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.2.3-nolib", "1.2.3"), Ordering::Equal);
+        assert_eq!(
+            compare_versions("2024.03.01", "2024.02.28"),
+            Ordering::Greater
+        );
+        assert_eq!(compare_versions("r99", "r100"), Ordering::Less);
+        assert_eq!(compare_versions("1.2.0-beta2", "1.2.0"), Ordering::Less);
+        assert_eq!(
+            compare_versions("1.2.0-beta2", "1.2.0-beta10"),
+            Ordering::Less
+        );
+        assert_eq!(compare_versions("1.2.0-beta1", "1.1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("beta", "alpha"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_is_version_number() {
+        assert!(is_version_number("v1.10.0"));
+        assert!(is_version_number("2024.03.01"));
+        assert!(is_version_number("r123"));
+        assert!(is_version_number("1.2.0-beta2"));
+        assert!(is_version_number("1.2.3-nolib"));
+        assert!(is_version_number("9.1.5 Classic"));
+
+        assert!(!is_version_number("5ad3c1f"));
+        assert!(!is_version_number("1.0.0-5ad3c1f"));
+        assert!(!is_version_number("Bartender4-4.10.0"));
+        assert!(!is_version_number("beta"));
+    }
+
     #[test]
     fn test_wow_path_resolution() {
         let classic_addon_path =
@@ -340,6 +659,98 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_addons_dir_for() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+        let addons = |folder: &str| root.join(folder).join("Interface").join("AddOns");
+
+        assert_eq!(
+            addons_dir_for(root, Flavor::ClassicEra),
+            addons("_classic_era_")
+        );
+        assert_eq!(addons_dir_for(root, Flavor::RetailBeta), addons("_beta_"));
+
+        fs::create_dir_all(root.join("_xptr_")).unwrap();
+        assert_eq!(addons_dir_for(root, Flavor::RetailBeta), addons("_xptr_"));
+
+        fs::create_dir_all(root.join("_beta_")).unwrap();
+        assert_eq!(addons_dir_for(root, Flavor::RetailBeta), addons("_beta_"));
+    }
+
+    #[test]
+    fn test_wtf_path_from_addons() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let flavor_dir = tempdir.path().join("_retail_");
+        let addons_dir = flavor_dir.join("Interface").join("AddOns");
+        fs::create_dir_all(&addons_dir).unwrap();
+
+        assert_eq!(wtf_path_from_addons(&addons_dir), None);
+
+        fs::create_dir_all(flavor_dir.join("WTF")).unwrap();
+        assert_eq!(
+            wtf_path_from_addons(&addons_dir),
+            Some(flavor_dir.join("WTF"))
+        );
+        assert_eq!(
+            wtf_path_from_addons(&flavor_dir.join("interface").join("addons")),
+            Some(flavor_dir.join("WTF"))
+        );
+        assert_eq!(wtf_path_from_addons(&flavor_dir.join("Interface")), None);
+        assert_eq!(wtf_path_from_addons(Path::new("AddOns")), None);
+    }
+
+    #[test]
+    fn test_detect_flavors() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+
+        for folder in &["_retail_", "_classic_era_", "_ptr_", "_xptr_", "Data"] {
+            fs::create_dir_all(root.join(folder)).unwrap();
+        }
+        fs::write(root.join("_classic_"), "").unwrap();
+
+        let addons = |folder: &str| root.join(folder).join("Interface").join("AddOns");
+        assert_eq!(
+            detect_flavors(root),
+            vec![
+                (Flavor::Retail, addons("_retail_")),
+                (Flavor::RetailPtr, addons("_ptr_")),
+                (Flavor::ClassicEra, addons("_classic_era_")),
+                (Flavor::RetailBeta, addons("_xptr_")),
+            ]
+        );
+
+        fs::create_dir_all(root.join("_beta_")).unwrap();
+        assert_eq!(
+            detect_flavors(root)
+                .into_iter()
+                .filter(|(f, _)| *f == Flavor::RetailBeta)
+                .collect::<Vec<_>>(),
+            vec![(Flavor::RetailBeta, addons("_beta_"))]
+        );
+    }
+
+    #[test]
+    fn test_flavor_from_interface() {
+        assert_eq!(flavor_from_interface(11401), Some(Flavor::ClassicEra));
+        assert_eq!(flavor_from_interface(20502), Some(Flavor::ClassicTbc));
+        assert_eq!(flavor_from_interface(90105), Some(Flavor::Retail));
+        assert_eq!(flavor_from_interface(100002), Some(Flavor::Retail));
+        assert_eq!(flavor_from_interface(30300), None);
+        assert_eq!(flavor_from_interface(0), None);
+    }
+
+    #[test]
+    fn test_interface_to_game_version() {
+        assert_eq!(interface_to_game_version(11302), "1.13.2");
+        assert_eq!(interface_to_game_version(11401), "1.14.1");
+        assert_eq!(interface_to_game_version(20502), "2.5.2");
+        assert_eq!(interface_to_game_version(90105), "9.1.5");
+        assert_eq!(interface_to_game_version(100207), "10.2.7");
+        assert_eq!(interface_to_game_version(110000), "11.0.0");
+    }
+
     #[test]
     fn test_interface() {
         let interface = "90001";
@@ -349,7 +760,10 @@ mod tests {
         assert_eq!("1.13.5", format_interface_into_game_version(interface));
 
         let interface = "100000";
-        assert_eq!("100000", format_interface_into_game_version(interface));
+        assert_eq!("10.0.0", format_interface_into_game_version(interface));
+
+        let interface = "1000000";
+        assert_eq!("1000000", format_interface_into_game_version(interface));
 
         let interface = "9.0.1";
         assert_eq!("9.0.1", format_interface_into_game_version(interface));
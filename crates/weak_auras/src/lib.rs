@@ -14,9 +14,11 @@ use std::fmt::{self, Debug, Display, Write};
 
 mod companion;
 mod error;
+mod list;
 
 pub use companion::{ensure_companion_addon_exists, write_updates};
 pub use error::Error;
+pub use list::{list_weakauras, WeakAuraEntry};
 
 pub async fn is_weak_auras_installed(addon_dir: impl AsRef<Path>) -> bool {
     let weak_auras_toc = addon_dir.as_ref().join("WeakAuras").join("WeakAuras.toc");
@@ -0,0 +1,302 @@
+use crate::Error;
+
+use async_std::path::Path;
+use isahc::http;
+
+use std::io::{BufRead, BufReader, Read};
+
+/// An aura listed in the `displays` table of a `WeakAuras.lua` SavedVariables file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeakAuraEntry {
+    /// Name of the aura, which is also its key in the `displays` table.
+    pub id: String,
+    /// Name of the group this aura is part of, `None` for top level auras.
+    pub parent: Option<String>,
+    /// Wago url the aura was imported from.
+    pub url: Option<String>,
+    /// Wago slug parsed from `url`.
+    pub slug: Option<String>,
+    /// Installed wago version parsed from `url`, or the `version` field if set.
+    pub version: Option<u16>,
+}
+
+/// List all auras in the accounts `WeakAuras.lua` SavedVariables file, without
+/// evaluating it.
+///
+/// The file is tokenized line by line and only the fields of each aura
+/// in `displays` are kept, so huge SavedVariables are never fully held in memory.
+pub async fn list_weakauras(
+    wtf_path: impl AsRef<Path>,
+    account: &str,
+) -> Result<Vec<WeakAuraEntry>, Error> {
+    let lua_path = wtf_path
+        .as_ref()
+        .join("Account")
+        .join(account)
+        .join("SavedVariables")
+        .join("WeakAuras.lua");
+
+    if !lua_path.exists().await {
+        return Ok(vec![]);
+    }
+
+    let lua_path: std::path::PathBuf = lua_path.into();
+
+    async_std::task::spawn_blocking(move || {
+        let file = std::fs::File::open(lua_path)?;
+        parse_weakauras_entries(file)
+    })
+    .await
+}
+
+/// Parses the auras out of the contents of a `WeakAuras.lua` file.
+fn parse_weakauras_entries(source: impl Read) -> Result<Vec<WeakAuraEntry>, Error> {
+    let mut entries: Vec<WeakAuraEntry> = vec![];
+
+    // Keys of all currently open tables, `None` for array entries.
+    let mut path: Vec<Option<String>> = vec![];
+    let mut key: Option<String> = None;
+    let mut after_equals = false;
+
+    let is_display = |path: &[Option<String>]| {
+        path.len() == 3
+            && path[0].as_deref() == Some("WeakAurasSaved")
+            && path[1].as_deref() == Some("displays")
+    };
+
+    for line in BufReader::new(source).split(b'\n') {
+        let line = String::from_utf8_lossy(&line?).into_owned();
+
+        for token in tokenize(&line) {
+            match token {
+                Token::Open => {
+                    path.push(key.take());
+                    after_equals = false;
+
+                    if is_display(&path) {
+                        if let Some(Some(id)) = path.last() {
+                            entries.push(WeakAuraEntry {
+                                id: id.clone(),
+                                parent: None,
+                                url: None,
+                                slug: None,
+                                version: None,
+                            });
+                        }
+                    }
+                }
+                Token::Close => {
+                    path.pop();
+                    key = None;
+                    after_equals = false;
+                }
+                Token::Equals => after_equals = true,
+                Token::Separator => {
+                    key = None;
+                    after_equals = false;
+                }
+                Token::Key(name) => key = Some(name),
+                Token::Value(value) if after_equals => {
+                    if is_display(&path) {
+                        if let Some(entry) = entries.last_mut() {
+                            match (key.as_deref(), value) {
+                                (Some("parent"), Literal::String(parent)) => {
+                                    entry.parent = Some(parent)
+                                }
+                                (Some("url"), Literal::String(url)) => {
+                                    if let Some((slug, version)) = slug_and_version(&url) {
+                                        entry.slug = Some(slug);
+                                        entry.version = entry.version.or(version);
+                                    }
+                                    entry.url = Some(url);
+                                }
+                                (Some("version"), Literal::Other(version)) => {
+                                    if let Ok(version) = version.parse() {
+                                        entry.version = Some(version);
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+
+                    key = None;
+                    after_equals = false;
+                }
+                Token::Value(Literal::Other(name)) => key = Some(name),
+                Token::Value(_) => {}
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Returns the slug and version of a wago `url`, eg. `https://wago.io/slug/12`.
+fn slug_and_version(url: &str) -> Option<(String, Option<u16>)> {
+    let uri = url.parse::<http::Uri>().ok()?;
+    let mut path = uri.path().split_terminator('/');
+    path.next();
+
+    let slug = path.next()?;
+    let version = path.next().map(str::parse::<u16>).and_then(Result::ok);
+
+    Some((slug.to_owned(), version))
+}
+
+#[derive(Debug, PartialEq)]
+enum Literal {
+    String(String),
+    Other(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Equals,
+    Separator,
+    /// A `["key"]` or `[1]` table key.
+    Key(String),
+    Value(Literal),
+}
+
+/// Splits a single line of Lua table constructors into tokens, skipping comments.
+fn tokenize(line: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '=' => tokens.push(Token::Equals),
+            ',' | ';' => tokens.push(Token::Separator),
+            '-' if chars.peek() == Some(&'-') => break,
+            '"' | '\'' => tokens.push(Token::Value(Literal::String(read_string(&mut chars, c)))),
+            '[' => {
+                let mut key = String::new();
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' | '\'' => key = read_string(&mut chars, c),
+                        ']' => break,
+                        c if !c.is_whitespace() => key.push(c),
+                        _ => {}
+                    }
+                }
+
+                tokens.push(Token::Key(key));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut atom = c.to_string();
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}=,;[]\"'".contains(c) {
+                        break;
+                    }
+                    atom.push(c);
+                    chars.next();
+                }
+
+                tokens.push(Token::Value(Literal::Other(atom)));
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Reads a string literal up to the closing `quote`, decoding escapes.
+fn read_string(chars: &mut impl Iterator<Item = char>, quote: char) -> String {
+    let mut string = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some(c) => string.push(c),
+                None => {}
+            },
+            c if c == quote => break,
+            c => string.push(c),
+        }
+    }
+
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_weakauras_entries() {
+        let source = r#"
+WeakAurasSaved = {
+	["dynamicIconCache"] = {
+	},
+	["displays"] = {
+		["My Group"] = {
+			["controlledChildren"] = {
+				"Buff {tracker}", -- [1]
+			},
+			["url"] = "https://wago.io/abc123/4",
+			["id"] = "My Group",
+		},
+		["Buff {tracker}"] = {
+			["load"] = {
+				["class"] = {
+					["url"] = "https://wago.io/nested/1",
+				},
+			},
+			["desc"] = "Shows \"buffs\" -- not a comment",
+			["parent"] = "My Group",
+			["url"] = "https://wago.io/abc123/4",
+			["version"] = 5,
+			["id"] = "Buff {tracker}",
+		},
+		["Local Aura"] = {
+			["id"] = "Local Aura",
+		},
+	},
+	["registered"] = {
+		["Not An Aura"] = {
+			["url"] = "https://wago.io/other/2",
+		},
+	},
+}
+"#;
+
+        let entries = parse_weakauras_entries(source.as_bytes()).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                WeakAuraEntry {
+                    id: "My Group".to_string(),
+                    parent: None,
+                    url: Some("https://wago.io/abc123/4".to_string()),
+                    slug: Some("abc123".to_string()),
+                    version: Some(4),
+                },
+                WeakAuraEntry {
+                    id: "Buff {tracker}".to_string(),
+                    parent: Some("My Group".to_string()),
+                    url: Some("https://wago.io/abc123/4".to_string()),
+                    slug: Some("abc123".to_string()),
+                    version: Some(5),
+                },
+                WeakAuraEntry {
+                    id: "Local Aura".to_string(),
+                    parent: None,
+                    url: None,
+                    slug: None,
+                    version: None,
+                },
+            ]
+        );
+    }
+}
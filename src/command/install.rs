@@ -5,7 +5,7 @@ use ajour_core::cache::{
     load_addon_cache, load_fingerprint_cache, update_addon_cache, AddonCacheEntry,
 };
 use ajour_core::config::{load_config, Flavor};
-use ajour_core::fs::install_addon;
+use ajour_core::fs::{install_addon, PersistentData};
 use ajour_core::network::download_addon;
 use ajour_core::parse::update_addon_fingerprint;
 use ajour_core::repository::RepositoryPackage;
@@ -13,6 +13,7 @@ use ajour_core::repository::RepositoryPackage;
 use anyhow::{format_err, Context};
 use async_std::sync::{Arc, Mutex};
 use async_std::task;
+use chrono::Utc;
 use futures::future::join_all;
 use isahc::http::Uri;
 
@@ -29,7 +30,7 @@ pub fn install_from_source(url: Uri, flavor: Flavor) -> Result<()> {
         hasher.write(url.to_string().as_bytes());
         let url_hash = hasher.finish();
 
-        let config = load_config().await?;
+        let mut config = load_config().await?;
         let global_release_channel = config.addons.global_release_channel;
 
         let addon_cache = Arc::new(Mutex::new(load_addon_cache().await?));
@@ -58,10 +59,20 @@ pub fn install_from_source(url: Uri, flavor: Flavor) -> Result<()> {
 
         // Install the addon and update Addon with the unpacked folders
         let addon_folders = install_addon(&addon, &download_directory, &addon_directory).await?;
+        let updated_at = Utc::now();
         log::debug!("Addon unpacked");
 
         addon.update_addon_folders(addon_folders);
 
+        addon.updated_at = Some(updated_at);
+        config
+            .addons
+            .updated_at
+            .entry(flavor)
+            .or_default()
+            .insert(addon.primary_folder_id.clone(), updated_at);
+        config.save()?;
+
         // Update cache with new entry
         if let Ok(entry) = AddonCacheEntry::try_from(&addon) {
             update_addon_cache(addon_cache.clone(), entry, flavor).await?;
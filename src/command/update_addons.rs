@@ -9,7 +9,7 @@ use ajour_core::cache::{
     FingerprintCache,
 };
 use ajour_core::config::{load_config, Flavor};
-use ajour_core::fs::install_addon;
+use ajour_core::fs::{install_addon, PersistentData};
 use ajour_core::network::download_addon;
 use ajour_core::parse::{read_addon_directory, update_addon_fingerprint};
 use ajour_core::repository::{GlobalReleaseChannel, RepositoryKind};
@@ -17,6 +17,7 @@ use ajour_core::repository::{GlobalReleaseChannel, RepositoryKind};
 use anyhow::Context;
 use async_std::sync::{Arc, Mutex};
 use async_std::task;
+use chrono::{DateTime, Utc};
 
 use futures::future::join_all;
 
@@ -27,7 +28,7 @@ pub fn update_all_addons() -> Result<()> {
     log::info!("Checking for addon updates...");
 
     task::block_on(async {
-        let config = load_config().await?;
+        let mut config = load_config().await?;
         let global_release_channel = config.addons.global_release_channel;
 
         let fingerprint_cache: Arc<Mutex<_>> =
@@ -62,6 +63,14 @@ pub fn update_all_addons() -> Result<()> {
                     .cloned()
                     .unwrap_or_default();
 
+                // Get any pinned addons from the config
+                let pinned = config
+                    .addons
+                    .pinned
+                    .get(flavor)
+                    .cloned()
+                    .unwrap_or_default();
+
                 // Get any ingnored addons from the config
                 let ignored_ids = config
                     .addons
@@ -70,11 +79,23 @@ pub fn update_all_addons() -> Result<()> {
                     .cloned()
                     .unwrap_or_default();
 
-                // Filter out any ignored addons
-                for mut addon in addons
-                    .into_iter()
-                    .filter(|a| !ignored_ids.iter().any(|i| i == &a.primary_folder_id))
-                {
+                // Get any addons with auto update disabled from the config
+                let auto_update_disabled = config
+                    .addons
+                    .auto_update_disabled
+                    .get(flavor)
+                    .cloned()
+                    .unwrap_or_default();
+
+                // Filter out any ignored addons, and those which only update manually
+                for mut addon in addons.into_iter().filter(|a| {
+                    !ignored_ids.iter().any(|i| i == &a.primary_folder_id)
+                        && !auto_update_disabled
+                            .iter()
+                            .any(|i| i == &a.primary_folder_id)
+                }) {
+                    addon.pinned_version = pinned.get(&addon.primary_folder_id).cloned();
+
                     // Apply release channel preference
                     if let Some(channel) = release_channels.get(&addon.primary_folder_id) {
                         addon.release_channel = *channel;
@@ -132,14 +153,28 @@ pub fn update_all_addons() -> Result<()> {
 
         // Call `update_addon` on each addon concurrently
         for result in join_all(addons_to_update.into_iter().map(update_addon)).await {
-            // Log any errors updating an addon
-            if let Err(e) = result {
-                log_error(&e);
+            match result {
+                Ok((flavor, id, updated_at)) => {
+                    config
+                        .addons
+                        .updated_at
+                        .entry(flavor)
+                        .or_default()
+                        .insert(id, updated_at);
+                }
+                // Log any errors updating an addon
+                Err(e) => {
+                    log_error(&e);
 
-                num_errors += 1;
+                    num_errors += 1;
+                }
             }
         }
 
+        if num_updates > num_errors {
+            config.save()?;
+        }
+
         if num_errors > 0 {
             log::error!("{} addons failed to update", num_errors);
         } else if num_updates > 0 {
@@ -155,6 +190,7 @@ pub fn update_all_addons() -> Result<()> {
 /// Updates an addon
 ///
 /// Downloads the latest file, extracts it and refingerprints the addon, saving it to the cache.
+/// Returns the flavor and id of the addon with the time it was updated at.
 async fn update_addon(
     (
         addon_cache,
@@ -173,12 +209,13 @@ async fn update_addon(
         PathBuf,
         PathBuf,
     ),
-) -> Result<()> {
+) -> Result<(Flavor, String, DateTime<Utc>)> {
     // Download the update to the temp directory
     download_addon(&addon, global_release_channel, &temp_directory).await?;
 
     // Extracts addon from the downloaded archive to the addon directory and removes the archive
     let installed_folders = install_addon(&addon, &temp_directory, &addon_directory).await?;
+    let updated_at = Utc::now();
 
     addon.update_addon_folders(installed_folders);
 
@@ -232,5 +269,5 @@ async fn update_addon(
         }
     }
 
-    Ok(())
+    Ok((flavor, addon.primary_folder_id, updated_at))
 }
@@ -371,7 +371,7 @@ impl Application for Ajour {
             ),
             Command::perform(load_user_themes(), Message::ThemesLoaded),
             Command::perform(
-                catalog_download_latest_or_use_cache(),
+                catalog_download_latest_or_use_cache(config.catalog_cache_ttl(), false),
                 Message::CatalogDownloaded,
             ),
         ];
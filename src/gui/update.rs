@@ -17,11 +17,15 @@ use {
         catalog,
         config::{ColumnConfig, ColumnConfigV2, Flavor},
         error::{DownloadError, FilesystemError, ParseError, RepositoryError, ThemeError},
-        fs::{delete_addons, delete_saved_variables, import_theme, install_addon, PersistentData},
+        fs::{
+            cleanup_stale_staging, delete_saved_variables, import_theme, install_addon,
+            uninstall_addon, PersistentData,
+        },
         network::download_addon,
         parse::{read_addon_directory, update_addon_fingerprint},
         repository::{
-            batch_refresh_repository_packages, Changelog, RepositoryKind, RepositoryPackage,
+            batch_refresh_repository_packages, set_chosen_sources, Changelog, RepositoryKind,
+            RepositoryPackage,
         },
         share,
         utility::{download_update_to_temp_file, get_latest_release, wow_path_resolution},
@@ -60,6 +64,20 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                 ajour.addon_cache = Some(Arc::new(Mutex::new(addon_cache)));
             }
 
+            // Remove what an install interrupted by a crash left behind, before
+            // anything is installed or parsed.
+            for flavor in ajour.config.wow.directories.keys() {
+                if let Some(addon_directory) = ajour.config.get_addon_directory_for_flavor(flavor) {
+                    match cleanup_stale_staging(&addon_directory) {
+                        Ok(stale) if !stale.is_empty() => {
+                            log::info!("{} - removed stale staging folders {:?}", flavor, stale)
+                        }
+                        Ok(_) => {}
+                        Err(error) => log::error!("{} - {}", flavor, error),
+                    }
+                }
+            }
+
             return Ok(Command::perform(async {}, Message::Parse));
         }
         Message::Parse(_) => {
@@ -224,8 +242,12 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                 Mode::Catalog => {
                     ajour.catalog = None;
                     ajour.state.insert(Mode::Catalog, State::Loading);
+                    // A manual refresh always checks for a newer catalog.
                     return Ok(Command::perform(
-                        catalog_download_latest_or_use_cache(),
+                        catalog_download_latest_or_use_cache(
+                            ajour.config.catalog_cache_ttl(),
+                            true,
+                        ),
                         Message::CatalogDownloaded,
                     ));
                 }
@@ -483,16 +505,18 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                 // Remove from local state.
                 addons.retain(|a| a.primary_folder_id != addon.primary_folder_id);
 
-                // Delete addon(s) from disk.
-                let _ = delete_addons(&addon.folders);
-
-                // Delete SavedVariable(s) if enabled.
-                if ajour.config.addons.delete_saved_variables {
-                    let wtf_path = &ajour
-                        .config
-                        .get_wtf_directory_for_flavor(&flavor)
-                        .expect("No World of Warcraft directory set.");
-                    let _ = delete_saved_variables(&addon.folders, wtf_path);
+                // Delete addon(s) and SavedVariable(s) if enabled from disk.
+                let wtf_path = if ajour.config.addons.delete_saved_variables {
+                    let wtf_path = ajour.config.get_wtf_directory_for_flavor(&flavor);
+                    if wtf_path.is_none() {
+                        log::error!("No World of Warcraft directory set, keeping SavedVariables");
+                    }
+                    wtf_path
+                } else {
+                    None
+                };
+                if let Err(e) = uninstall_addon(&addon, wtf_path.as_deref()) {
+                    log::error!("{}", e);
                 }
 
                 // Remove addon from cache
@@ -531,7 +555,7 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                     .config
                     .get_wtf_directory_for_flavor(&flavor)
                     .expect("No World of Warcraft directory set.");
-                let _ = delete_saved_variables(&addon.folders, wtf_path);
+                let _ = delete_saved_variables(&addon.folders, wtf_path, None, None);
             }
 
             // Remove any pending confirms.
@@ -578,7 +602,8 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                     // Close details if shown.
                     ajour.expanded_type = ExpandType::None;
 
-                    // Update all updatable addons, expect ignored.
+                    // Update all updatable addons, expect ignored and those with
+                    // auto update disabled.
                     let global_release_channel = ajour.config.addons.global_release_channel;
                     let ignored_ids = ajour.config.addons.ignored.entry(flavor).or_default();
                     let mut addons: Vec<_> = ajour
@@ -587,6 +612,7 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                         .or_default()
                         .iter_mut()
                         .filter(|a| !ignored_ids.iter().any(|i| i == &a.primary_folder_id))
+                        .filter(|a| a.auto_update)
                         .collect();
 
                     let mut commands = vec![];
@@ -761,6 +787,27 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                     let ignored_ids = ajour.config.addons.ignored.entry(flavor).or_default();
 
                     // Check if addons is updatable.
+                    let pinned = ajour
+                        .config
+                        .addons
+                        .pinned
+                        .get(&flavor)
+                        .cloned()
+                        .unwrap_or_default();
+                    let updated_at = ajour
+                        .config
+                        .addons
+                        .updated_at
+                        .get(&flavor)
+                        .cloned()
+                        .unwrap_or_default();
+                    let auto_update_disabled = ajour
+                        .config
+                        .addons
+                        .auto_update_disabled
+                        .get(&flavor)
+                        .cloned()
+                        .unwrap_or_default();
                     let release_channels = ajour
                         .config
                         .addons
@@ -770,6 +817,12 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                     let mut addons = addons
                         .into_iter()
                         .map(|mut a| {
+                            a.pinned_version = pinned.get(&a.primary_folder_id).cloned();
+                            a.updated_at = updated_at.get(&a.primary_folder_id).cloned();
+                            a.auto_update = !auto_update_disabled
+                                .iter()
+                                .any(|id| id == &a.primary_folder_id);
+
                             // Check if we have saved release channel for addon.
                             if let Some(release_channel) =
                                 release_channels.get(&a.primary_folder_id)
@@ -807,6 +860,24 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                     ajour.header_state.previous_sort_direction = Some(SortDirection::Desc);
                     ajour.header_state.previous_column_key = Some(ColumnKey::Status);
 
+                    // Persist the sources picked for folders which matched several
+                    // repositories, so the same source is picked on the next parse.
+                    let sources = ajour.config.addons.sources.entry(flavor).or_default();
+                    let mut sources_changed = false;
+                    for addon in &addons {
+                        if let Some(source) = addon.source {
+                            for folder in &addon.folders {
+                                if sources.insert(folder.id.clone(), source) != Some(source) {
+                                    sources_changed = true;
+                                }
+                            }
+                        }
+                    }
+                    if sources_changed {
+                        set_chosen_sources(ajour.config.addons.sources.clone());
+                        let _ = ajour.config.save();
+                    }
+
                     // Sets the flavor state to ready.
                     ajour.state.insert(Mode::MyAddons(flavor), State::Ready);
 
@@ -986,6 +1057,18 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
             if let (Some(addon), Some(folders)) = (addon, folders) {
                 addon.update_addon_folders(folders);
 
+                // Extraction has completed, so this is when the addon was updated.
+                let updated_at = Utc::now();
+                addon.updated_at = Some(updated_at);
+                ajour
+                    .config
+                    .addons
+                    .updated_at
+                    .entry(flavor)
+                    .or_default()
+                    .insert(addon.primary_folder_id.clone(), updated_at);
+                let _ = ajour.config.save();
+
                 addon.state = AddonState::Fingerprint;
 
                 // Set version & file id of installed addon to that of newly unpacked package.
@@ -1020,7 +1103,7 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                                     Message::AddonCacheUpdated,
                                 ));
                             }
-                            None => {}
+                            Some(RepositoryKind::Local) | None => {}
                         }
                     }
                 }
@@ -2047,7 +2130,10 @@ pub fn handle_message(ajour: &mut Ajour, message: Message) -> Result<Command<Mes
                     log::debug!("Message::RefreshCatalog: catalog needs to be refreshed");
 
                     return Ok(Command::perform(
-                        catalog_download_latest_or_use_cache(),
+                        catalog_download_latest_or_use_cache(
+                            ajour.config.catalog_cache_ttl(),
+                            false,
+                        ),
                         Message::CatalogDownloaded,
                     ));
                 }
@@ -3177,40 +3263,18 @@ fn query_and_sort_catalog(ajour: &mut Ajour) {
         let category = &ajour.catalog_search_state.category;
         let result_size = ajour.catalog_search_state.result_size.as_usize();
 
-        // Increase penalty for gaps between matching characters
-        let fuzzy_match_config = SkimScoreConfig {
-            gap_start: -12,
-            gap_extension: -6,
-            ..Default::default()
-        };
-        let fuzzy_matcher = SkimMatcherV2::default().score_config(fuzzy_match_config);
-
         let mut catalog_rows_and_score = catalog
             .addons
             .iter()
             .filter(|a| !a.versions.is_empty())
             .filter_map(|a| {
                 if let Some(query) = &query {
-                    let title_score = fuzzy_matcher
-                        .fuzzy_match(&a.name, query)
-                        .unwrap_or_default();
-                    let description_score = fuzzy_matcher
-                        .fuzzy_match(&a.summary, query)
-                        .unwrap_or_default()
-                        / 2;
-
-                    let max_score = title_score.max(description_score);
-
-                    if max_score > 0 {
-                        Some((a, max_score))
-                    } else {
-                        None
-                    }
+                    a.search_score(query).map(|score| (a, score))
                 } else {
                     Some((a, 0))
                 }
             })
-            .filter(|(a, _)| a.versions.iter().any(|v| v.flavor == flavor.base_flavor()))
+            .filter(|(a, _)| a.supports_flavor(*flavor))
             .filter(|(a, _)| match source {
                 Some(source) => a.source == *source,
                 None => true,